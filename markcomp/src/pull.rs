@@ -21,6 +21,9 @@ fn html_encode<W: std::io::Write>(input: &[u8], writer: &mut W) -> std::io::Resu
     Ok(())
 }
 
+/// Average adult reading speed, in words per minute.
+const WORDS_PER_MINUTE: usize = 200;
+
 static SET: LazyLock<syntect::parsing::SyntaxSet> =
     LazyLock::new(|| syntect::parsing::SyntaxSet::load_defaults_newlines());
 
@@ -30,14 +33,75 @@ static THEME: LazyLock<syntect::highlighting::Theme> = LazyLock::new(|| {
         .expect("Code theme should be valid")
 });
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Frontmatter {
     pub title: String,
+    /// Required for blog posts, in `%D` format.
+    #[serde(default)]
     pub date: String,
+    #[serde(default)]
     pub description: String,
+    /// The series this post belongs to, if any.
+    #[serde(default)]
+    pub series: Option<String>,
+    /// This post's one-based position within its series.
+    #[serde(default)]
+    pub part: Option<u32>,
+    /// Stylesheets loaded only on this post, relative to its source.
+    #[serde(default)]
+    pub css: Vec<String>,
+    /// Scripts loaded only on this post, relative to its source.
+    #[serde(default)]
+    pub scripts: Vec<String>,
+    /// Drafts are only built when explicitly requested.
+    #[serde(default)]
+    pub draft: bool,
+    /// Old URLs that should redirect to this post.
+    #[serde(default)]
+    pub redirect_from: Vec<String>,
+    /// The post's language, when it isn't the site's default.
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// Where this post is served, overriding the site's permalink pattern.
+    #[serde(default)]
+    pub permalink: Option<String>,
+    /// Pages set to `false` are left out of the sitemap.
+    #[serde(default = "listed")]
+    pub sitemap: bool,
+    /// The page's sitemap priority, from 0.0 to 1.0.
+    #[serde(default)]
+    pub sitemap_priority: Option<f32>,
+    /// How often the page is expected to change, like `weekly`.
+    #[serde(default)]
+    pub sitemap_changefreq: Option<String>,
+}
+
+fn listed() -> bool {
+    true
+}
+
+/// Parse only the frontmatter of `input`, without rendering the rest.
+pub fn frontmatter(input: &str) -> Result<Option<Frontmatter>, FrontmatterError> {
+    let mut yaml = None;
+    for event in Parser::new_ext(input, Options::ENABLE_YAML_STYLE_METADATA_BLOCKS) {
+        match event {
+            Event::Start(Tag::MetadataBlock(MetadataBlockKind::YamlStyle)) => {
+                yaml = Some(String::new())
+            }
+            Event::Text(text) => match &mut yaml {
+                Some(yaml) => yaml.push_str(&text),
+                None => break,
+            },
+            // Frontmatter can only open the document, so nothing after it matters.
+            _ => break,
+        }
+    }
+
+    yaml.map(|yaml| serde_yaml::from_str(&yaml).map_err(FrontmatterError::new))
+        .transpose()
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
 enum State {
     Normal,
     Footnote,
@@ -53,25 +117,56 @@ enum Code<'a> {
     Yaml(Vec<u8>),
 }
 
-#[derive(Debug)]
+/// Rendered markdown. It can be serialized so that callers can cache it.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct Writer {
     state: State,
     output: Vec<u8>,
     footnotes: Vec<u8>,
+    words: usize,
+    /// The excerpt's span in `output`, from the first paragraph to
+    /// a `<!-- more -->` marker or, failing that, the paragraph's end.
+    excerpt: (Option<usize>, Option<usize>),
+    /// The spans of footnote references in `output`, which are left out of
+    /// the excerpt since their footnotes aren't.
+    #[serde(default)]
+    footnote_refs: Vec<(usize, usize)>,
+    more: bool,
     pub frontmatter: Option<Frontmatter>,
 }
 
-/// Indicates malformed YAML.
+/// Reuses highlighted code blocks, which are slow to produce, across renders.
+pub trait CodeCache {
+    /// The highlighted HTML for `code` in the language `lang`,
+    /// calling `highlight` only when it isn't already known.
+    fn highlight(&self, lang: &str, code: &str, highlight: &dyn Fn() -> String) -> String;
+}
+
+/// Indicates malformed YAML frontmatter.
 #[derive(Debug)]
-pub struct SimpleError;
+pub struct FrontmatterError {
+    pub message: String,
+    /// The one-based line and column in the markdown source, when known.
+    pub location: Option<(usize, usize)>,
+}
+
+impl FrontmatterError {
+    fn new(error: serde_yaml::Error) -> Self {
+        Self {
+            message: error.to_string(),
+            // Frontmatter starts on the line after the opening `---`.
+            location: error.location().map(|l| (l.line() + 1, l.column())),
+        }
+    }
+}
 
-impl std::fmt::Display for SimpleError {
+impl std::fmt::Display for FrontmatterError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Error processing YAML frontmatter")
+        write!(f, "Error processing YAML frontmatter: {}", self.message)
     }
 }
 
-impl std::error::Error for SimpleError {}
+impl std::error::Error for FrontmatterError {}
 
 impl Writer {
     fn buffer(&mut self) -> &mut Vec<u8> {
@@ -85,7 +180,11 @@ impl Writer {
         self.buffer().extend(string.as_bytes());
     }
 
-    fn parse(&mut self, input: &str) -> Result<(), SimpleError> {
+    fn parse(
+        &mut self,
+        input: &str,
+        cache: Option<&dyn CodeCache>,
+    ) -> Result<(), FrontmatterError> {
         let parser = Parser::new_ext(
             input,
             Options::ENABLE_STRIKETHROUGH
@@ -96,6 +195,8 @@ impl Writer {
 
         let mut code = None;
         let mut footnote_def = None;
+        // An image's source and the alt text collected so far.
+        let mut image: Option<(String, String)> = None;
 
         for event in parser {
             match event {
@@ -105,7 +206,12 @@ impl Writer {
                             code = Some(Code::Yaml(Vec::new()));
                         }
                     }
-                    Tag::Paragraph => self.append("<p>"),
+                    Tag::Paragraph => {
+                        if matches!(self.state, State::Normal) && self.excerpt.0.is_none() {
+                            self.excerpt.0 = Some(self.output.len());
+                        }
+                        self.append("<p>")
+                    }
                     Tag::Emphasis => self.append("<em>"),
                     Tag::Strong => self.append("<strong>"),
                     Tag::Strikethrough => self.append("<delete>"),
@@ -138,18 +244,24 @@ impl Writer {
                         }
                     },
                     Tag::HtmlBlock => code = Some(Code::Html),
+                    Tag::Image { dest_url, .. } => image = Some((dest_url.into(), String::new())),
                     _ => {} // tag => todo!("tag start: {tag:#?}"),
                 },
                 Event::End(tag) => match tag {
                     TagEnd::MetadataBlock(kind) => match (kind, code.take()) {
                         (MetadataBlockKind::YamlStyle, Some(Code::Yaml(yaml))) => {
                             let frontmatter =
-                                serde_yaml::from_slice(&yaml).map_err(|_| SimpleError)?;
+                                serde_yaml::from_slice(&yaml).map_err(FrontmatterError::new)?;
                             self.frontmatter = Some(frontmatter);
                         }
                         _ => {}
                     },
-                    TagEnd::Paragraph => self.append("</p>"),
+                    TagEnd::Paragraph => {
+                        self.append("</p>");
+                        if matches!(self.state, State::Normal) && self.excerpt.1.is_none() {
+                            self.excerpt.1 = Some(self.output.len());
+                        }
+                    }
                     TagEnd::Emphasis => self.append("</em>"),
                     TagEnd::Strong => self.append("</strong>"),
                     TagEnd::Strikethrough => self.append("</delete>"),
@@ -159,10 +271,16 @@ impl Writer {
                         Some(Code::Named { lang, code }) => {
                             write!(self.buffer(), r#"<div class="codeblock">"#).unwrap();
 
-                            let output = syntect::html::highlighted_html_for_string(
-                                &code, &SET, lang, &THEME,
-                            )
-                            .unwrap();
+                            let highlight = || {
+                                syntect::html::highlighted_html_for_string(
+                                    &code, &SET, lang, &THEME,
+                                )
+                                .unwrap()
+                            };
+                            let output = match cache {
+                                Some(cache) => cache.highlight(&lang.name, &code, &highlight),
+                                None => highlight(),
+                            };
 
                             write!(self.buffer(), "{}</div>", output).unwrap();
                         }
@@ -183,22 +301,50 @@ impl Writer {
                         self.state = State::Normal;
                     }
                     TagEnd::HtmlBlock => code = None,
+                    TagEnd::Image => {
+                        if let Some((src, alt)) = image.take() {
+                            self.append(r#"<Image src=""#);
+                            html_encode(src.as_bytes(), self.buffer()).unwrap();
+                            self.append(r#"" alt=""#);
+                            html_encode(alt.as_bytes(), self.buffer()).unwrap();
+                            self.append(r#"" />"#);
+                        }
+                    }
                     _ => {} // tag => todo!("tag end: {tag:#?}"),
                 },
+                Event::Text(t) if image.is_some() => {
+                    if let Some((_, alt)) = &mut image {
+                        alt.push_str(&t);
+                    }
+                }
                 Event::Text(t) => match &mut code {
                     Some(Code::Named { code, .. }) => code.push_str(&t),
                     Some(Code::Yaml(yaml)) => yaml.extend(t.as_bytes()),
                     Some(Code::Html) => self.buffer().extend(t.as_bytes()),
-                    _ => html_encode(t.as_bytes(), self.buffer()).unwrap(),
+                    Some(Code::Unnamed) => html_encode(t.as_bytes(), self.buffer()).unwrap(),
+                    None => {
+                        self.words += t.split_whitespace().count();
+                        html_encode(t.as_bytes(), self.buffer()).unwrap()
+                    }
                 },
                 Event::FootnoteReference(label) => {
+                    let start = self.output.len();
                     write!(
                         self.buffer(),
                         r##"<FootnoteRef href="#fn{label}" id="ref{label}">{label}</FootnoteRef>"##
                     )
                     .unwrap();
+                    if matches!(self.state, State::Normal) {
+                        self.footnote_refs.push((start, self.output.len()));
+                    }
+                }
+                Event::Html(html) => {
+                    if html.trim() == "<!-- more -->" && !self.more {
+                        self.excerpt.1 = Some(self.output.len());
+                        self.more = true;
+                    }
+                    self.append(&html)
                 }
-                Event::Html(html) => self.append(&html),
                 Event::Code(code) => write!(self.buffer(), "<code>{code}</code>").unwrap(),
                 Event::InlineMath(math) => write!(self.buffer(), "<code>{math}</code>").unwrap(),
                 Event::SoftBreak => write!(self.buffer(), "\n").unwrap(),
@@ -212,19 +358,63 @@ impl Writer {
         Ok(())
     }
 
-    pub fn new(input: &str) -> Result<Self, SimpleError> {
+    pub fn new(input: &str) -> Result<Self, FrontmatterError> {
+        Self::render(input, None)
+    }
+
+    /// Render `input`, looking up highlighted code blocks in `cache`.
+    pub fn with_code_cache(input: &str, cache: &dyn CodeCache) -> Result<Self, FrontmatterError> {
+        Self::render(input, Some(cache))
+    }
+
+    fn render(input: &str, cache: Option<&dyn CodeCache>) -> Result<Self, FrontmatterError> {
         let mut visitor = Self {
             state: State::Normal,
             frontmatter: None,
             output: Vec::with_capacity(input.len()),
             footnotes: Vec::new(),
+            words: 0,
+            excerpt: (None, None),
+            footnote_refs: Vec::new(),
+            more: false,
         };
 
-        visitor.parse(input)?;
+        visitor.parse(input, cache)?;
 
         Ok(visitor)
     }
 
+    /// The number of words of prose, excluding code blocks.
+    pub fn word_count(&self) -> usize {
+        self.words
+    }
+
+    /// The estimated reading time in minutes, rounded up.
+    pub fn reading_time(&self) -> usize {
+        self.words.div_ceil(WORDS_PER_MINUTE).max(1)
+    }
+
+    /// The post's opening prose, from the first paragraph up to a
+    /// `<!-- more -->` marker or, failing that, the paragraph's end,
+    /// without its footnote references.
+    pub fn excerpt_html(&self) -> String {
+        let (Some(mut start), Some(end)) = self.excerpt else {
+            return String::new();
+        };
+
+        let mut excerpt = Vec::new();
+        for &(ref_start, ref_end) in &self.footnote_refs {
+            if ref_start >= start && ref_end <= end {
+                excerpt.extend(&self.output[start..ref_start]);
+                start = ref_end;
+            }
+        }
+        if start < end {
+            excerpt.extend(&self.output[start..end]);
+        }
+        String::from_utf8_lossy(&excerpt).into_owned()
+    }
+
     pub fn output(mut self) -> Vec<u8> {
         if !self.footnotes.is_empty() {
             write!(&mut self.output, "<Footnotes>").unwrap();
@@ -247,4 +437,52 @@ mod test {
         let writer = Writer::new(input).unwrap();
         let _output = writer.output();
     }
+
+    #[test]
+    fn counts_words() {
+        let input = "# One two\n\nThree *four* five.\n\n```rs\nfn six() {}\n```\n";
+
+        let writer = Writer::new(input).unwrap();
+        assert_eq!(writer.word_count(), 5);
+        assert_eq!(writer.reading_time(), 1);
+    }
+
+    #[test]
+    fn emits_images() {
+        let writer = Writer::new(r#"![A "quoted" cat](/img/cat.png)"#).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.output()).unwrap(),
+            r#"<p><Image src="/img/cat.png" alt="A &quot;quoted&quot; cat" /></p>"#
+        );
+    }
+
+    #[test]
+    fn extracts_excerpt() {
+        let writer = Writer::new("One.\n\nTwo.\n\n<!-- more -->\n\nThree.").unwrap();
+        assert_eq!(writer.excerpt_html(), "<p>One.</p><p>Two.</p>");
+
+        let writer = Writer::new("# Title\n\nOne.\n\nTwo.").unwrap();
+        assert_eq!(writer.excerpt_html(), "<p>One.</p>");
+
+        let writer = Writer::new("One.[^a] Two.[^b]\n\n[^a]: A.\n[^b]: B.").unwrap();
+        assert_eq!(writer.excerpt_html(), "<p>One. Two.</p>");
+    }
+
+    #[test]
+    fn parses_frontmatter_alone() {
+        let input = "---\ntitle: Post\nsitemap: false\n---\n\n# Heading\n";
+        let parsed = frontmatter(input).unwrap().unwrap();
+        assert_eq!(parsed.title, "Post");
+        assert!(!parsed.sitemap);
+
+        assert!(frontmatter("# No frontmatter\n").unwrap().is_none());
+    }
+
+    #[test]
+    fn locates_frontmatter_errors() {
+        let Err(error) = Writer::new("---\ntitle: Post\ndate: [\n---\n") else {
+            panic!("Malformed frontmatter was accepted");
+        };
+        assert_eq!(error.location.map(|(line, _)| line), Some(3));
+    }
 }
@@ -0,0 +1,33 @@
+use crate::output::Output;
+use anyhow::{anyhow, Error};
+use std::path::Path;
+
+/// Copy `source` into the build's `assets` directory under a
+/// content-hashed name, returning the URL it will be served from.
+pub fn copy_hashed(source: &Path, output: &Output) -> Result<String, Error> {
+    let data = fs_err::read(source)?;
+    let name = hashed_name(source, &data)?;
+
+    output.write(output.root().join("assets").join(&name), data)?;
+
+    Ok(format!("/assets/{name}"))
+}
+
+/// The URL [`copy_hashed`] will serve `source` from, without copying it.
+pub fn hashed_url(source: &Path) -> Result<String, Error> {
+    let data = fs_err::read(source)?;
+    Ok(format!("/assets/{}", hashed_name(source, &data)?))
+}
+
+fn hashed_name(source: &Path, data: &[u8]) -> Result<String, Error> {
+    let hash = blake3::hash(data).to_hex();
+
+    let stem = source
+        .file_stem()
+        .ok_or(anyhow!("Asset {source:?} has no file stem"))?
+        .to_string_lossy();
+    Ok(match source.extension() {
+        Some(extension) => format!("{stem}.{}.{}", &hash[..8], extension.to_string_lossy()),
+        None => format!("{stem}.{}", &hash[..8]),
+    })
+}
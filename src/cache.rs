@@ -0,0 +1,221 @@
+use anyhow::Error;
+use foldhash::HashSet;
+use markcomp::pull::{CodeCache, FrontmatterError, Writer};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Where rendered content is kept between builds.
+pub const DIR: &str = ".corvusite-cache";
+
+/// The kinds of entry the cache holds, each in its own directory.
+const KINDS: &[&str] = &["markdown", "code"];
+
+/// Rendered markdown and highlighted code blocks, keyed by a hash of
+/// their source, so that cold builds skip work for unchanged files.
+pub struct Cache {
+    /// `None` when caching is turned off.
+    root: Option<PathBuf>,
+    /// Entries looked up by this build, which garbage collection keeps.
+    used: Mutex<HashSet<PathBuf>>,
+}
+
+/// A cached markdown rendering, along with the code blocks it used.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Rendered {
+    writer: Writer,
+    code: Vec<PathBuf>,
+}
+
+impl Cache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: Some(root.into()),
+            used: Mutex::default(),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            root: None,
+            used: Mutex::default(),
+        }
+    }
+
+    /// Where the entry for `parts` of the given `kind` is kept.
+    fn entry(&self, kind: &str, parts: &[&str]) -> Option<PathBuf> {
+        let root = self.root.as_ref()?;
+
+        // Output can change between versions even when the source doesn't.
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        for part in parts {
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        let path = root.join(kind).join(hasher.finalize().to_hex().as_str());
+
+        self.keep(path.clone());
+        Some(path)
+    }
+
+    fn keep(&self, path: PathBuf) {
+        self.used.lock().unwrap().insert(path);
+    }
+
+    /// Render `source`, reusing an earlier rendering of the same markdown.
+    pub fn markdown(&self, source: &str) -> Result<Writer, FrontmatterError> {
+        let Some(path) = self.entry("markdown", &[source]) else {
+            return Writer::new(source);
+        };
+
+        let cached = read(&path).and_then(|data| serde_json::from_slice::<Rendered>(&data).ok());
+        if let Some(rendered) = cached {
+            for code in rendered.code {
+                self.keep(code);
+            }
+            return Ok(rendered.writer);
+        }
+
+        let recording = Recording {
+            cache: self,
+            code: RefCell::default(),
+        };
+        let writer = Writer::with_code_cache(source, &recording)?;
+        let rendered = Rendered {
+            writer,
+            code: recording.code.into_inner(),
+        };
+        // A cache that can't be written is only a missed speedup.
+        if let Ok(data) = serde_json::to_vec(&rendered) {
+            let _ = write(&path, &data);
+        }
+        Ok(rendered.writer)
+    }
+
+    /// Remove entries this build didn't use, returning how many there were.
+    ///
+    /// Only meaningful after a full build, since an incremental
+    /// one doesn't look up entries for the pages it skips.
+    pub fn collect_garbage(&self) -> Result<usize, Error> {
+        let Some(root) = &self.root else {
+            return Ok(0);
+        };
+
+        let used = self.used.lock().unwrap();
+        let mut removed = 0;
+        for kind in KINDS {
+            let Ok(entries) = fs_err::read_dir(root.join(kind)) else {
+                continue;
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if !used.contains(&path) {
+                    fs_err::remove_file(&path)?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// The contents of the entry at `path`, unless it's missing or was
+/// left incomplete or corrupted.
+fn read(path: &Path) -> Option<Vec<u8>> {
+    let mut data = fs_err::read(path).ok()?;
+    if data.len() < blake3::OUT_LEN {
+        return None;
+    }
+    let contents = data.split_off(blake3::OUT_LEN);
+    (blake3::hash(&contents) == blake3::Hash::from_slice(&data).ok()?).then_some(contents)
+}
+
+/// Write an entry behind a hash of its contents, which [`read`] checks.
+///
+/// Entries are written beside their path and renamed into place, so
+/// they never appear half-written, even to builds running alongside.
+fn write(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+
+    let Some(parent) = path.parent() else {
+        return Ok(());
+    };
+    fs_err::create_dir_all(parent)?;
+
+    let temporary = path.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut entry = blake3::hash(data).as_bytes().to_vec();
+    entry.extend_from_slice(data);
+    fs_err::write(&temporary, entry)?;
+    fs_err::rename(&temporary, path).inspect_err(|_| {
+        let _ = fs_err::remove_file(&temporary);
+    })
+}
+
+/// Looks up code blocks for one markdown file, noting which it used.
+struct Recording<'a> {
+    cache: &'a Cache,
+    code: RefCell<Vec<PathBuf>>,
+}
+
+impl CodeCache for Recording<'_> {
+    fn highlight(&self, lang: &str, code: &str, highlight: &dyn Fn() -> String) -> String {
+        let Some(path) = self.cache.entry("code", &[lang, code]) else {
+            return highlight();
+        };
+        self.code.borrow_mut().push(path.clone());
+
+        if let Some(html) = read(&path).and_then(|html| String::from_utf8(html).ok()) {
+            return html;
+        }
+        let html = highlight();
+        let _ = write(&path, html.as_bytes());
+        html
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reuses_renders_and_collects_garbage() {
+        let root = std::env::temp_dir().join(format!("corvusite-cache-{}", std::process::id()));
+        let _ = fs_err::remove_dir_all(&root);
+        let source = "---\ntitle: Post\n---\n\n```rs\nfn main() {}\n```\n";
+
+        let cache = Cache::new(&root);
+        let fresh = cache.markdown(source).unwrap().output();
+        let cached = Cache::new(&root).markdown(source).unwrap();
+        assert_eq!(fresh, cached.output());
+
+        // Another build that still uses the post keeps its code block too.
+        let cache = Cache::new(&root);
+        cache.markdown(source).unwrap();
+        assert_eq!(cache.collect_garbage().unwrap(), 0);
+
+        // One that doesn't clears out both.
+        let cache = Cache::new(&root);
+        cache.markdown("Something else.").unwrap();
+        assert_eq!(cache.collect_garbage().unwrap(), 2);
+
+        // Damaged entries are rendered again rather than trusted.
+        let cache = Cache::new(&root);
+        let path = cache.entry("markdown", &[source]).unwrap();
+        cache.markdown(source).unwrap();
+        let mut data = fs_err::read(&path).unwrap();
+        data.truncate(data.len() - 1);
+        fs_err::write(&path, &data).unwrap();
+        assert!(read(&path).is_none());
+        let rendered = Cache::new(&root).markdown(source).unwrap();
+        assert_eq!(fresh, rendered.output());
+        assert!(read(&path).is_some());
+
+        fs_err::remove_dir_all(&root).unwrap();
+    }
+}
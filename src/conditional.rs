@@ -0,0 +1,51 @@
+use std::time::UNIX_EPOCH;
+
+/// A validator for a served file, changing whenever its contents do.
+pub fn etag(contents: &[u8]) -> String {
+    format!("\"{}\"", &blake3::hash(contents).to_hex()[..16])
+}
+
+/// A validator for a file on disk from its size and modification time,
+/// to the nanosecond, so a rebuild within the same second still changes it.
+pub fn file_etag(metadata: &std::fs::Metadata) -> Option<String> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "\"{:x}-{:x}\"",
+        metadata.len(),
+        modified.as_nanos()
+    ))
+}
+
+/// Whether an `If-None-Match` header matches `etag`, meaning
+/// the client's copy is current. Comparison is weak, as for `GET`.
+pub fn matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|tag| opaque(tag) == opaque(etag))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_weakly() {
+        let tag = etag(b"<p>Hello</p>");
+        assert_ne!(tag, etag(b"<p>Hullo</p>"));
+
+        assert!(matches(&tag, &tag));
+        assert!(matches(&format!(r#""other", W/{tag}"#), &tag));
+        assert!(matches("*", &tag));
+        assert!(!matches(r#""other""#, &tag));
+    }
+
+    #[test]
+    fn tags_files_by_metadata() {
+        let metadata = std::fs::metadata("Cargo.toml").unwrap();
+        let tag = file_etag(&metadata).unwrap();
+        assert!(tag.starts_with(&format!("\"{:x}-", metadata.len())));
+        assert!(matches(&tag, &tag));
+    }
+}
@@ -0,0 +1,361 @@
+use crate::permalink;
+use crate::Options;
+use anyhow::Context;
+use convert_case::{Case, Casing};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Site configuration, read from `corvusite.toml` and
+/// overridden by command line options.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory containing source HTML files
+    pub site: String,
+    /// Directory for static files
+    #[serde(rename = "static")]
+    pub static_dir: String,
+    /// Directory for processed output
+    pub build: String,
+    /// Directory of JSON, YAML, and TOML files readable by `<For each>`
+    pub data: String,
+    /// Port for the development server
+    pub port: u16,
+    /// Extra headers the development server sends with every response,
+    /// like `"Cross-Origin-Opener-Policy" = "same-origin"`, to match production.
+    pub headers: BTreeMap<String, String>,
+    /// Include posts dated in the future
+    pub include_future: bool,
+    /// Include posts marked as drafts
+    pub drafts: bool,
+    /// Treat warnings, like prose lints and broken links, as errors
+    pub strict: bool,
+    /// Keep HTML comments, like conditional comments or license headers,
+    /// in pages. Minified builds always drop them.
+    pub keep_comments: bool,
+    /// The canonical root of the deployed site, like `https://example.com`.
+    /// Enables `sitemap.xml` and canonical links when set.
+    pub base_url: Option<String>,
+    /// Component packages produced by `corvusite pack`.
+    pub packages: Vec<PackageEntry>,
+    pub components: ComponentsConfig,
+    /// Generates `feed.xml` and `feed.json` from the blog when present.
+    pub feed: Option<FeedConfig>,
+    pub watch: WatchConfig,
+    /// Checks post prose at build time when present.
+    pub lint: Option<LintConfig>,
+    /// Adds JSON-LD `Article` metadata to each post when present.
+    pub structured_data: Option<StructuredDataConfig>,
+    /// Optimizes images referenced by `<Image src>` when present.
+    pub images: Option<ImagesConfig>,
+    /// An image to generate `favicon.ico` and the other icons from,
+    /// linked from every page.
+    pub favicon: Option<PathBuf>,
+    /// Old URLs mapped to where they've moved, like `"/old/" = "/new/"`.
+    pub redirects: BTreeMap<String, String>,
+    /// Languages the site is published in, like `["en", "de"]`. The first
+    /// is the default; pages for the others live under `site/<lang>/`,
+    /// and posts declare theirs with `lang:` frontmatter.
+    pub languages: Vec<String>,
+    /// Where posts are served, like `/{year}/{month}/{slug}/`. Posts can
+    /// override it with `permalink:` frontmatter.
+    pub permalink: String,
+    /// Runs the Tailwind CLI over generated pages when present,
+    /// appending its output to `output.css`.
+    pub tailwind: Option<TailwindConfig>,
+    /// Strips unused selectors from `output.css` in release builds when present.
+    pub purge: Option<PurgeConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PurgeConfig {
+    /// Classes, ids, and tags to keep even when no page uses them,
+    /// like those added by scripts.
+    pub safelist: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TailwindConfig {
+    /// The program and leading arguments, like `["npx", "tailwindcss"]`.
+    pub command: Vec<String>,
+    /// The stylesheet containing Tailwind's directives.
+    pub input: Option<PathBuf>,
+    /// Further arguments, with `{build}` replaced by the build directory.
+    pub args: Vec<String>,
+}
+
+impl Default for TailwindConfig {
+    fn default() -> Self {
+        Self {
+            command: vec!["tailwindcss".into()],
+            input: None,
+            args: vec!["--content".into(), "{build}/**/*.html".into()],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImagesConfig {
+    /// Wider images are scaled down to this width.
+    pub max_width: u32,
+    /// Smaller widths offered alongside each image through `srcset`.
+    pub widths: Vec<u32>,
+    /// The `sizes` given to each image, describing how wide it's displayed.
+    pub sizes: String,
+    pub format: ImageFormat,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self {
+            max_width: 1600,
+            widths: vec![480, 960],
+            sizes: "100vw".into(),
+            format: ImageFormat::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    #[default]
+    Webp,
+    Avif,
+    /// Keep each image's original format.
+    Original,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StructuredDataConfig {
+    pub author: Option<AuthorConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// A word list with one word per line. Words outside it are reported.
+    pub dictionary: Option<PathBuf>,
+    /// Words accepted in addition to the dictionary.
+    pub allow: Vec<String>,
+    /// Phrases that should never appear, matched case-insensitively.
+    pub banned: Vec<String>,
+    pub max_heading_length: Option<usize>,
+    pub require_description: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchConfig {
+    /// Gitignore-style patterns for changes that shouldn't trigger a rebuild.
+    pub ignore: Vec<String>,
+    /// Directories watched alongside the site, static, and data directories,
+    /// like a components repository shared between sites.
+    pub paths: Vec<PathBuf>,
+    /// How long to wait for a run of changes to settle before rebuilding.
+    pub debounce_ms: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            ignore: [
+                ".git/",
+                ".corvusite-cache/",
+                "README*",
+                "*.swp",
+                "*.swx",
+                "*~",
+                ".#*",
+                "4913",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            paths: Vec::new(),
+            debounce_ms: 150,
+        }
+    }
+}
+
+impl WatchConfig {
+    /// Build a matcher for the ignore patterns and `.corvusiteignore`, rooted at `root`.
+    pub fn matcher(&self, root: impl AsRef<Path>) -> anyhow::Result<Gitignore> {
+        let mut builder = ignore_builder(root.as_ref())?;
+        for pattern in &self.ignore {
+            builder.add_line(None, pattern)?;
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Gitignore-style patterns for sources that shouldn't be built or copied.
+pub const IGNORE_FILE: &str = ".corvusiteignore";
+
+fn ignore_builder(root: &Path) -> anyhow::Result<GitignoreBuilder> {
+    let mut builder = GitignoreBuilder::new(root);
+    let path = root.join(IGNORE_FILE);
+    if path.exists() {
+        if let Some(e) = builder.add(&path) {
+            return Err(e).with_context(|| format!("Failed to read {path:?}"));
+        }
+    }
+
+    Ok(builder)
+}
+
+/// Build a matcher for `.corvusiteignore` in `root`, if there is one.
+pub fn source_matcher(root: impl AsRef<Path>) -> anyhow::Result<Gitignore> {
+    Ok(ignore_builder(root.as_ref())?.build()?)
+}
+
+pub fn is_ignored(ignore: &Gitignore, path: &Path) -> bool {
+    // The matcher panics on absolute paths outside its root.
+    if path.is_absolute() && !path.starts_with(ignore.path()) {
+        return false;
+    }
+
+    ignore
+        .matched_path_or_any_parents(path, path.is_dir())
+        .is_ignore()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeedConfig {
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    /// Embed each article's rendered HTML rather than just its description.
+    #[serde(default)]
+    pub full_content: bool,
+    /// Credited in `feed.json`.
+    pub author: Option<AuthorConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthorConfig {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComponentsConfig {
+    /// Directory, relative to the site, whose subdirectories become
+    /// component namespaces (`ui/button.mod.html` => `<Ui.Button>`).
+    pub root: PathBuf,
+    /// Joins namespace segments and the component name.
+    pub separator: String,
+    /// Render each article as `<BlogArticle>` alone, given the post's `href`,
+    /// `title`, `description`, `date`, `minutes`, and `words`, with its
+    /// body as children, rather than in the built-in article shell.
+    pub blog_article_template: bool,
+    /// Render each blog card as `<BlogCard>` alone, given the post's `href`,
+    /// `title`, `description`, `date`, `minutes`, and `words`, with its
+    /// excerpt as children, rather than wrapping the built-in card markup.
+    pub blog_card_template: bool,
+}
+
+impl Default for ComponentsConfig {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("components"),
+            separator: ".".into(),
+            blog_article_template: false,
+            blog_card_template: false,
+        }
+    }
+}
+
+impl ComponentsConfig {
+    /// The namespace of a component at `path` (relative to the site),
+    /// or `None` if it lives in the global namespace.
+    pub fn namespace(&self, path: &Path) -> Option<String> {
+        let relative = path.parent()?.strip_prefix(&self.root).ok()?;
+
+        let segments: Vec<_> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_case(Case::Pascal))
+            .collect();
+
+        (!segments.is_empty()).then(|| segments.join(&self.separator))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageEntry {
+    pub path: PathBuf,
+    /// When set, the package's manifest must declare this exact version.
+    pub version: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            site: "site".into(),
+            static_dir: "static".into(),
+            build: "build".into(),
+            data: "data".into(),
+            port: 3000,
+            headers: BTreeMap::new(),
+            include_future: false,
+            drafts: false,
+            strict: false,
+            keep_comments: false,
+            base_url: None,
+            packages: Vec::new(),
+            components: ComponentsConfig::default(),
+            feed: None,
+            watch: WatchConfig::default(),
+            lint: None,
+            structured_data: None,
+            images: None,
+            favicon: None,
+            redirects: BTreeMap::new(),
+            languages: Vec::new(),
+            permalink: permalink::DEFAULT.into(),
+            tailwind: None,
+            purge: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config at `path`, falling back to the defaults if it doesn't exist.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let raw = fs_err::read_to_string(path)?;
+        toml::from_str(&raw).with_context(|| format!("Failed to parse config {path:?}"))
+    }
+
+    /// Apply command line overrides.
+    pub fn merge(&mut self, options: &Options) {
+        if let Some(site) = &options.site {
+            self.site.clone_from(site);
+        }
+        if let Some(static_dir) = &options.static_dir {
+            self.static_dir.clone_from(static_dir);
+        }
+        if let Some(build) = &options.build {
+            self.build.clone_from(build);
+        }
+
+        self.include_future |= options.include_future;
+        self.drafts |= options.drafts;
+        self.strict |= options.strict;
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::{anyhow, Context, Error};
+use std::collections::BTreeMap;
+use std::path::Path;
+use wincomp::data::Value;
+
+/// Load every JSON, YAML, and TOML file in `dir`, keyed by file stem,
+/// so `data/nav.yaml` is available as `nav`.
+pub fn load(dir: &Path) -> Result<Value, Error> {
+    let mut data = BTreeMap::new();
+    let Ok(entries) = fs_err::read_dir(dir) else {
+        return Ok(Value::Map(data));
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+            continue;
+        };
+
+        let source = || fs_err::read_to_string(&path);
+        let value: serde_json::Value = match extension {
+            "json" => serde_json::from_str(&source()?).map_err(Error::from),
+            "yaml" | "yml" => serde_yaml::from_str(&source()?).map_err(Error::from),
+            "toml" => toml::from_str(&source()?).map_err(Error::from),
+            _ => continue,
+        }
+        .with_context(|| format!("Failed to parse data file {path:?}"))?;
+
+        let stem = path
+            .file_stem()
+            .ok_or(anyhow!("Data file {path:?} has no file stem"))?
+            .to_string_lossy()
+            .into_owned();
+        if data.insert(stem, convert(value)).is_some() {
+            return Err(anyhow!("Data file {path:?} shares its name with another"));
+        }
+    }
+
+    Ok(Value::Map(data))
+}
+
+fn convert(value: serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::String(String::new()),
+        serde_json::Value::String(s) => Value::String(s),
+        serde_json::Value::Array(list) => Value::List(list.into_iter().map(convert).collect()),
+        serde_json::Value::Object(map) => {
+            Value::Map(map.into_iter().map(|(k, v)| (k, convert(v))).collect())
+        }
+        value => Value::String(value.to_string()),
+    }
+}
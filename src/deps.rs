@@ -0,0 +1,128 @@
+use foldhash::{HashMap, HashSet};
+use std::path::PathBuf;
+
+/// Records what each page was built from, so that a change to one
+/// source file only rebuilds the pages depending on it.
+///
+/// Every path is canonical.
+#[derive(Default)]
+pub struct Graph {
+    /// Component sources and the names they define.
+    components: HashMap<PathBuf, String>,
+    /// Stylesheets merged into `output.css`.
+    stylesheets: HashSet<PathBuf>,
+    pages: HashMap<PathBuf, Dependencies>,
+}
+
+#[derive(Default)]
+pub struct Dependencies {
+    /// Components expanded while rendering the page, including nested ones.
+    pub components: HashSet<String>,
+    /// Other sources that went into the page, like includes and post assets.
+    pub files: HashSet<PathBuf>,
+}
+
+impl Graph {
+    pub fn page(&mut self, key: PathBuf) -> &mut Dependencies {
+        self.pages.entry(key).or_default()
+    }
+
+    /// Replace the known components, returning whether any were
+    /// added, removed, or renamed.
+    pub fn set_components(&mut self, components: HashMap<PathBuf, String>) -> bool {
+        let changed = self.components != components;
+        self.components = components;
+        changed
+    }
+
+    pub fn set_stylesheets(&mut self, stylesheets: HashSet<PathBuf>) {
+        self.stylesheets = stylesheets;
+    }
+
+    /// The pages that need rebuilding after `changed` were modified,
+    /// or `None` if a change can't be traced and everything must be rebuilt.
+    pub fn invalidate(&self, changed: &[PathBuf]) -> Option<HashSet<PathBuf>> {
+        let mut dirty = HashSet::default();
+
+        for path in changed {
+            let mut known = self.stylesheets.contains(path);
+
+            if self.pages.contains_key(path) {
+                dirty.insert(path.clone());
+                known = true;
+            }
+
+            let component = self.components.get(path);
+            known |= component.is_some();
+
+            for (key, page) in &self.pages {
+                let uses_component = component.is_some_and(|c| page.components.contains(c));
+                if uses_component || page.files.contains(path) {
+                    dirty.insert(key.clone());
+                    known = true;
+                }
+            }
+
+            if !known {
+                return None;
+            }
+        }
+
+        Some(dirty)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn set<T: std::hash::Hash + Eq>(items: impl IntoIterator<Item = T>) -> HashSet<T> {
+        items.into_iter().collect()
+    }
+
+    fn graph() -> Graph {
+        let mut graph = Graph::default();
+        graph.set_components(
+            [("/c/card.mod.html".into(), "Card".to_string())]
+                .into_iter()
+                .collect(),
+        );
+        graph.set_stylesheets(set(["/site/index.css".into()]));
+        graph.page("/site/index.html".into()).components = set(["Shell".into()]);
+        graph.page("/site/blog/a.md".into()).components = set(["Shell".into(), "Card".into()]);
+        graph.page("/site/blog/b.md".into()).files = set(["/site/blog/shared.md".into()]);
+        graph
+    }
+
+    #[test]
+    fn invalidates_dependents() {
+        let graph = graph();
+
+        assert_eq!(
+            graph.invalidate(&["/site/index.html".into()]),
+            Some(set(["/site/index.html".into()]))
+        );
+        assert_eq!(
+            graph.invalidate(&["/c/card.mod.html".into()]),
+            Some(set(["/site/blog/a.md".into()]))
+        );
+        assert_eq!(
+            graph.invalidate(&["/site/blog/shared.md".into()]),
+            Some(set(["/site/blog/b.md".into()]))
+        );
+        assert_eq!(
+            graph.invalidate(&["/site/index.css".into()]),
+            Some(HashSet::default())
+        );
+    }
+
+    #[test]
+    fn unknown_files_rebuild_everything() {
+        let graph = graph();
+
+        assert_eq!(
+            graph.invalidate(&["/site/index.html".into(), "/site/new.html".into()]),
+            None
+        );
+    }
+}
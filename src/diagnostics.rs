@@ -0,0 +1,296 @@
+use anyhow::{bail, Error};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How many lines before the one a diagnostic points at its snippet shows.
+const CONTEXT_LINES: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem found during a build, pointing into its source where possible.
+///
+/// Diagnostics raised as errors keep their location through `anyhow`,
+/// so code can bail with one and still have it reported in full.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: Option<PathBuf>,
+    /// The one-based line and column.
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+    /// The source around `location`, with a caret under it.
+    pub snippet: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(path: impl Into<PathBuf>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            path: Some(path.into()),
+            location: None,
+            message: message.into(),
+            snippet: None,
+        }
+    }
+
+    pub fn warning(path: impl Into<PathBuf>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(path, message)
+        }
+    }
+
+    pub fn at(mut self, line: usize, column: usize) -> Self {
+        self.location = Some((line, column));
+        self
+    }
+
+    /// Point at the byte `offset` into `source`, showing the lines around it.
+    pub fn at_offset(mut self, source: &str, offset: usize) -> Self {
+        let (line, column) = location(source, offset);
+        self.snippet = Some(snippet(source, line, column));
+        self.at(line, column)
+    }
+
+    /// Where the diagnostic points, like `site/index.html:3:14`.
+    pub fn place(&self) -> String {
+        let path = self.path.as_deref().unwrap_or(Path::new("")).display();
+        match self.location {
+            Some((line, column)) => format!("{path}:{line}:{column}"),
+            None => path.to_string(),
+        }
+    }
+
+    /// The diagnostic an error was raised with, or one describing it.
+    pub fn from_error(error: &Error) -> Self {
+        match error.downcast_ref::<Diagnostic>() {
+            Some(diagnostic) => diagnostic.clone(),
+            None => Self {
+                severity: Severity::Error,
+                path: None,
+                location: None,
+                message: format!("{error:#}"),
+                snippet: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_some() {
+            write!(f, "{}: ", self.place())?;
+        }
+        f.write_str(&self.message)?;
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n{snippet}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The one-based line and column of the byte `offset` into `source`.
+pub fn location(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+/// The lines of `source` up to the one-based `line`, numbered, with a
+/// caret under `column`.
+///
+/// ```text
+/// 1 | <html>
+/// 2 |   <div>
+///   |   ^
+/// ```
+fn snippet(source: &str, line: usize, column: usize) -> String {
+    let first = line.saturating_sub(CONTEXT_LINES).max(1);
+    let width = line.to_string().len();
+
+    let mut snippet = String::new();
+    let mut pointed = "";
+    for (number, text) in source.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+        if number < first {
+            continue;
+        }
+        if number > line {
+            break;
+        }
+        snippet.push_str(&format!("{number:>width$} | {text}\n"));
+        pointed = text;
+    }
+
+    // Tabs are kept so the caret lines up however they're displayed.
+    let padding: String = pointed
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    snippet.push_str(&format!("{:width$} | {padding}^", ""));
+    snippet
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Accumulates a build's errors and warnings so it can report all of them at once.
+pub struct Diagnostics {
+    keep_going: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Collect the successful results, stopping at the first
+    /// error unless `keep_going` is set.
+    pub fn collect<T>(
+        &mut self,
+        results: impl IntoIterator<Item = Result<T, Error>>,
+    ) -> Result<Vec<T>, Error> {
+        let mut values = Vec::new();
+        for result in results {
+            match result {
+                Ok(value) => values.push(value),
+                Err(e) if self.keep_going => self.push(Diagnostic::from_error(&e)),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Print a table of everything collected, failing if there were
+    /// errors, or warnings when `strict` is set.
+    pub fn finish(self, strict: bool) -> Result<(), Error> {
+        if !self.diagnostics.is_empty() {
+            eprint!("{}", table(&self.diagnostics));
+        }
+
+        let errors = count(&self.diagnostics, Severity::Error);
+        let warnings = count(&self.diagnostics, Severity::Warning);
+        if errors > 0 {
+            bail!("Build failed with {errors} error(s)");
+        }
+        if strict && warnings > 0 {
+            bail!("Build failed with {warnings} warning(s)");
+        }
+        Ok(())
+    }
+}
+
+fn count(diagnostics: &[Diagnostic], severity: Severity) -> usize {
+    diagnostics
+        .iter()
+        .filter(|d| d.severity == severity)
+        .count()
+}
+
+/// Diagnostics in aligned columns, followed by a count of each severity.
+fn table(diagnostics: &[Diagnostic]) -> String {
+    let places: Vec<_> = diagnostics.iter().map(Diagnostic::place).collect();
+    let width = places.iter().map(|p| p.chars().count()).max().unwrap_or(0);
+
+    let mut table = String::new();
+    for (diagnostic, place) in diagnostics.iter().zip(&places) {
+        table.push_str(&format!(
+            "{:<7}  {place:<width$}  {}\n",
+            diagnostic.severity.to_string(),
+            diagnostic.message
+        ));
+        if let Some(snippet) = &diagnostic.snippet {
+            for line in snippet.lines() {
+                table.push_str(&format!("{:9}{line}\n", ""));
+            }
+        }
+    }
+
+    table.push_str(&format!(
+        "{} error(s), {} warning(s)\n",
+        count(diagnostics, Severity::Error),
+        count(diagnostics, Severity::Warning)
+    ));
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locates_and_tabulates() {
+        let error = Diagnostic::error("site/index.html", "Unclosed <div>")
+            .at_offset("<html>\n  <div>\n", 9);
+        assert_eq!(error.location, Some((2, 3)));
+        assert_eq!(
+            error.snippet.as_deref(),
+            Some("1 | <html>\n2 |   <div>\n  |   ^")
+        );
+        let error = Diagnostic {
+            snippet: None,
+            ..error
+        };
+
+        // Context doesn't hide where an error came from.
+        let wrapped = Error::new(error).context("Error processing files");
+        let mut diagnostics = Diagnostics::new(true);
+        let values = diagnostics.collect([Ok(1), Err(wrapped)]).unwrap();
+        assert_eq!(values, [1]);
+        diagnostics.push(Diagnostic::warning("site/blog/post.md", "Broken link"));
+
+        assert_eq!(
+            table(&diagnostics.diagnostics),
+            "error    site/index.html:2:3  Unclosed <div>\n\
+             warning  site/blog/post.md    Broken link\n\
+             1 error(s), 1 warning(s)\n"
+        );
+        assert!(diagnostics.finish(false).is_err());
+    }
+
+    #[test]
+    fn shows_source_snippets() {
+        let source = "<html>\n<body>\n\t<main>\n\t\t<p>\n\t</main>\n";
+        let error = Diagnostic::error("site/index.html", "Unclosed <p>")
+            .at_offset(source, source.find("<p>").unwrap());
+
+        assert_eq!(
+            error.to_string(),
+            "site/index.html:4:3: Unclosed <p>\n\
+             2 | <body>\n\
+             3 | \t<main>\n\
+             4 | \t\t<p>\n  \
+             | \t\t^"
+        );
+        assert_eq!(
+            table(&[error]),
+            "error    site/index.html:4:3  Unclosed <p>\n         \
+             2 | <body>\n         \
+             3 | \t<main>\n         \
+             4 | \t\t<p>\n           \
+             | \t\t^\n\
+             1 error(s), 0 warning(s)\n"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use crate::output::Output;
+use anyhow::{Context, Error};
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::FilterType;
+use image::{DynamicImage, ExtendedColorType, ImageFormat};
+use std::io::Cursor;
+use std::path::Path;
+
+/// Sizes bundled into `favicon.ico`.
+const ICO_SIZES: &[u32] = &[16, 32, 48];
+
+/// Standalone PNG icons, by file name and size.
+const PNGS: &[(&str, u32)] = &[
+    ("favicon-16x16.png", 16),
+    ("favicon-32x32.png", 32),
+    ("icon-192x192.png", 192),
+    ("apple-touch-icon.png", 180),
+];
+
+/// The `<link>` tags pointing browsers at the generated icons.
+pub const LINKS: &str = r#"
+        <link rel="icon" href="/favicon.ico" sizes="48x48">
+        <link rel="icon" type="image/png" sizes="16x16" href="/favicon-16x16.png">
+        <link rel="icon" type="image/png" sizes="32x32" href="/favicon-32x32.png">
+        <link rel="icon" type="image/png" sizes="192x192" href="/icon-192x192.png">
+        <link rel="apple-touch-icon" href="/apple-touch-icon.png">
+    "#;
+
+/// Generate the favicon set from a single `source` image at the build's root.
+pub fn generate(source: &Path, output: &Output) -> Result<(), Error> {
+    let image =
+        image::open(source).with_context(|| format!("Failed to read favicon {source:?}"))?;
+    let square = |size| {
+        image
+            .resize_to_fill(size, size, FilterType::Lanczos3)
+            .to_rgba8()
+    };
+
+    let frames = ICO_SIZES
+        .iter()
+        .map(|&size| IcoFrame::as_png(&square(size), size, size, ExtendedColorType::Rgba8))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut ico = Vec::new();
+    IcoEncoder::new(&mut ico).encode_images(&frames)?;
+    output.write(output.root().join("favicon.ico"), ico)?;
+
+    for &(name, size) in PNGS {
+        let mut png = Vec::new();
+        DynamicImage::from(square(size)).write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        output.write(output.root().join(name), png)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::output::Memory;
+    use image::{GenericImageView, RgbaImage};
+
+    #[test]
+    fn generates_square_icons() {
+        let root = std::env::temp_dir().join(format!("corvusite-favicon-{}", std::process::id()));
+        fs_err::create_dir_all(&root).unwrap();
+        // Wider than it is tall, so each icon is cropped to a square.
+        let source = root.join("logo.png");
+        RgbaImage::new(64, 32).save(&source).unwrap();
+
+        let memory = Memory::default();
+        let output = Output::in_memory(root.join("build"), &memory).unwrap();
+        generate(&source, &output).unwrap();
+        assert!(generate(&root.join("missing.png"), &output).is_err());
+        output.commit().unwrap();
+        fs_err::remove_dir_all(&root).unwrap();
+
+        let ico = memory.get(Path::new("favicon.ico")).unwrap();
+        let ico = image::load_from_memory_with_format(&ico, ImageFormat::Ico).unwrap();
+        assert_eq!(ico.dimensions(), (48, 48));
+
+        for &(name, size) in PNGS {
+            let png = memory.get(Path::new(name)).unwrap();
+            let png = image::load_from_memory_with_format(&png, ImageFormat::Png).unwrap();
+            assert_eq!(png.dimensions(), (size, size), "{name}");
+            assert!(LINKS.contains(&format!("/{name}")));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::config::FeedConfig;
+use crate::gen::escape_html;
+use anyhow::Error;
+use jiff::{civil::Date, tz::TimeZone};
+use std::fmt::Write;
+
+pub struct FeedItem<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+    pub date: Date,
+    /// The item's path relative to the site root, like `/blog/my-post/`.
+    pub path: &'a str,
+    /// Fully rendered article HTML, if full-content feeds are enabled.
+    pub content: Option<&'a str>,
+}
+
+/// Render an RSS 2.0 feed.
+pub fn rss(config: &FeedConfig, base_url: &str, items: &[FeedItem<'_>]) -> Result<String, Error> {
+    let base_url = base_url.trim_end_matches('/');
+    let mut output = String::new();
+
+    write!(
+        output,
+        r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/"><channel><title>{}</title><link>{base_url}/</link><description>{}</description>"#,
+        escape_html(&config.title),
+        escape_html(&config.description),
+    )?;
+
+    for item in items {
+        let link = format!("{base_url}{}", item.path);
+        let date = item.date.to_zoned(TimeZone::UTC)?;
+
+        write!(
+            output,
+            "<item><title>{}</title><link>{link}</link><guid>{link}</guid><pubDate>{}</pubDate><description>{}</description>",
+            escape_html(item.title),
+            jiff::fmt::rfc2822::to_string(&date)?,
+            escape_html(item.description.trim()),
+        )?;
+
+        if let Some(content) = item.content {
+            let content = absolutize(content, base_url, &link);
+            // A literal `]]>` would end the CDATA section early.
+            let content = content.replace("]]>", "]]]]><![CDATA[>");
+            write!(
+                output,
+                "<content:encoded><![CDATA[{content}]]></content:encoded>"
+            )?;
+        }
+
+        output.push_str("</item>");
+    }
+
+    output.push_str("</channel></rss>");
+
+    Ok(output)
+}
+
+/// Render a JSON Feed 1.1 document.
+pub fn json(config: &FeedConfig, base_url: &str, items: &[FeedItem<'_>]) -> Result<String, Error> {
+    let base_url = base_url.trim_end_matches('/');
+
+    let items = items
+        .iter()
+        .map(|item| {
+            let link = format!("{base_url}{}", item.path);
+            let date = item.date.to_zoned(TimeZone::UTC)?.timestamp();
+
+            let mut entry = serde_json::json!({
+                "id": link,
+                "url": link,
+                "title": item.title,
+                "summary": item.description.trim(),
+                "date_published": date.to_string(),
+            });
+            match item.content {
+                Some(content) => {
+                    entry["content_html"] = absolutize(content, base_url, &link).into()
+                }
+                None => entry["content_text"] = item.description.trim().into(),
+            }
+
+            Ok(entry)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut feed = serde_json::json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": config.title,
+        "home_page_url": format!("{base_url}/"),
+        "feed_url": format!("{base_url}/feed.json"),
+        "items": items,
+    });
+    if !config.description.is_empty() {
+        feed["description"] = config.description.as_str().into();
+    }
+    if let Some(author) = &config.author {
+        let mut entry = serde_json::json!({ "name": author.name });
+        if let Some(url) = &author.url {
+            entry["url"] = url.as_str().into();
+        }
+        feed["authors"] = serde_json::json!([entry]);
+    }
+
+    Ok(serde_json::to_string_pretty(&feed)?)
+}
+
+/// Rewrite `href` and `src` attributes into absolute URLs, since feed readers
+/// display content outside the context of the page it came from.
+fn absolutize(html: &str, base_url: &str, page_url: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = ["href=\"", "src=\""]
+        .iter()
+        .filter_map(|a| rest.find(a).map(|i| i + a.len()))
+        .min()
+    {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with("//") || rest.split('"').next().is_some_and(|v| v.contains(':')) {
+            continue;
+        }
+
+        if rest.starts_with('/') {
+            output.push_str(base_url);
+        } else {
+            output.push_str(page_url);
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
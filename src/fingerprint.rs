@@ -0,0 +1,204 @@
+use crate::output::Output;
+use anyhow::{anyhow, Error};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const IMAGES: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp", "avif", "ico"];
+
+/// Files at the root that are requested by name, rather than through a
+/// reference that could be rewritten, and so keep their names.
+const FIXED: &[&str] = &[
+    "favicon.ico",
+    "apple-touch-icon.png",
+    "apple-touch-icon-precomposed.png",
+    "sw.js",
+    "service-worker.js",
+];
+
+/// Rename the build's stylesheets, scripts, and images to content-hashed
+/// names, rewriting references to them in HTML, CSS, and XML.
+///
+/// References are rewritten when they're absolute, or relative to the
+/// referring file's own directory, like `photo.png` or `./photo.png`.
+/// Ones that climb out of it, like `../photo.png`, are left alone.
+///
+/// Writes `manifest.json`, mapping each original URL to its hashed one.
+pub fn fingerprint(output: &Output) -> Result<(), Error> {
+    let root = output.root();
+    let mut produced = output.produced();
+    produced.sort();
+
+    let extension = |path: &Path| {
+        path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    };
+    // Assets copied by `assets::copy_hashed` are already fingerprinted.
+    let hashed = root.join("assets");
+    let (images, rest): (Vec<_>, Vec<_>) = produced
+        .into_iter()
+        .filter(|p| !p.starts_with(&hashed))
+        .filter(|p| {
+            !(p.parent() == Some(root)
+                && FIXED.contains(&p.file_name().unwrap_or_default().to_string_lossy().as_ref()))
+        })
+        .partition(|p| IMAGES.contains(&extension(p).as_str()));
+    let (stylesheets, rest): (Vec<_>, Vec<_>) =
+        rest.into_iter().partition(|p| extension(p) == "css");
+    let (scripts, documents): (Vec<_>, Vec<_>) =
+        rest.into_iter().partition(|p| extension(p) == "js");
+
+    let mut manifest = BTreeMap::new();
+
+    // Images first, since stylesheets can refer to them.
+    for path in images.iter().chain(&scripts) {
+        let data = output.read(path)?;
+        rename(output, path, &data, &mut manifest)?;
+    }
+    for path in &stylesheets {
+        let data = rewrite(&output.read_to_string(path)?, &manifest, &base(root, path)?);
+        rename(output, path, data.as_bytes(), &mut manifest)?;
+    }
+
+    for path in documents
+        .iter()
+        .filter(|p| matches!(extension(p).as_str(), "html" | "xml"))
+    {
+        let data = rewrite(&output.read_to_string(path)?, &manifest, &base(root, path)?);
+        output.write(path, data)?;
+    }
+
+    output.write(
+        root.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+
+    Ok(())
+}
+
+/// Write `data` alongside `path` under a hashed name, recording the new URL.
+fn rename(
+    output: &Output,
+    path: &Path,
+    data: &[u8],
+    manifest: &mut BTreeMap<String, String>,
+) -> Result<(), Error> {
+    let hash = blake3::hash(data).to_hex();
+    let stem = path
+        .file_stem()
+        .ok_or(anyhow!("Asset {path:?} has no file stem"))?
+        .to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}.{}.{}", &hash[..8], extension.to_string_lossy()),
+        None => format!("{stem}.{}", &hash[..8]),
+    };
+    let hashed = path.with_file_name(name);
+
+    output.write(&hashed, data)?;
+    output.discard(path);
+
+    manifest.insert(url(output.root(), path)?, url(output.root(), &hashed)?);
+
+    Ok(())
+}
+
+fn url(root: &Path, path: &Path) -> Result<String, Error> {
+    let segments: Vec<_> = path
+        .strip_prefix(root)?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    Ok(format!("/{}", segments.join("/")))
+}
+
+/// The URL of the directory holding `path`, ending in a slash.
+fn base(root: &Path, path: &Path) -> Result<String, Error> {
+    let url = url(root, path)?;
+    Ok(url[..=url.rfind('/').unwrap_or(0)].to_owned())
+}
+
+/// Replace quoted or `url()` references to each original URL, either
+/// absolute or relative to `base`.
+fn rewrite(source: &str, manifest: &BTreeMap<String, String>, base: &str) -> String {
+    let mut source = source.to_owned();
+    for (from, to) in manifest {
+        let mut forms = vec![(from.clone(), to.clone())];
+        // Hashed files stay in the same directory, so both strip alike.
+        if let (Some(from), Some(to)) = (from.strip_prefix(base), to.strip_prefix(base)) {
+            forms.push((format!("./{from}"), format!("./{to}")));
+            forms.push((from.to_owned(), to.to_owned()));
+        }
+        for (from, to) in &forms {
+            for (open, close) in [("\"", "\""), ("'", "'"), ("(", ")")] {
+                source = source.replace(
+                    &format!("{open}{from}{close}"),
+                    &format!("{open}{to}{close}"),
+                );
+            }
+        }
+    }
+    source
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rewrites_references() {
+        let manifest = [(
+            "/output.css".to_string(),
+            "/output.1234abcd.css".to_string(),
+        )]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            rewrite(
+                r#"<link href="/output.css"><a href="/output.css.bak">"#,
+                &manifest,
+                "/"
+            ),
+            r#"<link href="/output.1234abcd.css"><a href="/output.css.bak">"#
+        );
+        assert_eq!(
+            rewrite("a { background: url(/output.css) }", &manifest, "/"),
+            "a { background: url(/output.1234abcd.css) }"
+        );
+    }
+
+    #[test]
+    fn rewrites_relative_references_and_keeps_fixed_names() {
+        let root =
+            std::env::temp_dir().join(format!("corvusite-fingerprint-{}", std::process::id()));
+        let _ = fs_err::remove_dir_all(&root);
+
+        let output = Output::new(&root);
+        output.write(root.join("favicon.ico"), "icon").unwrap();
+        output
+            .write(root.join("blog/post/photo.png"), "photo")
+            .unwrap();
+        output.write(root.join("blog/photo.png"), "parent").unwrap();
+        output
+            .write(
+                root.join("blog/post/index.html"),
+                r#"<link rel="icon" href="/favicon.ico"><img src="photo.png"><img src="./photo.png"><img src="../photo.png">"#,
+            )
+            .unwrap();
+        fingerprint(&output).unwrap();
+
+        let photo = format!("photo.{}.png", &blake3::hash(b"photo").to_hex()[..8]);
+        assert_eq!(
+            output
+                .read_to_string(root.join("blog/post/index.html"))
+                .unwrap(),
+            format!(
+                r#"<link rel="icon" href="/favicon.ico"><img src="{photo}"><img src="./{photo}"><img src="../photo.png">"#
+            )
+        );
+        assert!(output.is_file(&root.join("favicon.ico")));
+        assert!(output.is_file(&root.join("blog/post").join(&photo)));
+
+        fs_err::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,39 +1,419 @@
+use crate::assets;
+use crate::cache::{self, Cache};
+use crate::config::{self, is_ignored, Config, StructuredDataConfig};
+use crate::data;
+use crate::deps::Graph;
+use crate::diagnostics::{self, Diagnostic, Diagnostics};
+use crate::favicon;
+use crate::feed::{self, FeedItem};
+use crate::fingerprint;
+use crate::hooks::Hooks;
+use crate::i18n::{self, Locales};
+use crate::images::{self, Images};
+use crate::include;
 use crate::lazy_comp::{icons, LazyComponents};
-use crate::Options;
+use crate::links;
+use crate::lint::Linter;
+use crate::live::{self, Refresh};
+use crate::output::{Memory, Output};
+use crate::package::Package;
+use crate::permalink;
+use crate::plugin::Plugins;
+use crate::purge;
+use crate::redirects;
+use crate::series;
+use crate::sitemap::{self, Entry};
+use crate::tailwind;
+use crate::timings::Timings;
 use anyhow::{anyhow, bail, Error};
-use foldhash::HashMap;
-use std::io::Write;
-use std::path::Path;
-use std::sync::LazyLock;
+use convert_case::{Case, Casing};
+use foldhash::{HashMap, HashSet};
+use ignore::gitignore::Gitignore;
+use markcomp::pull::Frontmatter;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use tracing::{debug, info, info_span};
+use wincomp::data::Value;
+use wincomp::element::{Element, Node};
 
 pub static ICONS: LazyLock<LazyComponents<'static, foldhash::fast::RandomState>> =
     LazyLock::new(icons::<foldhash::fast::RandomState>);
 
+#[derive(Clone)]
+struct Article {
+    /// The canonical path of the article's markdown source.
+    source: PathBuf,
+    /// Included files and post assets the article was built from.
+    dependencies: HashSet<PathBuf>,
+    date: jiff::civil::Date,
+    /// Where the post is served, following its permalink.
+    url: String,
+    /// The URL prefix of the post's language, empty for the default one.
+    prefix: String,
+    /// The directory containing the article's markdown source.
+    source_dir: PathBuf,
+    frontmatter: Frontmatter,
+    /// Where the post is written, relative to the build directory.
+    outpath: PathBuf,
+    /// The opening of the page's shell, up to the article body.
+    head: String,
+    body: Vec<u8>,
+    /// The rest of the page's shell, including post-specific scripts.
+    tail: String,
+    /// The expanded article body, for full-content feeds.
+    content: Option<String>,
+    words: usize,
+    /// Estimated reading time in minutes.
+    minutes: usize,
+    /// The article's opening, shown on blog cards.
+    excerpt: String,
+}
+
+impl Article {
+    fn url(&self) -> String {
+        self.url.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct BuildOptions {
+    /// Add the live reload script to every page.
+    pub inject_reload: bool,
+    /// Report every failing page rather than stopping at the first.
+    pub keep_going: bool,
+    /// Fingerprint assets for long-lived caching.
+    pub release: bool,
+    /// Minify generated pages.
+    pub minify: bool,
+    /// Indent generated pages.
+    pub pretty: bool,
+    /// Sort directory traversals and pin dates to `SOURCE_DATE_EPOCH`.
+    pub reproducible: bool,
+    /// Render markdown from scratch rather than reusing the build cache.
+    pub no_cache: bool,
+    /// Report how long each stage of the build took.
+    pub timings: bool,
+    /// Work around malformed markup in pages, like unclosed tags,
+    /// warning about it rather than failing the page.
+    pub lenient: bool,
+}
+
+impl BuildOptions {
+    /// Walk `dir`, in a stable order when building reproducibly.
+    fn walk(&self, dir: &Path) -> walkdir::WalkDir {
+        let walk = walkdir::WalkDir::new(dir);
+        if self.reproducible {
+            walk.sort_by_file_name()
+        } else {
+            walk
+        }
+    }
+
+    /// The pinned build time, when building reproducibly.
+    fn source_date(&self) -> Result<Option<jiff::Timestamp>, Error> {
+        if !self.reproducible {
+            return Ok(None);
+        }
+        let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") else {
+            return Ok(None);
+        };
+        let seconds = epoch
+            .trim()
+            .parse()
+            .map_err(|e| anyhow!("Invalid SOURCE_DATE_EPOCH {epoch:?}: {e}"))?;
+        Ok(Some(jiff::Timestamp::from_second(seconds)?))
+    }
+
+    /// When the file at `path` was last modified, for the sitemap.
+    ///
+    /// Reproducible builds can't depend on modification times,
+    /// so they use the pinned build time, if any.
+    fn modified(&self, path: &Path) -> Result<Option<jiff::civil::Date>, Error> {
+        let timestamp = if self.reproducible {
+            self.source_date()?
+        } else {
+            Some(jiff::Timestamp::try_from(
+                fs_err::metadata(path)?.modified()?,
+            )?)
+        };
+        Ok(timestamp.map(|t| t.to_zoned(jiff::tz::TimeZone::UTC).date()))
+    }
+}
+
+/// State kept between builds so that rebuilds can skip unaffected pages.
+#[derive(Default)]
+pub(crate) struct Incremental {
+    graph: Graph,
+    /// Rendered articles, keyed by their canonical source path.
+    articles: HashMap<PathBuf, Article>,
+    /// The ids on each page, by path relative to the build directory.
+    anchors: HashMap<PathBuf, HashSet<String>>,
+    /// Hashes of the markup generated for posts and blog indexes,
+    /// by path relative to the build directory.
+    generated: HashMap<PathBuf, blake3::Hash>,
+    /// Parsed components, before plugins see them, and hashes of their
+    /// sources, by canonical path. Rebuilds only parse what's changed.
+    components: HashMap<PathBuf, (blake3::Hash, Arc<wincomp::Component<'static>>)>,
+    /// Where builds go instead of the build directory, if anywhere.
+    memory: Option<Memory>,
+}
+
+impl Incremental {
+    /// State for builds kept in `memory` rather than written to disk.
+    pub fn in_memory(memory: Memory) -> Self {
+        Self {
+            memory: Some(memory),
+            ..Default::default()
+        }
+    }
+
+    /// Forget everything about earlier builds, but not where they go or
+    /// the components parsed, which are keyed by their sources' hashes.
+    fn reset(&mut self) {
+        *self = Self {
+            memory: self.memory.take(),
+            components: std::mem::take(&mut self.components),
+            ..Default::default()
+        };
+    }
+
+    /// Note the markup generated for `outpath`, returning whether it changed.
+    fn generate(&mut self, outpath: &Path, markup: &str) -> bool {
+        let hash = blake3::hash(markup.as_bytes());
+        self.generated.insert(outpath.to_owned(), hash) != Some(hash)
+    }
+}
+
+/// A page's markup before its components are expanded.
+enum Markup {
+    /// A page read from its source file.
+    Source(PathBuf),
+    /// A page generated from markdown.
+    Generated(String),
+}
+
+/// A page waiting to be expanded.
+struct Pending {
+    /// Where the page is written, relative to the build directory.
+    outpath: PathBuf,
+    /// The page's key in the dependency graph.
+    key: PathBuf,
+    markup: Markup,
+}
+
+fn load_packages(config: &Config) -> Result<Vec<Package>, Error> {
+    config
+        .packages
+        .iter()
+        .map(|p| Package::load(&p.path, p.version.as_deref()))
+        .collect()
+}
+
+/// Run `build` against a staged copy of the build directory, or of the
+/// in-memory build, which replaces the real one only if the build succeeds.
+fn staged<T>(
+    config: &Config,
+    state: &mut Incremental,
+    build: impl FnOnce(&mut Incremental, &Output) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let output = match &state.memory {
+        Some(memory) => Output::in_memory(&config.build, memory)?,
+        None => Output::stage(&config.build)?,
+    };
+    match build(state, &output) {
+        Ok(value) => {
+            output.commit()?;
+            Ok(value)
+        }
+        Err(e) => {
+            output.abandon();
+            // The state may describe pages that were never committed.
+            state.reset();
+            Err(e)
+        }
+    }
+}
+
 // Process all files in the HTML directory
-pub(crate) fn process_all_files(args: &Options, inject_reload: bool) -> Result<(), Error> {
-    // Clear build directory
-    let _ = fs_err::remove_dir_all(&args.build);
-    fs_err::create_dir_all(&args.build)?;
+pub(crate) fn process_all_files(
+    config: &Config,
+    options: BuildOptions,
+    state: &mut Incremental,
+    plugins: &Plugins,
+) -> Result<(), Error> {
+    staged(config, state, |state, output| {
+        build_all(config, options, state, plugins, output)
+    })
+}
+
+#[tracing::instrument(name = "build", skip_all)]
+fn build_all(
+    config: &Config,
+    options: BuildOptions,
+    state: &mut Incremental,
+    plugins: &Plugins,
+    output: &Output,
+) -> Result<(), Error> {
+    state.reset();
+    let ignore = config::source_matcher(std::env::current_dir()?)?;
+    let timings = Timings::new(options.timings);
 
     // Copy static files to build directory
-    copy_dir_all(&args.static_dir, &args.build)?;
+    info_span!("static").in_scope(|| {
+        timings.time("static copy", || {
+            copy_dir_all(
+                &config.static_dir,
+                output.root(),
+                output,
+                config,
+                options,
+                &ignore,
+            )
+        })
+    })?;
+
+    if let Some(favicon) = &config.favicon {
+        info_span!("favicon").in_scope(|| favicon::generate(favicon, output))?;
+    }
 
     // Process HTML files
-    process_site(&args.site, &args.build)?;
+    process_site(config, options, state, None, output, plugins, &timings)?;
 
-    // Inject hot reload script into all HTML files in build directory
-    if inject_reload {
-        inject_hot_reload_into_build_dir(&args.build)?;
+    if options.release {
+        if let Some(purge) = &config.purge {
+            info_span!("purge").in_scope(|| purge::run(purge, output))?;
+        }
+        info_span!("fingerprint").in_scope(|| fingerprint::fingerprint(output))?;
     }
-    inject_css_into_build_dir(&args.build)?;
+
+    // Anything not produced by this build is left over from an earlier one.
+    let removed = output.prune()?;
+    debug!(removed, "Pruned files left over from earlier builds");
+
+    timings.print();
 
     Ok(())
 }
 
-// Helper function to recursively copy directories
-fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result<()> {
-    fs_err::create_dir_all(&dst)?;
+/// Rebuild only the pages affected by the `changed` files, falling back
+/// to a full build when a change can't be traced to specific pages.
+///
+/// Returns how open pages should pick up the changes.
+pub(crate) fn rebuild(
+    config: &Config,
+    options: BuildOptions,
+    state: &mut Incremental,
+    changed: &[PathBuf],
+    plugins: &Plugins,
+) -> Result<Refresh, Error> {
+    staged(config, state, |state, output| {
+        rebuild_into(config, options, state, changed, plugins, output)
+    })
+}
+
+#[tracing::instrument(name = "rebuild", skip_all, fields(changed = changed.len()))]
+fn rebuild_into(
+    config: &Config,
+    options: BuildOptions,
+    state: &mut Incremental,
+    changed: &[PathBuf],
+    plugins: &Plugins,
+    output: &Output,
+) -> Result<Refresh, Error> {
+    // Pages can swap in new styles without reloading, unless
+    // they link a stylesheet directly, as posts can.
+    let styles = changed.iter().all(|p| {
+        p.extension()
+            .is_some_and(|e| ["css", "scss", "sass"].iter().any(|s| e == *s))
+    });
+    let refresh = |dirty: bool| {
+        if styles && !dirty {
+            Refresh::Styles
+        } else {
+            Refresh::Reload
+        }
+    };
+
+    if let Some(favicon) = &config.favicon {
+        let favicon = std::path::absolute(favicon)?;
+        if changed
+            .iter()
+            .any(|p| std::path::absolute(p).is_ok_and(|p| p == favicon))
+        {
+            favicon::generate(&favicon, output)?;
+        }
+    }
+
+    // Static files map directly onto the build directory, so they're handled one by one.
+    let static_dir = std::path::absolute(&config.static_dir)?;
+    let mut sources = Vec::new();
+    // Static pages can use Tailwind utilities too.
+    let mut restyle = false;
+    for path in changed {
+        let path = std::path::absolute(path)?;
+        match path.strip_prefix(&static_dir) {
+            Ok(relative) => {
+                restyle |=
+                    config.tailwind.is_some() && path.extension().is_some_and(|e| e == "html");
+
+                let target = output.root().join(relative);
+                if path.is_dir() {
+                    let ignore = config::source_matcher(std::env::current_dir()?)?;
+                    copy_dir_all(&path, &target, output, config, options, &ignore)?;
+                } else if path.exists() {
+                    copy_static(&path, &target, output, config, options)?;
+                    // Pages may also depend on it through `<Image>`.
+                    let canonical = path.canonicalize()?;
+                    if state
+                        .graph
+                        .invalidate(std::slice::from_ref(&canonical))
+                        .is_some_and(|dirty| !dirty.is_empty())
+                    {
+                        sources.push(canonical);
+                    }
+                } else {
+                    output.remove(&target)?;
+                }
+            }
+            Err(_) => sources.push(path),
+        }
+    }
+    if sources.is_empty() && !restyle {
+        return Ok(refresh(false));
+    }
+
+    let sources: Option<Vec<_>> = sources.iter().map(|p| p.canonicalize().ok()).collect();
+    let Some(dirty) = sources.and_then(|c| state.graph.invalidate(&c)) else {
+        build_all(config, options, state, plugins, output)?;
+        return Ok(Refresh::Reload);
+    };
+
+    let timings = Timings::new(options.timings);
+    process_site(
+        config,
+        options,
+        state,
+        Some(&dirty),
+        output,
+        plugins,
+        &timings,
+    )?;
+    timings.print();
+    Ok(refresh(!dirty.is_empty()))
+}
 
+// Helper function to recursively copy directories
+fn copy_dir_all(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    output: &Output,
+    config: &Config,
+    options: BuildOptions,
+    ignore: &Gitignore,
+) -> std::io::Result<()> {
     let Ok(entries) = fs_err::read_dir(src.as_ref()) else {
         return Ok(());
     };
@@ -41,28 +421,81 @@ fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result
     for entry in entries {
         let entry = entry?;
         let ty = entry.file_type()?;
+        let path = entry.path();
+        if is_ignored(ignore, &path) {
+            continue;
+        }
+
+        let target = dst.as_ref().join(entry.file_name());
         if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            copy_dir_all(path, target, output, config, options, ignore)?;
         } else {
-            fs_err::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            copy_static(&path, &target, output, config, options)?;
         }
     }
     Ok(())
 }
 
-// Process HTML files (placeholder - implement your preprocessor here)
-fn process_site(src_dir: &str, build_dir: &str) -> Result<(), Error> {
-    let src_dir = Path::new(src_dir);
-    let build_dir = Path::new(build_dir);
-    let mut combined_css = Vec::new();
+fn copy_static(
+    path: &Path,
+    target: &Path,
+    output: &Output,
+    config: &Config,
+    options: BuildOptions,
+) -> std::io::Result<()> {
+    if path.extension().and_then(|s| s.to_str()) == Some("html") {
+        let content = fs_err::read_to_string(path)?;
+        output.write(target, inject(content, config, options))?;
+    } else {
+        output.copy(path, target)?;
+    }
+    Ok(())
+}
+
+/// Render the site's pages, posts, and styles into `output`.
+///
+/// When `dirty` is given, only those pages (by canonical source path)
+/// and pages whose generated markup changed are expanded again.
+#[tracing::instrument(name = "site", skip_all, fields(incremental = dirty.is_some()))]
+fn process_site(
+    config: &Config,
+    options: BuildOptions,
+    state: &mut Incremental,
+    mut dirty: Option<&HashSet<PathBuf>>,
+    output: &Output,
+    plugins: &Plugins,
+    timings: &Timings,
+) -> Result<(), Error> {
+    let src_dir = Path::new(&config.site);
+    let build_dir = output.root();
+    let mut diagnostics = Diagnostics::new(options.keep_going);
+    let ignore = config::source_matcher(std::env::current_dir()?)?;
+    let packages = load_packages(config)?;
+
+    // Package styles come first so the site's own CSS can override them.
+    let mut combined_css: Vec<u8> = packages
+        .iter()
+        .flat_map(|p| p.css.iter().copied())
+        .collect();
+    let mut css_entries = Vec::new();
 
     let start = std::time::Instant::now();
 
+    let data = data::load(Path::new(&config.data))?;
+    let hooks = Hooks::load(src_dir)?;
+    let cache = if options.no_cache {
+        Cache::disabled()
+    } else {
+        Cache::new(cache::DIR)
+    };
+
     // pass one
     let mut component_entries = Vec::new();
     let mut markdown_entries = Vec::new();
-    for entry in walkdir::WalkDir::new(src_dir)
+    for entry in options
+        .walk(src_dir)
         .into_iter()
+        .filter_entry(|e| !is_ignored(&ignore, e.path()))
         .filter_map(|f| match f {
             Ok(f) => (!f.path().is_dir()).then_some(f),
             _ => None,
@@ -73,8 +506,11 @@ fn process_site(src_dir: &str, build_dir: &str) -> Result<(), Error> {
 
         if path_string.ends_with(".mod.html") {
             component_entries.push(entry);
-        } else if path_string.ends_with(".css") {
-            combined_css.extend(fs_err::read(path)?);
+        } else if [".css", ".scss", ".sass"]
+            .iter()
+            .any(|e| path_string.ends_with(e))
+        {
+            css_entries.push(entry);
         } else if path_string.ends_with(".md") {
             markdown_entries.push(entry);
         }
@@ -84,21 +520,107 @@ fn process_site(src_dir: &str, build_dir: &str) -> Result<(), Error> {
 
     let components = component_entries
         .into_par_iter()
-        .map(|entry| fs_err::read_to_string(entry.path()))
-        .collect::<Result<Vec<_>, _>>()?;
+        .map(|entry| {
+            let relative = entry.path().strip_prefix(src_dir)?;
+            let namespace = config.components.namespace(relative);
+            // Fragments, which have no declaration, are named after their file.
+            let file_name = entry.file_name().to_string_lossy();
+            let fragment_name = file_name
+                .trim_end_matches(".mod.html")
+                .to_case(Case::Pascal);
+            Ok((
+                entry.path().to_owned(),
+                entry.path().canonicalize()?,
+                namespace,
+                fragment_name,
+                fs_err::read_to_string(entry.path())?,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let cached = &state.components;
+    let parsed = components
+        .into_par_iter()
+        .map(|(source, path, namespace, fragment_name, c)| {
+            let hash = blake3::hash(c.as_bytes());
+            let c = match cached.get(&path) {
+                Some((cached, component)) if *cached == hash => Arc::clone(component),
+                _ => {
+                    let start = std::time::Instant::now();
+                    // Components are declared by a single capitalized element,
+                    // like `<Card title>…</Card>`, and anything else is a fragment.
+                    let component = if declares_component(&c) {
+                        wincomp::Component::new(&c)
+                    } else {
+                        wincomp::Component::fragment(&fragment_name, &c)
+                    }
+                    .map_err(|e| parse_error(&source, &c, e.offset(), e.inner()))?;
+                    timings.record("component parse", Some(&source), start.elapsed());
+                    Arc::new(component.into_owned())
+                }
+            };
+            let name = match namespace {
+                Some(namespace) => {
+                    format!("{namespace}{}{}", config.components.separator, c.root.name)
+                }
+                None => c.root.name.to_string(),
+            };
+            Ok((path, name, hash, c))
+        })
+        .collect::<Vec<Result<_, Error>>>();
+    let parsed = diagnostics.collect(parsed)?;
+    state.components = parsed
+        .iter()
+        .map(|(path, _, hash, c)| (path.clone(), (*hash, Arc::clone(c))))
+        .collect();
 
-    let result = components
-        .par_iter()
-        .map(|c| wincomp::Component::new(c).map(|c| (c.root.name, c)))
-        .collect::<Result<HashMap<_, _>, _>>();
+    // A new or renamed component could be used by any page.
+    let names = parsed
+        .iter()
+        .map(|(path, name, _, _)| (path.clone(), name.clone()))
+        .collect();
+    if state.graph.set_components(names) {
+        dirty = None;
+    }
+    let is_dirty = |key: &Path| dirty.is_none_or(|d| d.contains(key));
 
-    let components = match result {
-        Ok(c) => c,
-        Err(e) => bail!("Error processing components: {e}"),
-    };
+    let mut components: HashMap<_, _> = parsed
+        .into_iter()
+        .map(|(_, name, _, c)| (Cow::Owned(name), c))
+        .collect();
+
+    // Site components take precedence over packaged ones.
+    for package in &packages {
+        for raw in &package.components {
+            let component = match wincomp::Component::new(raw) {
+                Ok(c) => c,
+                Err(e) => bail!(
+                    "Error processing components in package {}: {e}",
+                    package.manifest.name
+                ),
+            };
+            // Packages' components are always reachable with their prefix,
+            // like `<pkg:Button>`, even when a site component shadows them.
+            let prefixed = format!("{}:{}", package.manifest.name, component.root.name);
+            let component = Arc::new(component);
+            components.insert(Cow::Owned(prefixed), Arc::clone(&component));
+            components
+                .entry(component.root.name.clone())
+                .or_insert(component);
+        }
+    }
+    // Plugins change copies of their own, while everything else shares
+    // the components kept between builds.
+    if !plugins.is_empty() {
+        for (name, component) in &mut components {
+            plugins.component_loaded(name, Arc::make_mut(component))?;
+        }
+    }
 
-    let mut paths: Vec<_> = walkdir::WalkDir::new(src_dir)
+    let paths: Vec<_> = options
+        .walk(src_dir)
         .into_iter()
+        .filter_entry(|e| !is_ignored(&ignore, e.path()))
         .filter_map(|f| match f {
             Ok(f) => {
                 if f.path().is_dir() {
@@ -116,188 +638,1055 @@ fn process_site(src_dir: &str, build_dir: &str) -> Result<(), Error> {
         })
         .collect();
 
-    let blog_build_dir = build_dir.join("blog-build");
-    let mut articles = Vec::new();
-    markdown_entries
+    // Pages to expand, along with their keys in the dependency graph.
+    let mut pages = Vec::new();
+    let mut sitemap_entries = Vec::new();
+    for path in paths {
+        sitemap_entries.push(Entry {
+            path: sitemap::page_url(path.strip_prefix(src_dir)?),
+            lastmod: options.modified(&path)?,
+            ..Default::default()
+        });
+
+        let key = path.canonicalize()?;
+        if is_dirty(&key) {
+            pages.push(Pending {
+                outpath: path.strip_prefix(src_dir)?.to_owned(),
+                key,
+                markup: Markup::Source(path),
+            });
+        }
+    }
+
+    // Files pulled in by `{{ include }}` are fragments rather than pages.
+    let markdown_entries = markdown_entries
         .into_iter()
-        .map(|entry| {
-            let path = entry.path();
+        .map(|entry| Ok((include::resolve(entry.path(), src_dir)?, entry)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut included = HashMap::default();
+    for (resolved, _) in &markdown_entries {
+        for dependency in &resolved.dependencies {
+            included
+                .entry(dependency.clone())
+                .or_insert_with(|| resolved.path.clone());
+        }
+    }
+
+    // A post can't be one, since it would vanish from the blog while its
+    // frontmatter landed in the middle of the page including it.
+    let included_posts = markdown_entries.iter().filter_map(|(resolved, _)| {
+        let includer = included.get(&resolved.path)?;
+        let frontmatter = markcomp::pull::frontmatter(&resolved.source);
+        matches!(frontmatter, Ok(Some(_))).then(|| {
+            let message = format!("Has frontmatter, but is included by {includer:?}");
+            Err(Diagnostic::error(&resolved.path, message).into())
+        })
+    });
+    diagnostics.collect::<()>(included_posts)?;
 
-            let trimmed_entry = path.strip_prefix(src_dir)?;
-            let outpath = blog_build_dir.join(trimmed_entry);
+    let locales = Locales::new(&config.languages);
+    let linter = config.lint.as_ref().map(Linter::new).transpose()?;
+    let mut lints = Vec::new();
 
-            let base = outpath
-                .parent()
-                .ok_or(anyhow!("Blog file has no parent path"))?;
-            let sans_extension = outpath
+    let article_template = config.components.blog_article_template;
+
+    // Only posts under `blog/` join the blog; other markdown becomes a standalone page.
+    let blog_dir = src_dir.join("blog");
+    let (markdown_entries, markdown_pages): (Vec<_>, Vec<_>) = markdown_entries
+        .into_iter()
+        .filter(|(resolved, _)| !included.contains_key(&resolved.path))
+        .partition(|(_, entry)| entry.path().starts_with(&blog_dir));
+
+    let mut rendered_pages = Vec::new();
+    for (resolved, entry) in markdown_pages {
+        let path = entry.path();
+        let outpath = markdown_page_path(path, src_dir)?;
+        let entry = Entry {
+            path: sitemap::page_url(&outpath),
+            lastmod: options.modified(path)?,
+            ..Default::default()
+        };
+        let frontmatter = markcomp::pull::frontmatter(&resolved.source)
+            .map_err(|e| frontmatter_error(path, e))?;
+        sitemap_entries.push(match frontmatter {
+            Some(frontmatter) => entry
+                .with_frontmatter(&frontmatter)
+                .map_err(|e| Diagnostic::error(path, e.to_string()))?,
+            None => entry,
+        });
+
+        if is_dirty(&resolved.path) {
+            let start = std::time::Instant::now();
+            let markup = render_markdown_page(path, &resolved.source, &cache);
+            timings.record("markdown render", Some(path), start.elapsed());
+            rendered_pages.push(markup.map(|markup| {
+                let dependencies = resolved.dependencies.into_iter().collect();
+                state.graph.page(resolved.path.clone()).files = dependencies;
+                Pending {
+                    outpath,
+                    key: resolved.path,
+                    markup: Markup::Generated(markup),
+                }
+            }));
+        }
+    }
+    pages.extend(diagnostics.collect(rendered_pages)?);
+
+    let articles = markdown_entries
+        .into_iter()
+        .map(|(resolved, entry)| {
+            if !is_dirty(&resolved.path) {
+                if let Some(article) = state.articles.get(&resolved.path) {
+                    return Ok((article.clone(), false));
+                }
+            }
+
+            let path = entry.path();
+            let start = std::time::Instant::now();
+
+            let sans_extension = path
                 .file_stem()
                 .ok_or(anyhow!("Blog file has no file stem"))?;
-            let outpath = base.join(sans_extension).join("index.html");
-            paths.push(outpath.to_owned());
 
-            if let Some(path) = outpath.parent() {
-                fs_err::create_dir_all(path)?;
-            }
+            let mut dependencies: HashSet<_> = resolved.dependencies.into_iter().collect();
 
-            let markdown = fs_err::read_to_string(path)?;
-            let mut output = Vec::new();
-            let mut markdown = markcomp::pull::Writer::new(&markdown)?;
+            let markdown = resolved.source;
+            let mut markdown = cache
+                .markdown(&markdown)
+                .map_err(|e| frontmatter_error(path, e))?;
 
             let frontmatter = markdown
                 .frontmatter
                 .take()
-                .ok_or(anyhow!("Missing frontmatter in {path:?}"))?;
+                .ok_or_else(|| Diagnostic::error(path, "Missing frontmatter"))?;
 
-            let date = jiff::fmt::strtime::parse("%D", &frontmatter.date)?.to_date()?;
+            let date = jiff::fmt::strtime::parse("%D", &frontmatter.date)
+                .and_then(|d| d.to_date())
+                .map_err(|e| Diagnostic::error(path, format!("Invalid date: {e}")))?;
 
-            write!(
-                &mut output,
-                r#"<html lang="en"><ShellHead><title>{} | Corvus Prudens</title></ShellHead><ShellBody><article>"#,
-                frontmatter.title
-            )?;
+            if frontmatter.series.is_some() != frontmatter.part.is_some() {
+                return Err(Diagnostic::error(
+                    path,
+                    "Frontmatter must declare both `series` and `part`, or neither",
+                )
+                .into());
+            }
+
+            let lang = match &frontmatter.lang {
+                Some(lang) => locales
+                    .validate(lang)
+                    .map_err(|e| Diagnostic::error(path, format!("Invalid language: {e}")))?,
+                None => locales.default_language(),
+            };
+            let prefix = locales.prefix(lang);
+            let pattern = frontmatter.permalink.as_deref().unwrap_or(&config.permalink);
+            let slug = sans_extension.to_string_lossy();
+            let url = permalink::expand(pattern, date, &slug)
+                .map_err(|e| Diagnostic::error(path, format!("Invalid permalink: {e}")))?;
+            let url = format!("{prefix}{url}");
+            let outpath = Path::new(url.trim_matches('/')).join("index.html");
+
+            let source_dir = path.parent().unwrap_or(src_dir);
+            let mut head_assets = String::new();
+            if let Some(structured) = &config.structured_data {
+                head_assets.push_str(&json_ld(config, structured, &frontmatter, date, &url)?);
+            }
+            for css in &frontmatter.css {
+                let css = source_dir.join(css);
+                let url = assets::hashed_url(&css)?;
+                dependencies.insert(css.canonicalize()?);
+                head_assets.push_str(&format!(r#"<link rel="stylesheet" href="{url}" />"#));
+            }
+            let mut body_assets = String::new();
+            for script in &frontmatter.scripts {
+                let script = source_dir.join(script);
+                let url = assets::hashed_url(&script)?;
+                dependencies.insert(script.canonicalize()?);
+                body_assets.push_str(&format!(r#"<script src="{url}" defer></script>"#));
+            }
+
+            let attributes = format!(
+                r#"title="{}" description="{}" date="{}""#,
+                escape_html(&frontmatter.title),
+                escape_html(frontmatter.description.trim()),
+                escape_html(&frontmatter.date),
+            );
+
+            let words = markdown.word_count();
+            let minutes = markdown.reading_time();
+            let excerpt = markdown.excerpt_html();
+
+            let draft = if frontmatter.draft {
+                "<DraftBanner></DraftBanner>"
+            } else {
+                ""
+            };
+            let (head, tail) = if article_template {
+                // Stylesheet links and JSON-LD are both allowed in the body,
+                // so the template needn't know where the head is.
+                (
+                    format!(
+                        r#"<BlogArticle {attributes} href="{}" minutes="{minutes}" words="{words}">{head_assets}{draft}"#,
+                        escape_html(&url),
+                    ),
+                    format!("{body_assets}</BlogArticle>"),
+                )
+            } else {
+                (
+                    format!(
+                        r#"<html lang="{lang}"><ShellHead {attributes}>{head_assets}</ShellHead><ShellBody {attributes}><article>{draft}<ArticleMeta minutes="{minutes}" words="{words}">{minutes} min read</ArticleMeta>"#,
+                    ),
+                    format!("</article>{body_assets}</ShellBody></html>"),
+                )
+            };
+
+            let body = markdown.output();
+
+            if let Some(linter) = &linter {
+                lints.extend(linter.check(path, &frontmatter, &body)?);
+            }
 
-            articles.push((
+            let content = match &config.feed {
+                Some(feed) if feed.full_content => Some(render_fragment(&body, &components, &data)?),
+                _ => None,
+            };
+
+            let article = Article {
+                source: resolved.path,
+                dependencies,
                 date,
-                sans_extension.to_string_lossy().to_string(),
+                url,
+                prefix,
+                source_dir: source_dir.to_owned(),
                 frontmatter,
-            ));
-            let mut markdown = markdown.output();
+                outpath,
+                head,
+                body,
+                tail,
+                content,
+                words,
+                minutes,
+                excerpt,
+            };
+            timings.record("markdown render", Some(path), start.elapsed());
+            Ok((article, true))
+        })
+        .collect::<Vec<_>>();
+
+    let mut rendered = HashSet::default();
+    let articles: Vec<_> = diagnostics
+        .collect(articles)?
+        .into_iter()
+        .map(|(article, fresh)| {
+            if fresh {
+                state.graph.page(article.source.clone()).files = article.dependencies.clone();
+                state
+                    .articles
+                    .insert(article.source.clone(), article.clone());
+                rendered.insert(article.source.clone());
+            }
+            article
+        })
+        .collect();
+
+    for lint in lints {
+        diagnostics.push(Diagnostic::warning(lint.path, lint.message));
+    }
+
+    // Stylesheets claimed by individual posts stay out of the combined CSS.
+    let post_css: HashSet<_> = articles
+        .iter()
+        .flat_map(|a| a.frontmatter.css.iter().map(|css| a.source_dir.join(css)))
+        .filter_map(|path| path.canonicalize().ok())
+        .collect();
+    // Tailwind's input is compiled separately rather than included as is.
+    let tailwind_input = config
+        .tailwind
+        .as_ref()
+        .and_then(|t| t.input.as_ref())
+        .and_then(|input| input.canonicalize().ok());
+    let mut stylesheets = HashSet::default();
+    let styles = std::time::Instant::now();
+    for entry in css_entries {
+        let path = entry.path().canonicalize()?;
+        if post_css.contains(&path) || tailwind_input.as_ref() == Some(&path) {
+            continue;
+        }
 
-            output.append(&mut markdown);
-            write!(&mut output, "</article></ShellBody></html>")?;
-            fs_err::write(outpath, output)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("scss" | "sass") => {
+                // Partials are only compiled through the sheets importing them.
+                if !entry.file_name().to_string_lossy().starts_with('_') {
+                    let css = grass::from_path(entry.path(), &grass::Options::default())
+                        .map_err(|e| Diagnostic::error(entry.path(), e.to_string()))?;
+                    combined_css.extend(css.as_bytes());
+                }
+            }
+            _ => combined_css.extend(fs_err::read(entry.path())?),
+        }
+        stylesheets.insert(path);
+    }
+    state.graph.set_stylesheets(stylesheets);
 
-            Ok(())
+    // Components sharing the same styles share a scope, so each is only included once.
+    let scoped: BTreeMap<_, _> = components
+        .values()
+        .filter_map(|c| c.style.as_ref())
+        .map(|(css, class)| (class, css))
+        .collect();
+    for css in scoped.into_values() {
+        combined_css.push(b'\n');
+        combined_css.extend(css.as_bytes());
+    }
+    timings.record("styles", None, styles.elapsed());
+
+    let today = match config.include_future {
+        false => Some(match options.source_date()? {
+            Some(date) => date.to_zoned(jiff::tz::TimeZone::UTC).date(),
+            None => jiff::Zoned::now().date(),
+        }),
+        true => None,
+    };
+    let scheduled = |a: &Article| today.is_some_and(|today| a.date > today);
+    let hidden = |a: &Article| !config.drafts && a.frontmatter.draft;
+
+    // Series are linked before scheduled posts and drafts are left out,
+    // so the published parts keep their numbers.
+    let parts: Vec<_> = articles
+        .iter()
+        .map(|article| {
+            Some(series::Part {
+                series: article.frontmatter.series.as_deref()?,
+                part: article.frontmatter.part?,
+                title: &article.frontmatter.title,
+                url: article.url(),
+                published: !scheduled(article) && !hidden(article),
+            })
         })
-        .collect::<Result<Vec<_>, Error>>()?;
+        .collect();
+    let series = series::link(&parts)?;
+    let (later, mut linked): (Vec<_>, Vec<_>) = articles
+        .into_iter()
+        .zip(series)
+        .partition(|(article, _)| scheduled(article));
+    let later: Vec<_> = later.into_iter().map(|(article, _)| article).collect();
+    report_scheduled(&later);
+
+    let count = linked.len();
+    linked.retain(|(article, _)| !hidden(article));
+    if linked.len() < count {
+        info!("Skipping {} draft(s)", count - linked.len());
+    }
+
+    // Only posts that are published bring their stylesheets and scripts.
+    for (article, _) in &linked {
+        let frontmatter = &article.frontmatter;
+        for asset in frontmatter.css.iter().chain(&frontmatter.scripts) {
+            assets::copy_hashed(&article.source_dir.join(asset), output)?;
+        }
+    }
+
+    let mut permalinks = HashSet::default();
+    for (article, _) in &linked {
+        if !permalinks.insert(&article.url) {
+            bail!(
+                "{:?} shares its permalink {:?} with another post",
+                article.source,
+                article.url
+            );
+        }
+    }
+
+    let mut articles = Vec::with_capacity(linked.len());
+    for (article, series) in linked {
+        let mut markup = article.head.clone();
+        markup.push_str(&series.index);
+        markup.push_str(std::str::from_utf8(&article.body)?);
+        markup.push_str(&series.nav);
+        markup.push_str(&article.tail);
+
+        // Untouched posts still change when another part of their series does.
+        let changed = state.generate(&article.outpath, &markup);
+        if changed || rendered.contains(&article.source) {
+            pages.push(Pending {
+                outpath: article.outpath.clone(),
+                key: article.source.clone(),
+                markup: Markup::Generated(markup),
+            });
+        }
+        articles.push(article);
+    }
 
     // Create blog index
-    articles.sort_by_key(|s| std::cmp::Reverse(s.0));
-    let path = blog_build_dir.join("blog").join("index.html");
-    let data = format!(
-        "<BlogShell>{}</BlogShell>",
+    articles.sort_by_key(|a| std::cmp::Reverse(a.date));
+    let card_template = config.components.blog_card_template;
+
+    // Each language gets its own index, like `/blog/` and `/de/blog/`.
+    let mut by_language: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    by_language.insert(String::new(), Vec::new());
+    for article in &articles {
+        by_language
+            .entry(article.prefix.clone())
+            .or_default()
+            .push(article);
+    }
+
+    let mut listings = Vec::new();
+    for (prefix, articles) in by_language {
+        let blog = format!("{prefix}/blog");
+        sitemap_entries.push(Entry {
+            path: format!("{blog}/"),
+            lastmod: articles.first().map(|a| a.date),
+            ..Default::default()
+        });
+        listings.push((
+            PathBuf::from(blog.trim_start_matches('/')),
+            blog_listing(None, articles.iter().copied(), card_template),
+        ));
+
+        // Yearly and monthly archives, like `/blog/2024/` and `/blog/2024/05/`.
+        let mut archives: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for article in &articles {
+            let (year, month) = (article.date.year(), article.date.month());
+            archives.entry((year, None)).or_default().push(*article);
+            archives
+                .entry((year, Some(month)))
+                .or_default()
+                .push(*article);
+        }
+        for ((year, month), archived) in archives {
+            let (dir, heading) = match month {
+                Some(month) => (
+                    format!("{year}/{month:02}"),
+                    jiff::fmt::strtime::format("%B %Y", archived[0].date)?,
+                ),
+                None => (year.to_string(), year.to_string()),
+            };
+
+            sitemap_entries.push(Entry {
+                path: format!("{blog}/{dir}/"),
+                lastmod: Some(archived[0].date),
+                ..Default::default()
+            });
+            listings.push((
+                Path::new(blog.trim_start_matches('/')).join(dir),
+                blog_listing(Some(&heading), archived, card_template),
+            ));
+        }
+    }
+
+    for (dir, markup) in listings {
+        let outpath = dir.join("index.html");
+        // Staged builds alternate directories, so keys use where the page ends up.
+        let key = std::path::absolute(output.logical(&build_dir.join(&outpath)))?;
+        if state.generate(&outpath, &markup) || is_dirty(&key) {
+            pages.push(Pending {
+                outpath,
+                key,
+                markup: Markup::Generated(markup),
+            });
+        }
+    }
+
+    if let Some(feed) = &config.feed {
+        let base_url = config.base_url.as_deref().ok_or(anyhow!(
+            "Generating a feed requires `base_url` to be configured"
+        ))?;
+
+        // Feeds follow the default language.
+        let articles: Vec<_> = articles.iter().filter(|a| a.prefix.is_empty()).collect();
+        let urls: Vec<_> = articles.iter().map(|a| a.url()).collect();
+        let items: Vec<_> = articles
+            .iter()
+            .zip(&urls)
+            .map(|(article, url)| FeedItem {
+                title: &article.frontmatter.title,
+                description: &article.frontmatter.description,
+                date: article.date,
+                path: url,
+                content: article.content.as_deref(),
+            })
+            .collect();
+
+        output.write(
+            build_dir.join("feed.xml"),
+            feed::rss(feed, base_url, &items)?,
+        )?;
+        output.write(
+            build_dir.join("feed.json"),
+            feed::json(feed, base_url, &items)?,
+        )?;
+    }
+
+    let redirects: BTreeMap<_, _> = config
+        .redirects
+        .iter()
+        .map(|(from, to)| (from.clone(), to.clone()))
+        .chain(articles.iter().flat_map(|article| {
+            let url = article.url();
+            article
+                .frontmatter
+                .redirect_from
+                .iter()
+                .map(move |from| (from.clone(), url.clone()))
+        }))
+        .collect();
+    if !redirects.is_empty() {
+        for (from, to) in &redirects {
+            if !from.starts_with('/') {
+                bail!("Redirect from {from:?} must be an absolute path");
+            }
+            // A stub written over a real page would hide it.
+            let url = sitemap::page_url(&redirects::stub_path(from));
+            if sitemap_entries.iter().any(|e| e.path == url)
+                || articles.iter().any(|a| a.url() == url)
+            {
+                bail!("Redirect from {from:?} would replace the page at {url}");
+            }
+            output.write(
+                build_dir.join(redirects::stub_path(from)),
+                redirects::stub(to),
+            )?;
+        }
+        output.write(
+            build_dir.join(redirects::FILE),
+            redirects::rules(redirects.iter().map(|(f, t)| (f.as_str(), t.as_str()))),
+        )?;
+    }
+
+    for article in &articles {
+        let entry = Entry {
+            path: article.url(),
+            lastmod: Some(article.date),
+            ..Default::default()
+        };
+        sitemap_entries.push(
+            entry
+                .with_frontmatter(&article.frontmatter)
+                .map_err(|e| Diagnostic::error(&article.source, e.to_string()))?,
+        );
+    }
+    sitemap_entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    // Every page's URL, for finding translations.
+    let known: HashSet<_> = sitemap_entries.iter().map(|e| e.path.clone()).collect();
+
+    if let Some(base_url) = &config.base_url {
+        output.write(
+            build_dir.join("sitemap.xml"),
+            sitemap::sitemap(base_url, &sitemap_entries),
+        )?;
+    }
+
+    let images = config
+        .images
+        .as_ref()
+        .map(|c| Images::new(c, &config.static_dir, output));
+
+    let transforms = plugins.transforms();
+    let results = pages
+        .par_iter()
+        .map(|pending| {
+            let start = std::time::Instant::now();
+            let file;
+            // The page's own markup, which its diagnostics point into.
+            let source;
+            // What `<Include>` and `<Markdown>` pull in, which the document borrows from.
+            let included;
+            let mut warnings = Vec::new();
+            let mut document =
+                match &pending.markup {
+                    Markup::Source(path) if options.lenient => {
+                        file = fs_err::read_to_string(path)?;
+                        source = Some(file.as_str());
+                        let (document, recovered) = wincomp::Document::lenient(&file);
+                        warnings.extend(recovered.into_iter().map(|r| {
+                            Diagnostic::warning(path, r.message).at_offset(&file, r.offset)
+                        }));
+                        document
+                    }
+                    Markup::Source(path) => {
+                        file = fs_err::read_to_string(path)?;
+                        source = Some(file.as_str());
+                        wincomp::Document::new(&file)
+                            .map_err(|e| parse_error(path, &file, e.offset(), e.inner()))?
+                    }
+                    // Offsets into generated markup wouldn't mean much in the source.
+                    Markup::Generated(markup) => {
+                        source = None;
+                        wincomp::Document::new(markup)
+                            .map_err(|e| Diagnostic::error(&pending.key, e.inner().to_string()))?
+                    }
+                };
+
+            let mut urls = HashMap::default();
+            let mut sources = Vec::new();
+            if let Some(images) = &images {
+                for src in images::sources(&mut document) {
+                    if let Some(source) = images.source(&src) {
+                        urls.insert(src, images.optimize(&source)?);
+                        sources.push(source.canonicalize()?);
+                    }
+                }
+            }
+            let sizes = config.images.as_ref().map_or("", |c| c.sizes.as_str());
+            images::rewrite(&mut document, &urls, sizes);
+
+            let mut used = HashSet::default();
+            expand(&mut document, &components, &data, &mut used)
+                .map_err(|e| Diagnostic::error(&pending.key, e.to_string()))?;
+
+            // Components may include files too, so these are spliced in after
+            // expansion, and then whatever markdown brought in is expanded.
+            let includes = include::sources(&document);
+            if !includes.is_empty() {
+                included = include::read(src_dir, includes, &cache)
+                    .map_err(|e| Diagnostic::error(&pending.key, e.to_string()))?;
+                sources.extend(included.values().filter_map(|file| file.path.clone()));
+                include::splice(&mut document, &included)
+                    .map_err(|e| Diagnostic::error(&pending.key, e.to_string()))?;
+                expand(&mut document, &components, &data, &mut used)
+                    .map_err(|e| Diagnostic::error(&pending.key, e.to_string()))?;
+            }
+            for name in document.unresolved() {
+                // Left as a bogus tag, which is almost always a typo or a missing component.
+                let warning = Diagnostic::warning(
+                    &pending.key,
+                    format!("<{name}> doesn't match any component"),
+                );
+                // Where the page itself uses it, rather than a component.
+                let location = source.and_then(|source| {
+                    let element = document
+                        .elements()
+                        .find(|e| e.name == name && e.offset.is_some())?;
+                    Some(diagnostics::location(source, element.offset?))
+                });
+                warnings.push(at(warning, location));
+            }
+
+            let trimmed_entry = pending.outpath.as_path();
+            let outpath = build_dir.join(trimmed_entry);
+            let url = sitemap::page_url(trimmed_entry);
+
+            let mut document = match &hooks {
+                Some(hooks) => hooks.run(&url, &document)?,
+                None => document,
+            };
+            let mut alternates = String::new();
+            if locales.enabled() {
+                let (lang, _) = locales.split(&url);
+                i18n::set_lang(&mut document, lang);
+                alternates = locales.alternates(
+                    &url,
+                    &known,
+                    config.base_url.as_deref().unwrap_or_default(),
+                );
+            }
+            transforms.apply(&mut document);
+            let links = links::Page::collect(trimmed_entry.to_owned(), &document, source);
+
+            // Mirrors of the site should credit this copy.
+            let canonical = config
+                .base_url
+                .as_ref()
+                .map(|base_url| format!("{}{url}", base_url.trim_end_matches('/')));
+            if let Some(head) = document.find_mut(|e| e.name == "head") {
+                head.append_child(Node::Raw(head_links(config, options).into()));
+                if !alternates.is_empty() {
+                    head.append_child(Node::Raw(alternates.into()));
+                }
+                if let Some(canonical) = canonical {
+                    let mut link = Element::new("link");
+                    link.set_attribute("rel", Some("canonical".into()));
+                    link.set_attribute("href", Some(canonical.into()));
+                    head.append_child(Node::Element(link));
+                }
+            }
+            if options.inject_reload {
+                if let Some(body) = document.find_mut(|e| e.name == "body") {
+                    body.append_child(Node::Raw(live::SCRIPT.into()));
+                }
+            }
+
+            let mut buffer = Vec::new();
+            if options.pretty {
+                let pretty = wincomp::pretty::Pretty {
+                    comments: config.keep_comments,
+                    ..Default::default()
+                };
+                document.write_pretty(&mut buffer, &pretty)?;
+            } else if options.minify {
+                document.write_minified(&mut buffer)?;
+            } else {
+                let write = wincomp::WriteOptions {
+                    comments: config.keep_comments,
+                };
+                document.write_with(&mut buffer, &write)?;
+            }
+            let mut page = String::from_utf8(buffer)?;
+            plugins.page_rendered(&output.logical(&outpath), &mut page)?;
+            timings.record("expansion", Some(trimmed_entry), start.elapsed());
+            let start = std::time::Instant::now();
+            output.write(&outpath, page)?;
+            timings.record("write", Some(trimmed_entry), start.elapsed());
+
+            Ok((&pending.key, used, sources, links, warnings))
+        })
+        .collect::<Vec<_>>();
+
+    let mut checked = Vec::new();
+    for (key, used, sources, mut links, warnings) in diagnostics.collect(results)? {
+        for warning in warnings {
+            diagnostics.push(warning);
+        }
+        let page = state.graph.page(key.clone());
+        page.components = used;
+        page.files.extend(sources);
+
+        state
+            .anchors
+            .insert(links.path.clone(), std::mem::take(&mut links.ids));
+        checked.push((key.clone(), links));
+    }
+
+    // Tailwind scans the finished pages for the utilities they use.
+    if let Some(tailwind) = &config.tailwind {
+        let tailwind = info_span!("tailwind")
+            .in_scope(|| timings.time("tailwind", || tailwind::run(tailwind, build_dir)))?;
+        combined_css.extend(tailwind);
+    }
+    output.write(build_dir.join("output.css"), combined_css)?;
+    plugins.assets_written(output)?;
+
+    info_span!("links").in_scope(|| {
+        for (key, page) in &checked {
+            for link in links::check(output, page, &state.anchors) {
+                let warning = Diagnostic::warning(
+                    key,
+                    format!("Broken link to {:?}, {}", link.href, link.message),
+                );
+                diagnostics.push(at(warning, link.location));
+            }
+        }
+    });
+    // Only a full build looks up everything the site still needs.
+    if dirty.is_none() {
+        cache.collect_garbage()?;
+    }
+
+    let elapsed = std::time::Instant::now() - start;
+
+    let files = components.len() + pages.len();
+    info!(
+        files,
+        "Processed {files} files in {}us",
+        elapsed.as_micros()
+    );
+
+    diagnostics.finish(config.strict)
+}
+
+/// Point `diagnostic` at `location` in its page's source, if it has one.
+fn at(diagnostic: Diagnostic, location: Option<(usize, usize)>) -> Diagnostic {
+    match location {
+        Some((line, column)) => diagnostic.at(line, column),
+        None => diagnostic,
+    }
+}
+
+/// Where a markdown page outside the blog is served from, relative to the build
+/// directory. `about.md` and `about/index.md` both become `about/index.html`.
+fn markdown_page_path(path: &Path, src_dir: &Path) -> Result<PathBuf, Error> {
+    let relative = path.strip_prefix(src_dir)?.with_extension("");
+    let relative = match relative.file_name() {
+        Some(name) if name == "index" => relative.parent().unwrap_or(Path::new("")),
+        _ => &relative,
+    };
+    Ok(relative.join("index.html"))
+}
+
+/// Render a markdown page into a neutral layout, without any blog chrome.
+fn render_markdown_page(path: &Path, source: &str, cache: &Cache) -> Result<String, Error> {
+    let mut markdown = cache
+        .markdown(source)
+        .map_err(|e| frontmatter_error(path, e))?;
+
+    let attributes = match markdown.frontmatter.take() {
+        Some(frontmatter) => format!(
+            r#"title="{}" description="{}""#,
+            escape_html(&frontmatter.title),
+            escape_html(frontmatter.description.trim()),
+        ),
+        None => String::new(),
+    };
+
+    let mut page =
+        format!(r#"<html lang="en"><ShellHead {attributes}></ShellHead><ShellBody {attributes}>"#);
+    page.push_str(&String::from_utf8(markdown.output())?);
+    page.push_str("</ShellBody></html>");
+    Ok(page)
+}
+
+/// Whether `source` declares a component, like `<Card title>…</Card>`,
+/// rather than being a fragment. It's judged by the outermost tags alone,
+/// so a mistake between them is reported rather than read as a fragment.
+fn declares_component(source: &str) -> bool {
+    let source = source.trim();
+    let Some(rest) = source.strip_prefix('<') else {
+        return false;
+    };
+    let name = &rest[..rest
+        .find(|c: char| c.is_whitespace() || matches!(c, '>' | '/'))
+        .unwrap_or(rest.len())];
+    name.starts_with(char::is_uppercase) && source.ends_with(&format!("</{name}>"))
+}
+
+/// Point a markup parse error at where it happened in `source`.
+fn parse_error(path: &Path, source: &str, offset: usize, error: impl Display) -> Diagnostic {
+    // The context spans several lines, which would break up the summary table.
+    let message = error.to_string().replace('\n', ", ");
+    Diagnostic::error(path, message).at_offset(source, offset)
+}
+
+/// Point a frontmatter error at the markdown file it came from.
+fn frontmatter_error(path: &Path, error: markcomp::pull::FrontmatterError) -> Diagnostic {
+    let diagnostic = Diagnostic::error(path, error.to_string());
+    match error.location {
+        Some((line, column)) => diagnostic.at(line, column),
+        None => diagnostic,
+    }
+}
+
+/// Blog index markup with a card for each article.
+fn blog_listing<'a>(
+    heading: Option<&str>,
+    articles: impl IntoIterator<Item = &'a Article>,
+    template: bool,
+) -> String {
+    let heading = heading
+        .map(|h| format!(r#"<h2 class="archive-heading">{}</h2>"#, escape_html(h)))
+        .unwrap_or_default();
+
+    format!(
+        "<BlogShell>{heading}{}</BlogShell>",
         articles
             .into_iter()
-            .map(|(date, path, frontmatter)| {
+            .map(|article| {
+                let href = escape_html(&article.url());
+                let title = escape_html(&article.frontmatter.title);
+                let description = escape_html(&article.frontmatter.description);
+                let date = jiff::fmt::strtime::format("%D", article.date).unwrap();
+                let date = escape_html(&date);
+
+                if template {
+                    return format!(
+                        r#"<BlogCard href="{href}" title="{title}" description="{}" date="{date}" minutes="{}" words="{}">{}</BlogCard>"#,
+                        description.trim(),
+                        article.minutes,
+                        article.words,
+                        article.excerpt,
+                    );
+                }
+
                 format!(
                     r#"
                         <BlogCard>
                             <div class="title-items">
-                                <BlogLink href="/blog/{path}/">
-                                    {}
+                                <BlogLink href="{href}">
+                                    {title}
                                 </BlogLink>
                                 <BlogDate>
-                                    {}
+                                    {date}
                                 </BlogDate>
+                                <BlogReadingTime minutes="{}" words="{}">
+                                    {} min read
+                                </BlogReadingTime>
                             </div>
                             <BlogDescription>
-                                {}
+                                {description}
                             </BlogDescription>
+                            <BlogExcerpt>{}</BlogExcerpt>
                         </BlogCard>"#,
-                    frontmatter.title,
-                    jiff::fmt::strtime::format("%D", date).unwrap(),
-                    frontmatter.description,
+                    article.minutes,
+                    article.words,
+                    article.minutes,
+                    article.excerpt,
                 )
             })
             .collect::<Vec<_>>()
             .join("")
-    );
-    fs_err::write(&path, data.as_bytes())?;
-    paths.push(path);
+    )
+}
 
-    paths
-        .par_iter()
-        .map(|path| {
-            let file = fs_err::read_to_string(path)?;
+fn report_scheduled(scheduled: &[Article]) {
+    if scheduled.is_empty() {
+        return;
+    }
 
-            let mut document = match wincomp::Document::new(&file) {
-                Ok(d) => d,
-                Err(e) => bail!("Error processing {path:?}: {e}"),
-            };
-            document.expand(|name| components.get(name).or_else(|| ICONS.get(name)));
+    info!(
+        "Skipping {} scheduled post(s), pass --future to include them:",
+        scheduled.len()
+    );
+    for article in scheduled {
+        info!(
+            "  {} {} ({})",
+            article.date,
+            article.frontmatter.title,
+            article.url()
+        );
+    }
+}
 
-            let trimmed_entry = if path.starts_with(src_dir) {
-                path.strip_prefix(src_dir)
-            } else {
-                path.strip_prefix(&blog_build_dir)
-            }
-            .map_err(|e| anyhow!("No prefix on target file: {e}"))?;
+/// A JSON-LD `Article` block describing a post.
+fn json_ld(
+    config: &Config,
+    structured: &StructuredDataConfig,
+    frontmatter: &Frontmatter,
+    date: jiff::civil::Date,
+    url: &str,
+) -> Result<String, Error> {
+    let mut data = serde_json::json!({
+        "@context": "https://schema.org",
+        "@type": "Article",
+        "headline": frontmatter.title,
+        "description": frontmatter.description.trim(),
+        "datePublished": date.to_string(),
+    });
+    if let Some(base_url) = &config.base_url {
+        let url = format!("{}{url}", base_url.trim_end_matches('/'));
+        data["url"] = url.clone().into();
+        data["mainEntityOfPage"] = url.into();
+    }
+    if let Some(author) = &structured.author {
+        let mut person = serde_json::json!({ "@type": "Person", "name": author.name });
+        if let Some(url) = &author.url {
+            person["url"] = url.as_str().into();
+        }
+        data["author"] = person;
+    }
 
-            let outpath = build_dir.join(trimmed_entry);
+    // A literal `</script>` in a string would end the block early.
+    let data = serde_json::to_string(&data)?.replace("</", r"<\/");
+    Ok(format!(
+        r#"<script type="application/ld+json">{data}</script>"#
+    ))
+}
 
-            if let Some(path) = outpath.parent() {
-                fs_err::create_dir_all(path)?;
+/// Expand `document`'s components, recording the names of those it uses.
+fn expand<'s, 'c: 's>(
+    document: &mut wincomp::Document<'s>,
+    components: &'s HashMap<Cow<'c, str>, Arc<wincomp::Component<'c>>>,
+    data: &'s Value,
+    used: &mut HashSet<String>,
+) -> Result<(), wincomp::ExpandError> {
+    document.expand_with_data(
+        |name| {
+            let component = resolve(components, name);
+            if component.is_some() {
+                used.insert(name.to_owned());
             }
+            component
+        },
+        Some(data),
+    )
+}
 
-            let mut buffer = Vec::new();
-            document.write(&mut buffer)?;
-            fs_err::write(outpath, buffer)?;
-
-            Ok(())
-        })
-        .collect::<Result<Vec<_>, Error>>()?;
+/// The prefix resolving a component only against the bundled icons,
+/// as in `<icons:ArrowRight>`.
+const ICONS_PREFIX: &str = "icons";
 
-    fs_err::write(build_dir.join("output.css"), combined_css)?;
-    // fs_err::remove_dir_all(blog_build_dir)?;
+/// Find the component called `name` among the site's and packages'
+/// components, then the icons, then the built-in ones.
+///
+/// A prefixed name, like `<icons:ArrowRight>` or `<pkg:Button>`, only
+/// resolves against that registry, so libraries can't collide. Dotted
+/// names, like `<Ui.Button>`, are the site's namespaced components, or
+/// else name a registry the same way, as in `<icons.ArrowRight>`.
+fn resolve<'a, 's>(
+    components: &'a HashMap<Cow<'s, str>, Arc<wincomp::Component<'s>>>,
+    name: &str,
+) -> Option<&'a wincomp::Component<'s>> {
+    let registry = |registry: &str, local: &str| match registry {
+        ICONS_PREFIX => ICONS.get(local),
+        _ => components
+            .get(format!("{registry}:{local}").as_str())
+            .map(|c| &**c),
+    };
 
-    let elapsed = std::time::Instant::now() - start;
+    match name.split_once(':') {
+        Some((prefix, local)) => registry(prefix, local),
+        None => components
+            .get(name)
+            .map(|c| &**c)
+            .or_else(|| {
+                let (prefix, local) = name.split_once('.')?;
+                registry(prefix, local)
+            })
+            .or_else(|| ICONS.get(name))
+            .or_else(|| (name == "Image").then(|| &*images::IMAGE)),
+    }
+}
 
-    println!(
-        "Processed {} files in {}us",
-        components.len() + paths.len(),
-        elapsed.as_micros()
-    );
+/// Expand a page fragment, like an article's body, without any surrounding shell.
+fn render_fragment<'s>(
+    source: &[u8],
+    components: &HashMap<Cow<'s, str>, Arc<wincomp::Component<'s>>>,
+    data: &'s Value,
+) -> Result<String, Error> {
+    let source = std::str::from_utf8(source)?;
+    let mut document = match wincomp::Document::new(source) {
+        Ok(d) => d,
+        Err(e) => bail!("Error processing fragment: {e}"),
+    };
+    document.expand_fragment_with_data(|name| resolve(components, name), Some(data))?;
 
-    Ok(())
+    let mut buffer = Vec::new();
+    document.write_fragment(&mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
 }
 
-fn inject_hot_reload_into_build_dir(build_dir: &str) -> Result<(), Error> {
-    let script = r#"
-        <script>
-            const ws = new WebSocket(`ws://${location.host}/ws`);
-            ws.onmessage = () => location.reload();
-        </script>
-    "#;
-
-    fn inject_into_dir(dir: &Path, script: &str) -> std::io::Result<()> {
-        for entry in fs_err::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                inject_into_dir(&path, script)?;
-            } else if path.extension().and_then(|s| s.to_str()) == Some("html") {
-                let content = fs_err::read_to_string(&path)?;
-                let modified = content.replace("</body>", &format!("{script}</body>"));
-                fs_err::write(path, modified)?;
-            }
+pub(crate) fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
         }
-        Ok(())
     }
+    escaped
+}
 
-    inject_into_dir(Path::new(build_dir), script)?;
-    Ok(())
+/// Link the combined stylesheet, and optionally the live reload script,
+/// into a static HTML file.
+fn inject(page: String, config: &Config, options: BuildOptions) -> String {
+    let mut page = page;
+    if options.inject_reload {
+        page = page.replace("</body>", &format!("{}</body>", live::SCRIPT));
+    }
+    page.replace(
+        "</head>",
+        &format!("{}</head>", head_links(config, options)),
+    )
 }
 
-fn inject_css_into_build_dir(build_dir: &str) -> Result<(), Error> {
+/// The markup every page's `<head>` ends with, linking the combined
+/// stylesheet and any favicons.
+fn head_links(config: &Config, options: BuildOptions) -> String {
     let css = r#"
         <link rel="stylesheet" type="text/css" href="/output.css">
     "#;
 
-    fn inject_into_dir(dir: &Path, script: &str) -> std::io::Result<()> {
-        for entry in fs_err::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                inject_into_dir(&path, script)?;
-            } else if path.extension().and_then(|s| s.to_str()) == Some("html") {
-                let content = fs_err::read_to_string(&path)?;
-                let modified = content.replace("</head>", &format!("{script}</head>"));
-                fs_err::write(path, modified)?;
-            }
-        }
-        Ok(())
+    let icons = if config.favicon.is_some() {
+        favicon::LINKS
+    } else {
+        ""
+    };
+    let mut head = format!("{css}{icons}");
+    if options.minify {
+        head = head.lines().map(str::trim).collect();
     }
-
-    inject_into_dir(Path::new(build_dir), css)?;
-    Ok(())
+    head
 }
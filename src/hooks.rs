@@ -0,0 +1,235 @@
+use anyhow::{anyhow, bail, Error};
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use std::path::Path;
+use wincomp::element::{Attribute, Element, Node};
+use wincomp::Document;
+
+/// The script, in the site directory, run over every expanded page.
+pub const FILE: &str = "hooks.rhai";
+
+/// A Rhai script that transforms pages before they're written.
+///
+/// The script sees the page's URL as `url` and its nodes as `nodes`, which
+/// it can modify in place or reassign. Elements are maps like
+/// `#{ name: "a", attrs: #{ href: "/" }, children: [] }`, text is a plain
+/// string, escaped when it's written, trusted markup like an included file
+/// is a map like `#{ raw: "<b>...</b>" }`, written as it is, and comments
+/// are maps like `#{ comment: "..." }`.
+///
+/// Rhai maps are sorted by key, so parsed elements also carry their
+/// attributes' names in source order as `attr_order`, and keep that order
+/// when written back. Attributes the script adds come after them.
+pub struct Hooks {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Hooks {
+    /// Compile the hooks script in `site`, if it has one.
+    pub fn load(site: &Path) -> Result<Option<Self>, Error> {
+        let path = site.join(FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Self::compile(&fs_err::read_to_string(&path)?)
+            .map(Some)
+            .map_err(|e| anyhow!("Error compiling {path:?}: {e}"))
+    }
+
+    fn compile(script: &str) -> Result<Self, rhai::ParseError> {
+        let mut engine = Engine::new();
+        // Scripts walk the page recursively, one call or more per level of nesting.
+        engine.set_max_call_levels(256);
+        let ast = engine.compile(script)?;
+
+        Ok(Self { engine, ast })
+    }
+
+    /// Run the script over the page at `url`, returning the transformed page.
+    pub fn run(&self, url: &str, document: &Document) -> Result<Document<'static>, Error> {
+        let nodes: Array = document.nodes.iter().filter_map(to_dynamic).collect();
+
+        let mut scope = Scope::new();
+        scope.push("url", url.to_owned()).push("nodes", nodes);
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| anyhow!("Error running {FILE} on {url:?}: {e}"))?;
+
+        let nodes = scope
+            .get_value::<Array>("nodes")
+            .ok_or(anyhow!("{FILE} must leave `nodes` as an array"))?;
+        let nodes = nodes
+            .into_iter()
+            .map(from_dynamic)
+            .collect::<Result<_, _>>()?;
+        Ok(Document { nodes })
+    }
+}
+
+fn to_dynamic(node: &Node) -> Option<Dynamic> {
+    match node {
+        Node::Text(text) => Some(text.to_string().into()),
+        Node::Raw(markup) => {
+            let mut map = Map::new();
+            map.insert("raw".into(), markup.to_string().into());
+            Some(map.into())
+        }
+        Node::Comment(comment) => {
+            let mut map = Map::new();
+            map.insert("comment".into(), comment.to_string().into());
+            Some(map.into())
+        }
+        Node::Element(element) => {
+            let attributes: Map = element
+                .attributes
+                .iter()
+                .map(|a| {
+                    let value = a
+                        .value
+                        .as_ref()
+                        .map_or(Dynamic::UNIT, |v| v.to_string().into());
+                    (a.name.as_ref().into(), value)
+                })
+                .collect();
+            let children: Array = element.children.iter().filter_map(to_dynamic).collect();
+
+            let order: Array = element
+                .attributes
+                .iter()
+                .map(|a| a.name.to_string().into())
+                .collect();
+
+            let mut map = Map::new();
+            map.insert("name".into(), element.name.to_string().into());
+            map.insert("attrs".into(), attributes.into());
+            map.insert("attr_order".into(), order.into());
+            map.insert("children".into(), children.into());
+            Some(map.into())
+        }
+    }
+}
+
+fn from_dynamic(value: Dynamic) -> Result<Node<'static>, Error> {
+    if value.is_string() {
+        return Ok(Node::Text(value.into_string().unwrap_or_default().into()));
+    }
+
+    let type_name = value.type_name();
+    let Some(mut map) = value.try_cast::<Map>() else {
+        bail!("Expected a node from {FILE}, found {type_name}");
+    };
+
+    if let Some(comment) = map.remove("comment") {
+        return Ok(Node::Comment(comment.to_string().into()));
+    }
+    if let Some(markup) = map.remove("raw") {
+        return Ok(Node::Raw(markup.to_string().into()));
+    }
+
+    let name = map
+        .remove("name")
+        .ok_or(anyhow!("Element from {FILE} is missing its `name`"))?
+        .to_string();
+    let attributes = match map.remove("attrs") {
+        Some(attrs) => {
+            let mut attrs = attrs
+                .try_cast::<Map>()
+                .ok_or(anyhow!("The `attrs` of <{name}> must be a map"))?;
+            let order = map
+                .remove("attr_order")
+                .and_then(|order| order.try_cast::<Array>())
+                .unwrap_or_default();
+
+            // Attributes in their original order, then any new ones.
+            let ordered: Vec<_> = order
+                .into_iter()
+                .filter_map(|name| attrs.remove_entry(name.to_string().as_str()))
+                .collect();
+            ordered
+                .into_iter()
+                .chain(attrs)
+                .map(|(name, value)| Attribute {
+                    name: name.to_string().into(),
+                    value: (!value.is_unit()).then(|| value.to_string().into()),
+                    offset: None,
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+    let children = match map.remove("children") {
+        Some(children) => children
+            .try_cast::<Array>()
+            .ok_or(anyhow!("The `children` of <{name}> must be an array"))?
+            .into_iter()
+            .map(from_dynamic)
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+
+    Ok(Node::Element(Element {
+        name: name.into(),
+        attributes,
+        children,
+        offset: None,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transforms_pages() {
+        let hooks = Hooks::compile(
+            r#"
+                fn visit(node) {
+                    if type_of(node) != "map" || !("name" in node) {
+                        return node;
+                    }
+                    if node.name == "a" && node.attrs.href.starts_with("http") {
+                        node.attrs.rel = "external";
+                    }
+                    node.children = node.children.map(|child| visit(child));
+                    node
+                }
+
+                nodes = nodes.map(|node| visit(node));
+                if url == "/" {
+                    nodes[0].children.push(#{ name: "footer", children: ["Hi"] });
+                }
+            "#,
+        )
+        .unwrap();
+
+        let document = Document::new(
+            r#"<body><a href="https://example.com" hidden>out</a><a href="/">in</a></body>"#,
+        )
+        .unwrap();
+        let document = hooks.run("/", &document).unwrap();
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<body><a href="https://example.com" hidden rel="external">out</a><a href="/">in</a><footer>Hi</footer></body>"#
+        );
+    }
+
+    #[test]
+    fn keeps_trusted_markup() {
+        let hooks = Hooks::compile(r#"nodes.push("<i>text</i>");"#).unwrap();
+        let document = Document {
+            nodes: vec![Node::Raw("<b>included</b>".into())],
+        };
+        let document = hooks.run("/", &document).unwrap();
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<b>included</b>&lt;i>text&lt;/i>"
+        );
+    }
+}
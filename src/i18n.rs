@@ -0,0 +1,143 @@
+use crate::gen::escape_html;
+use anyhow::{bail, Error};
+use foldhash::HashSet;
+use wincomp::element::{walk, Attribute};
+use wincomp::Document;
+
+/// The languages a site is published in. The first is the default,
+/// served from the root, while the rest live under `/<lang>/`.
+pub struct Locales<'a> {
+    languages: &'a [String],
+}
+
+impl<'a> Locales<'a> {
+    pub fn new(languages: &'a [String]) -> Self {
+        Self { languages }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.languages.is_empty()
+    }
+
+    pub fn default_language(&self) -> &'a str {
+        self.languages.first().map_or("en", String::as_str)
+    }
+
+    /// The URL prefix for `lang`, empty for the default language.
+    pub fn prefix(&self, lang: &str) -> String {
+        if lang == self.default_language() {
+            String::new()
+        } else {
+            format!("/{lang}")
+        }
+    }
+
+    /// Check that `lang` is one of the site's languages, or the default
+    /// when the site doesn't list any.
+    pub fn validate(&self, lang: &'a str) -> Result<&'a str, Error> {
+        if !self.enabled() && lang != self.default_language() {
+            bail!("Unknown language {lang:?}, since `languages` isn't configured");
+        }
+        if self.enabled() && !self.languages.iter().any(|l| l == lang) {
+            bail!(
+                "Unknown language {lang:?}, expected one of {:?}",
+                self.languages
+            );
+        }
+        Ok(lang)
+    }
+
+    /// The language of the page at `url`, along with the URL stripped of its prefix.
+    pub fn split<'u>(&self, url: &'u str) -> (&'a str, &'u str) {
+        for lang in self.languages.iter().skip(1) {
+            if let Some(rest) = url.strip_prefix(&format!("/{lang}")) {
+                if rest.starts_with('/') {
+                    return (lang, rest);
+                }
+            }
+        }
+        (self.default_language(), url)
+    }
+
+    /// `<link rel="alternate">` tags for every translation of the page at
+    /// `url` among `known` page URLs, or nothing if it has none.
+    pub fn alternates(&self, url: &str, known: &HashSet<String>, base_url: &str) -> String {
+        let (_, neutral) = self.split(url);
+        let translations: Vec<_> = self
+            .languages
+            .iter()
+            .map(|lang| (lang, format!("{}{neutral}", self.prefix(lang))))
+            .filter(|(_, url)| known.contains(url))
+            .collect();
+
+        if translations.len() < 2 {
+            return String::new();
+        }
+
+        let base_url = base_url.trim_end_matches('/');
+        translations
+            .into_iter()
+            .map(|(lang, url)| {
+                format!(
+                    r#"<link rel="alternate" hreflang="{}" href="{}">"#,
+                    escape_html(lang),
+                    escape_html(&format!("{base_url}{url}"))
+                )
+            })
+            .collect()
+    }
+}
+
+/// Set the `lang` attribute of the document's `<html>` element.
+pub fn set_lang<'s>(document: &mut Document<'s>, lang: &'s str) {
+    for node in &mut document.nodes {
+        if let Some(element) = node.element_mut() {
+            walk(element, &mut |e| {
+                if e.name != "html" {
+                    return;
+                }
+                match e.attributes.iter_mut().find(|a| a.name == "lang") {
+                    Some(attribute) => attribute.value = Some(lang.into()),
+                    None => e.attributes.push(Attribute {
+                        name: "lang".into(),
+                        value: Some(lang.into()),
+                        offset: None,
+                    }),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links_translations() {
+        let languages = ["en".to_string(), "de".to_string()];
+        let locales = Locales::new(&languages);
+
+        assert_eq!(locales.split("/de/about/"), ("de", "/about/"));
+        assert_eq!(locales.split("/design/"), ("en", "/design/"));
+        assert_eq!(locales.prefix("en"), "");
+        assert!(locales.validate("fr").is_err());
+
+        let unconfigured = Locales::new(&[]);
+        assert_eq!(unconfigured.validate("en").unwrap(), "en");
+        assert!(unconfigured.validate("de").is_err());
+
+        let known = [
+            "/about/".to_string(),
+            "/de/about/".to_string(),
+            "/blog/".to_string(),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            locales.alternates("/de/about/", &known, "https://example.com/"),
+            r#"<link rel="alternate" hreflang="en" href="https://example.com/about/"><link rel="alternate" hreflang="de" href="https://example.com/de/about/">"#
+        );
+        assert_eq!(locales.alternates("/blog/", &known, ""), "");
+    }
+}
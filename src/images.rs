@@ -0,0 +1,268 @@
+use crate::config::{ImageFormat, ImagesConfig};
+use crate::output::Output;
+use anyhow::{anyhow, Context, Error};
+use foldhash::HashMap;
+use image::codecs::avif::AvifEncoder;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use wincomp::element::{walk, Attribute, Element};
+use wincomp::{Component, Document};
+
+/// Where encoded images are kept between builds, keyed by content hash.
+pub const CACHE_DIR: &str = ".corvusite-cache/images";
+
+/// Formats we can decode and re-encode. Anything else is left alone.
+const RASTER: &[&str] = &["png", "jpg", "jpeg", "webp"];
+
+/// Resizes and re-encodes the static images that `<Image>` elements refer to.
+pub struct Images<'a> {
+    config: &'a ImagesConfig,
+    static_dir: PathBuf,
+    output: &'a Output,
+}
+
+impl<'a> Images<'a> {
+    pub fn new(
+        config: &'a ImagesConfig,
+        static_dir: impl Into<PathBuf>,
+        output: &'a Output,
+    ) -> Self {
+        Self {
+            config,
+            static_dir: static_dir.into(),
+            output,
+        }
+    }
+
+    /// The static file an `src` refers to, if it's one we can optimize.
+    pub fn source(&self, src: &str) -> Option<PathBuf> {
+        let path = self.static_dir.join(src.strip_prefix('/')?);
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+
+        (RASTER.contains(&extension.as_str()) && path.is_file()).then_some(path)
+    }
+
+    /// Write optimized copies of `source` into the build's `assets/images`
+    /// directory at each configured width no wider than the original.
+    pub fn optimize(&self, source: &Path) -> Result<Optimized, Error> {
+        let data = fs_err::read(source)?;
+        let extension = match self.config.format {
+            ImageFormat::Webp => "webp".to_owned(),
+            ImageFormat::Avif => "avif".to_owned(),
+            ImageFormat::Original => source
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+        };
+        let stem = source
+            .file_stem()
+            .ok_or(anyhow!("Image {source:?} has no file stem"))?
+            .to_string_lossy();
+
+        let hash = blake3::hash(&data).to_hex();
+        let (width, _) = image::ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()?
+            .into_dimensions()
+            .with_context(|| format!("Failed to read {source:?}"))?;
+
+        let largest = width.min(self.config.max_width);
+        let mut widths: Vec<_> = self
+            .config
+            .widths
+            .iter()
+            .copied()
+            .filter(|&w| w < largest)
+            .chain([largest])
+            .collect();
+        widths.sort_unstable();
+        widths.dedup();
+
+        // Only decoded when something's missing from the cache.
+        let mut decoded = None;
+        let mut candidates = Vec::new();
+        let mut src = String::new();
+        for width in widths {
+            let cached = Path::new(CACHE_DIR).join(format!("{}-{width}.{extension}", &hash[..16]));
+
+            let encoded = match fs_err::read(&cached) {
+                Ok(encoded) => encoded,
+                Err(_) => {
+                    let encoded = (|| {
+                        let (image, format) = match &decoded {
+                            Some(decoded) => decoded,
+                            None => decoded.insert(decode(&data)?),
+                        };
+                        self.encode(image, *format, width)
+                    })()
+                    .with_context(|| format!("Failed to optimize {source:?}"))?;
+                    fs_err::create_dir_all(CACHE_DIR)?;
+                    fs_err::write(&cached, &encoded)?;
+                    encoded
+                }
+            };
+
+            let name = format!(
+                "{stem}.{}.{extension}",
+                &blake3::hash(&encoded).to_hex()[..8]
+            );
+            self.output.write(
+                self.output.root().join("assets/images").join(&name),
+                encoded,
+            )?;
+
+            src = format!("/assets/images/{name}");
+            candidates.push(format!("{src} {width}w"));
+        }
+
+        Ok(Optimized {
+            src,
+            srcset: candidates.join(", "),
+        })
+    }
+
+    fn encode(
+        &self,
+        image: &DynamicImage,
+        original: image::ImageFormat,
+        width: u32,
+    ) -> Result<Vec<u8>, Error> {
+        let resized;
+        let image = if image.width() > width {
+            resized = image.resize(width, u32::MAX, FilterType::Lanczos3);
+            &resized
+        } else {
+            image
+        };
+
+        let mut encoded = Vec::new();
+        match self.config.format {
+            ImageFormat::Webp => DynamicImage::from(image.to_rgba8())
+                .write_to(&mut Cursor::new(&mut encoded), image::ImageFormat::WebP)?,
+            ImageFormat::Avif => DynamicImage::from(image.to_rgba8())
+                .write_with_encoder(AvifEncoder::new_with_speed_quality(&mut encoded, 6, 80))?,
+            ImageFormat::Original => image.write_to(&mut Cursor::new(&mut encoded), original)?,
+        }
+
+        Ok(encoded)
+    }
+}
+
+/// An image's largest optimized copy, along with every width of it.
+pub struct Optimized {
+    pub src: String,
+    pub srcset: String,
+}
+
+fn decode(data: &[u8]) -> Result<(DynamicImage, image::ImageFormat), Error> {
+    let reader = image::ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    let format = reader
+        .format()
+        .ok_or(anyhow!("Unrecognized image format"))?;
+    Ok((reader.decode()?, format))
+}
+
+/// Call `f` with every `<Image>` element in `document`.
+fn each_image<'s>(document: &mut Document<'s>, mut f: impl FnMut(&mut Element<'s>)) {
+    for node in &mut document.nodes {
+        if let Some(element) = node.element_mut() {
+            walk(element, &mut |e| {
+                if e.name == "Image" {
+                    f(e)
+                }
+            });
+        }
+    }
+}
+
+/// The `src` of every `<Image>` element in `document`.
+pub fn sources(document: &mut Document) -> Vec<String> {
+    let mut sources = Vec::new();
+    each_image(document, |image| {
+        let src = image.attributes.iter().find(|a| a.name == "src");
+        sources.extend(src.and_then(|a| a.value.as_deref()).map(String::from));
+    });
+    sources
+}
+
+/// Point each `<Image>` at its optimized counterpart in `urls`, offering
+/// every width through `srcset` and loading it lazily.
+pub fn rewrite<'s>(
+    document: &mut Document<'s>,
+    urls: &'s HashMap<String, Optimized>,
+    sizes: &'s str,
+) {
+    each_image(document, |image| {
+        let src = image.attributes.iter().find(|a| a.name == "src");
+        let Some(optimized) = src
+            .and_then(|a| a.value.as_deref())
+            .and_then(|v| urls.get(v))
+        else {
+            return;
+        };
+
+        for attribute in image.attributes.iter_mut().filter(|a| a.name == "src") {
+            attribute.value = Some(optimized.src.as_str().into());
+        }
+        for (name, value) in [
+            ("srcset", optimized.srcset.as_str()),
+            ("sizes", sizes),
+            ("loading", "lazy"),
+        ] {
+            // Anything set explicitly wins.
+            if !image.attributes.iter().any(|a| a.name == name) {
+                image.attributes.push(Attribute {
+                    name: name.into(),
+                    value: Some(value.into()),
+                    offset: None,
+                });
+            }
+        }
+    });
+}
+
+/// Renders `<Image>` as a plain `<img>` when the site doesn't define its own,
+/// leaving out `srcset` and `sizes` for images that weren't optimized.
+pub static IMAGE: LazyLock<Component<'static>> = LazyLock::new(|| {
+    Component::new(
+        r#"<Image src alt="" srcset="" sizes="" loading="lazy"><If cond="srcset"><img src="src" alt="alt" srcset="srcset" sizes="sizes" loading="loading" /><Else /><img src="src" alt="alt" loading="loading" /></If></Image>"#,
+    )
+    .unwrap()
+});
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rewrites_images() {
+        let mut document = Document::new(
+            r#"<div><Image src="/a.png" alt="a" loading="eager" /><img src="/a.png" /><Image src="/b.svg" /></div>"#,
+        )
+        .unwrap();
+        assert_eq!(sources(&mut document), ["/a.png", "/b.svg"]);
+
+        let urls = [(
+            "/a.png".to_string(),
+            Optimized {
+                src: "/assets/images/a.2.webp".into(),
+                srcset: "/assets/images/a.1.webp 480w, /assets/images/a.2.webp 600w".into(),
+            },
+        )]
+        .into_iter()
+        .collect();
+        rewrite(&mut document, &urls, "50vw");
+        document
+            .expand(|name| (name == "Image").then(|| &*IMAGE))
+            .unwrap();
+
+        let mut buffer = Vec::new();
+        document.write_fragment(&mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            r#"<div><img src="/assets/images/a.2.webp" alt="a" srcset="/assets/images/a.1.webp 480w, /assets/images/a.2.webp 600w" sizes="50vw" loading="eager"><img src="/a.png"><img src="/b.svg" alt="" loading="lazy"></div>"#
+        );
+    }
+}
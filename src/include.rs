@@ -0,0 +1,449 @@
+use crate::cache::Cache;
+use anyhow::{anyhow, bail, Context, Error};
+use foldhash::HashMap;
+use std::path::{Path, PathBuf};
+use wincomp::{
+    element::{self, Element, Node},
+    Document,
+};
+
+/// Markdown with its `{{ include "..." }}` directives inlined.
+pub struct Resolved {
+    /// The canonical path of the root file.
+    pub path: PathBuf,
+    pub source: String,
+    /// Every file that was transitively inlined.
+    pub dependencies: Vec<PathBuf>,
+}
+
+/// Read the markdown file at `path`, recursively inlining includes.
+///
+/// Include paths are relative to the file containing the directive,
+/// and must stay within `root`. Directives inside fenced code blocks are
+/// left untouched.
+pub fn resolve(path: &Path, root: &Path) -> Result<Resolved, Error> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {path:?}"))?;
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {root:?}"))?;
+
+    let mut resolved = Resolved {
+        path: path.clone(),
+        source: String::new(),
+        dependencies: Vec::new(),
+    };
+    resolve_recursive(&path, &root, &mut vec![], &mut resolved)?;
+
+    Ok(resolved)
+}
+
+fn resolve_recursive(
+    path: &Path,
+    root: &Path,
+    stack: &mut Vec<PathBuf>,
+    resolved: &mut Resolved,
+) -> Result<(), Error> {
+    if let Some(start) = stack.iter().position(|p| p == path) {
+        let cycle = stack[start..]
+            .iter()
+            .chain([&path.to_path_buf()])
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        bail!("Include cycle detected: {cycle}");
+    }
+
+    let source = fs_err::read_to_string(path)?;
+    let parent = path.parent().unwrap_or(Path::new(""));
+    stack.push(path.to_owned());
+
+    let mut fence: Option<&str> = None;
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+        }
+
+        if fence.is_some() {
+            resolved.source.push_str(line);
+            continue;
+        }
+
+        let mut rest = line;
+        while let Some((before, target, after)) = directive(rest) {
+            resolved.source.push_str(before);
+
+            let target = parent
+                .join(target)
+                .canonicalize()
+                .with_context(|| format!("Failed to include {target:?} from {path:?}"))?;
+            if !target.starts_with(root) {
+                bail!("Can't include {target:?} from {path:?}, since it's outside the site");
+            }
+            resolve_recursive(&target, root, stack, resolved)?;
+
+            if !resolved.dependencies.contains(&target) {
+                resolved.dependencies.push(target);
+            }
+
+            rest = after;
+        }
+        resolved.source.push_str(rest);
+    }
+
+    stack.pop();
+
+    Ok(())
+}
+
+/// Find the first `{{ include "path" }}` in `input`, returning the
+/// text before it, the included path, and the text after it.
+fn directive(input: &str) -> Option<(&str, &str, &str)> {
+    let mut offset = 0;
+    while let Some(start) = input[offset..].find("{{").map(|s| s + offset) {
+        let parsed = (|| {
+            let inner = input[start + 2..].trim_start().strip_prefix("include")?;
+            let inner = inner.trim_start().strip_prefix('"')?;
+            let (target, inner) = inner.split_once('"')?;
+            let inner = inner.trim_start().strip_prefix("}}")?;
+            Some((target, inner))
+        })();
+
+        match parsed {
+            Some((target, after)) => return Some((&input[..start], target, after)),
+            None => offset = start + 2,
+        }
+    }
+
+    None
+}
+
+/// What an `<Include src="...">` or `<Markdown>` element pulls into a page.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Source {
+    /// A file relative to the site directory, rendered when it's markdown.
+    File { src: String, markdown: bool },
+    /// Markdown written inside a `<Markdown>` element.
+    Markdown(String),
+}
+
+impl Source {
+    fn of(element: &Element) -> Option<Self> {
+        let src = element
+            .attributes
+            .iter()
+            .find(|a| a.name == "src")
+            .and_then(|a| a.value.as_deref());
+
+        match (element.name.as_ref(), src) {
+            ("Include", Some(src)) => Some(Self::File {
+                src: src.to_owned(),
+                markdown: src.ends_with(".md"),
+            }),
+            ("Markdown", Some(src)) => Some(Self::File {
+                src: src.to_owned(),
+                markdown: true,
+            }),
+            ("Markdown", None) => {
+                let text = element.children.iter().find_map(|child| match child {
+                    Node::Text(text) => Some(text.as_ref()),
+                    _ => None,
+                });
+                Some(Self::Markdown(dedent(text.unwrap_or_default())))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// What a [`Source`] turned into.
+pub struct Included {
+    /// The canonical path of the file it came from, if any.
+    pub path: Option<PathBuf>,
+    pub html: String,
+    /// Whether `html` was rendered from markdown, so it may use components.
+    pub markdown: bool,
+}
+
+impl Included {
+    /// `html` parsed, if it's spliced in as elements rather than verbatim.
+    fn nested(&self) -> Option<Result<Document<'_>, Error>> {
+        (self.markdown || self.html.contains("<Include") || self.html.contains("<Markdown")).then(
+            || Document::new(&self.html).map_err(|e| anyhow!("Failed to parse: {}", e.inner())),
+        )
+    }
+}
+
+/// What every `<Include>` and `<Markdown>` element in `document` pulls in.
+pub fn sources(document: &Document) -> Vec<Source> {
+    fn collect(nodes: &[Node], sources: &mut Vec<Source>) {
+        for element in nodes.iter().filter_map(Node::element) {
+            match Source::of(element) {
+                Some(source) => sources.push(source),
+                None => collect(&element.children, sources),
+            }
+        }
+    }
+
+    let mut sources = Vec::new();
+    collect(&document.nodes, &mut sources);
+    sources
+}
+
+/// Read and render `sources`, with files relative to the site directory,
+/// along with everything they include in turn.
+pub fn read(
+    site: &Path,
+    mut sources: Vec<Source>,
+    cache: &Cache,
+) -> Result<HashMap<Source, Included>, Error> {
+    let site = site
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {site:?}"))?;
+    let render = |markdown: &str| -> Result<String, Error> {
+        let writer = cache.markdown(markdown).map_err(|e| anyhow!("{e}"))?;
+        Ok(String::from_utf8(writer.output())?)
+    };
+
+    let mut included = HashMap::default();
+    while let Some(source) = sources.pop() {
+        if included.contains_key(&source) {
+            continue;
+        }
+
+        let file = match &source {
+            Source::File { src, markdown } => {
+                let path = site
+                    .join(src.trim_start_matches('/'))
+                    .canonicalize()
+                    .with_context(|| format!("Failed to include {src:?}"))?;
+                if !path.starts_with(&site) {
+                    bail!("Can't include {src:?}, since it's outside the site");
+                }
+                let contents = fs_err::read_to_string(&path)?;
+                Included {
+                    path: Some(path),
+                    html: if *markdown {
+                        render(&contents)?
+                    } else {
+                        contents
+                    },
+                    markdown: *markdown,
+                }
+            }
+            Source::Markdown(markdown) => Included {
+                path: None,
+                html: render(markdown)?,
+                markdown: true,
+            },
+        };
+        if let Some(document) = file.nested() {
+            sources.extend(self::sources(&document?));
+        }
+        included.insert(source, file);
+    }
+    Ok(included)
+}
+
+/// Replace each `<Include>` and `<Markdown>` in `document` with what it
+/// pulls in from `included`.
+///
+/// HTML files are kept verbatim, while rendered markdown is parsed so
+/// the components it uses can be expanded. Anything these include in
+/// turn is spliced in too, so HTML files that include others are parsed
+/// as well.
+pub fn splice<'s>(
+    document: &mut Document<'s>,
+    included: &'s HashMap<Source, Included>,
+) -> Result<(), Error> {
+    splice_nodes(&mut document.nodes, included, &mut Vec::new())
+}
+
+fn splice_nodes<'s>(
+    nodes: &mut Vec<Node<'s>>,
+    included: &'s HashMap<Source, Included>,
+    stack: &mut Vec<&'s Source>,
+) -> Result<(), Error> {
+    let mut index = 0;
+    while index < nodes.len() {
+        let Some(element) = nodes[index].element_mut() else {
+            index += 1;
+            continue;
+        };
+        let Some(source) = Source::of(element) else {
+            splice_nodes(&mut element.children, included, stack)?;
+            index += 1;
+            continue;
+        };
+
+        let replacement = match included.get_key_value(&source) {
+            Some((source, file)) => match file.nested() {
+                Some(document) => {
+                    if stack.contains(&source) {
+                        bail!("{source:?} includes itself");
+                    }
+                    let mut nodes = document
+                        .with_context(|| format!("Failed to include {source:?}"))?
+                        .nodes;
+                    element::unlocate(&mut nodes);
+                    stack.push(source);
+                    splice_nodes(&mut nodes, included, stack)?;
+                    stack.pop();
+                    nodes
+                }
+                None => vec![Node::Raw(file.html.as_str().into())],
+            },
+            None => Vec::new(),
+        };
+
+        let len = replacement.len();
+        nodes.splice(index..=index, replacement);
+        index += len;
+    }
+    Ok(())
+}
+
+/// Remove the indentation shared by every line of `text`, so markdown
+/// nested in indented HTML isn't read as a code block.
+fn dedent(text: &str) -> String {
+    let indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    text.lines()
+        .map(|line| line.get(indent..).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_directive() {
+        assert_eq!(
+            directive(r#"a {{ include "b.md" }} c"#),
+            Some(("a ", "b.md", " c"))
+        );
+        assert_eq!(directive(r#"{{include "b.md"}}"#), Some(("", "b.md", "")));
+        assert_eq!(
+            directive(r#"{{ other }} {{ include "b.md" }}"#),
+            Some(("{{ other }} ", "b.md", ""))
+        );
+        assert_eq!(directive(r#"{{ include b.md }}"#), None);
+    }
+
+    #[test]
+    fn splices_includes() {
+        let mut document = Document::new(
+            "<body><Include src=\"a.html\" /><div><Include src=\"b.md\" /></div>\n  <Markdown>\n    Some *text*\n      - a < b\n  </Markdown></body>",
+        )
+        .unwrap();
+        let file = |src: &str, markdown| Source::File {
+            src: src.to_owned(),
+            markdown,
+        };
+        let inline = Source::Markdown("\nSome *text*\n  - a < b\n".into());
+        assert_eq!(
+            sources(&document),
+            [file("a.html", false), file("b.md", true), inline.clone()]
+        );
+
+        let included: HashMap<_, _> = [
+            (
+                file("a.html", false),
+                "<!-- kept --><script>a && b</script>",
+            ),
+            (file("b.md", true), "<p>Some <em>text</em></p>"),
+            (inline, "<p>Inline</p>"),
+        ]
+        .into_iter()
+        .map(|(source, html)| {
+            let markdown = source != file("a.html", false);
+            let file = Included {
+                path: None,
+                html: html.into(),
+                markdown,
+            };
+            (source, file)
+        })
+        .collect();
+        splice(&mut document, &included).unwrap();
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<body><!-- kept --><script>a && b</script><div><p>Some <em>text</em></p></div>\n  <p>Inline</p></body>"
+        );
+    }
+
+    #[test]
+    fn splices_nested_includes() {
+        let included = |files: &[(&str, &str)]| -> HashMap<_, _> {
+            files
+                .iter()
+                .map(|(src, html)| {
+                    let source = Source::File {
+                        src: src.to_string(),
+                        markdown: false,
+                    };
+                    let file = Included {
+                        path: None,
+                        html: html.to_string(),
+                        markdown: false,
+                    };
+                    (source, file)
+                })
+                .collect()
+        };
+        let document = || Document::new("<main><Include src=\"a.html\" /></main>").unwrap();
+
+        let files = included(&[
+            ("a.html", "<nav><Include src=\"b.html\" /></nav>"),
+            ("b.html", "<script>a && b</script>"),
+        ]);
+        let mut spliced = document();
+        splice(&mut spliced, &files).unwrap();
+        let mut output = Vec::new();
+        spliced.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<main><nav><script>a && b</script></nav></main>"
+        );
+
+        let files = included(&[("a.html", "<Include src=\"a.html\" />")]);
+        assert!(splice(&mut document(), &files).is_err());
+    }
+
+    #[test]
+    fn rejects_files_outside_the_site() {
+        let root = std::env::temp_dir().join(format!("corvusite-include-{}", std::process::id()));
+        let _ = fs_err::remove_dir_all(&root);
+        fs_err::create_dir_all(root.join("site")).unwrap();
+        fs_err::write(root.join("secret.md"), "Secret").unwrap();
+        fs_err::write(root.join("site/post.md"), "{{ include \"../secret.md\" }}").unwrap();
+
+        assert!(resolve(&root.join("site/post.md"), &root.join("site")).is_err());
+        assert!(resolve(&root.join("site/post.md"), &root).is_ok());
+
+        let source = Source::File {
+            src: "../secret.md".into(),
+            markdown: true,
+        };
+        let cache = Cache::disabled();
+        assert!(read(&root.join("site"), vec![source], &cache).is_err());
+
+        fs_err::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::Error;
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+
+/// The addresses other devices can reach a server bound to `host` on.
+pub fn addresses(host: IpAddr) -> Vec<IpAddr> {
+    if !host.is_unspecified() {
+        return vec![host];
+    }
+
+    // Connecting a UDP socket sends nothing, but picks the
+    // interface that routes outward, which is the LAN one.
+    let outward = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|socket| {
+            socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9))?;
+            socket.local_addr()
+        })
+        .map(|addr| addr.ip());
+    match outward {
+        Ok(ip) if !ip.is_unspecified() => vec![ip],
+        _ => Vec::new(),
+    }
+}
+
+/// A QR code for `url`, drawn with block characters for the terminal.
+pub fn qr(url: &str) -> Result<String, Error> {
+    let code = qrcode::QrCode::new(url)?;
+    Ok(code
+        .render::<qrcode::render::unicode::Dense1x2>()
+        .quiet_zone(true)
+        .build())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lists_addresses() {
+        let host = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+        assert_eq!(addresses(host), [host]);
+
+        let code = qr("http://192.168.1.20:3000").unwrap();
+        assert!(code.lines().count() > 10);
+    }
+}
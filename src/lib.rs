@@ -0,0 +1,1070 @@
+use anyhow::Context;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Request,
+    },
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use base64::prelude::*;
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
+use config::{is_ignored, Config};
+use live::Live;
+use notify_debouncer_full::{
+    new_debouncer,
+    notify::{EventKind, RecursiveMode},
+    DebounceEventResult,
+};
+use std::{
+    io::IsTerminal,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+use tower_http::{
+    compression::CompressionLayer,
+    services::{ServeDir, ServeFile},
+    set_header::SetResponseHeaderLayer,
+    trace::TraceLayer,
+};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::fmt::format::FmtSpan;
+
+mod assets;
+mod cache;
+mod conditional;
+mod config;
+mod data;
+mod deps;
+mod diagnostics;
+mod favicon;
+mod feed;
+mod fingerprint;
+mod gen;
+mod hooks;
+mod i18n;
+mod images;
+mod include;
+mod lan;
+mod lazy_comp;
+mod links;
+mod lint;
+mod live;
+mod output;
+mod package;
+mod permalink;
+mod plugin;
+mod purge;
+mod redirects;
+mod scaffold;
+mod series;
+mod sitemap;
+mod tags;
+mod tailwind;
+mod timings;
+mod tls;
+
+pub use output::Output;
+pub use plugin::{Noopener, Plugin, Plugins};
+pub use wincomp;
+
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Commands,
+
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct Options {
+    /// Directory for processed output [default: build]
+    #[arg(short = 'o', long, global = true)]
+    build: Option<String>,
+
+    /// Directory for static files [default: static]
+    #[arg(long, name = "static", global = true)]
+    static_dir: Option<String>,
+
+    /// Directory containing source HTML files [default: site]
+    #[arg(long, global = true)]
+    site: Option<String>,
+
+    /// Path to the site configuration file
+    #[arg(long, default_value = "corvusite.toml", global = true)]
+    config: String,
+
+    /// Include posts dated in the future
+    #[arg(long, visible_alias = "future", global = true)]
+    include_future: bool,
+
+    /// Include posts marked as drafts (always on when serving)
+    #[arg(long, global = true)]
+    drafts: bool,
+
+    /// Treat warnings, like prose lints and broken links, as errors
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// The most detailed messages to log: error, warn, info, debug, or trace.
+    /// Debug and trace also log how long each build stage takes
+    #[arg(long, default_value = "info", global = true)]
+    log_level: tracing::Level,
+
+    /// How to write log messages
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+
+    /// How many files to build at once [default: one per CPU]
+    #[arg(short, long, global = true)]
+    jobs: Option<NonZeroUsize>,
+
+    /// Build one file at a time, in order, for debugging ordering-sensitive issues
+    #[arg(long, conflicts_with = "jobs", global = true)]
+    no_parallel: bool,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum LogFormat {
+    Text,
+    /// One JSON object per line, for CI and log collectors
+    Json,
+}
+
+/// Send log messages to stdout at `level` and above.
+fn init_logging(level: tracing::Level, format: LogFormat) {
+    let spans = if level >= tracing::Level::DEBUG {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false)
+        .with_ansi(std::io::stdout().is_terminal())
+        .with_span_events(spans);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Commands {
+    Build(BuildArgs),
+    Serve(ServeArgs),
+    /// Rebuild the site whenever its sources change, without serving it
+    Watch(BuildArgs),
+    /// Bundle a directory of components into a versioned package
+    Pack(PackArgs),
+    /// Create a new site with a starter layout
+    Init(InitArgs),
+    /// Create new content from a template
+    #[command(subcommand)]
+    New(NewCommand),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum NewCommand {
+    /// Create a blog post with prefilled frontmatter
+    Post {
+        /// The post's title
+        title: String,
+    },
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct BuildArgs {
+    /// Report every failing page before exiting, rather than stopping at the first
+    #[arg(short, long)]
+    keep_going: bool,
+
+    /// Give stylesheets, scripts, and images content-hashed names
+    #[arg(long)]
+    release: bool,
+
+    /// Strip comments and redundant whitespace from generated pages
+    #[arg(long)]
+    minify: bool,
+
+    /// Indent generated pages for reading and diffing
+    #[arg(long, conflicts_with = "minify")]
+    pretty: bool,
+
+    /// Produce byte-identical output for identical inputs, taking dates from `SOURCE_DATE_EPOCH`
+    #[arg(long)]
+    reproducible: bool,
+
+    /// Render every page from scratch, ignoring `.corvusite-cache`
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Report how long each stage of the build took, and its slowest files
+    #[arg(long)]
+    timings: bool,
+}
+
+impl BuildArgs {
+    fn options(&self) -> gen::BuildOptions {
+        gen::BuildOptions {
+            inject_reload: false,
+            keep_going: self.keep_going,
+            release: self.release,
+            minify: self.minify,
+            pretty: self.pretty,
+            reproducible: self.reproducible,
+            no_cache: self.no_cache,
+            timings: self.timings,
+            lenient: false,
+        }
+    }
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct InitArgs {
+    /// Directory to create the site in
+    #[arg(default_value = ".")]
+    dir: PathBuf,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct ServeArgs {
+    /// Port to run the server on [default: 3000]
+    #[arg(short, long)]
+    port: Option<u16>,
+
+    /// Address to listen on, like `127.0.0.1` to keep the site to this machine
+    #[arg(long, default_value = "0.0.0.0")]
+    host: IpAddr,
+
+    /// Print a QR code of the LAN address, for opening the site on a phone
+    #[arg(long)]
+    qr: bool,
+
+    /// Log each request's method, path, status, size, and latency
+    #[arg(long)]
+    log: bool,
+
+    /// Require HTTP basic auth with these credentials, like `user:pass`
+    #[arg(long, value_parser = parse_credentials)]
+    auth: Option<String>,
+
+    /// Open the site in the default browser once it's built
+    #[arg(long)]
+    open: bool,
+
+    /// Keep the built site in memory rather than writing it to the build directory
+    #[arg(long)]
+    memory: bool,
+
+    /// Serve over HTTPS with a self-signed certificate, for secure-context APIs
+    #[arg(long)]
+    tls: bool,
+
+    /// Serve over HTTPS with this PEM certificate instead of a self-signed one
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// The PEM private key for `--tls-cert`
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+}
+
+fn parse_credentials(credentials: &str) -> Result<String, String> {
+    match credentials.split_once(':') {
+        Some((user, _)) if !user.is_empty() => Ok(credentials.to_owned()),
+        _ => Err("expected credentials like `user:pass`".into()),
+    }
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+struct PackArgs {
+    /// Directory containing the `.mod.html` components and their CSS
+    dir: PathBuf,
+
+    /// Package name (defaults to the directory name)
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// Package version
+    #[arg(long, default_value = "0.1.0")]
+    version: String,
+
+    /// Directory to write the archive into
+    #[arg(long, default_value = ".")]
+    out: PathBuf,
+}
+
+/// Runs the command line interface, building and serving sites with the
+/// given plugins.
+pub fn run(plugins: plugin::Plugins) -> anyhow::Result<()> {
+    let args = Args::parse();
+    init_logging(args.options.log_level, args.options.log_format);
+
+    // With a single thread, parallel iterators visit files in order.
+    let jobs = match args.options.no_parallel {
+        true => Some(NonZeroUsize::MIN),
+        false => args.options.jobs,
+    };
+    if let Some(jobs) = jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.get())
+            .build_global()?;
+    }
+
+    let mut config = Config::load(&args.options.config)?;
+    config.merge(&args.options);
+
+    match args.command {
+        Commands::Build(build_args) => {
+            // Create build directory if it doesn't exist
+            fs_err::create_dir_all(&config.build).context("Failed to create build directory")?;
+
+            gen::process_all_files(
+                &config,
+                build_args.options(),
+                &mut Default::default(),
+                &plugins,
+            )
+            .context("Error processing files")?;
+        }
+        Commands::Watch(build_args) => {
+            fs_err::create_dir_all(&config.build).context("Failed to create build directory")?;
+
+            let config = Arc::new(config);
+            let options = build_args.options();
+            let mut state = gen::Incremental::default();
+            if let Err(e) = gen::process_all_files(&config, options, &mut state, &plugins) {
+                error!("Error processing files: {e}");
+            }
+
+            let (builds, watcher) = watch(Arc::clone(&config), options, state, plugins, |_| ())?;
+            info!("Watching for changes, building into {}", config.build);
+
+            // As when serving, a build in progress finishes before exiting.
+            let rt = tokio::runtime::Runtime::new()?;
+            rt.block_on(tokio::signal::ctrl_c())?;
+            builds.send(Build::Stop).unwrap_or(());
+            if watcher.join().is_err() {
+                error!("The watcher thread panicked");
+            }
+        }
+        Commands::Serve(serve_args) => {
+            // Start the Tokio runtime
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async move {
+                if let Some(port) = serve_args.port {
+                    config.port = port;
+                }
+                config.drafts = true;
+
+                if let Err(e) = serve(config, serve_args, plugins).await {
+                    error!("Server error: {e}");
+                }
+            });
+        }
+        Commands::Pack(pack_args) => {
+            let name = match pack_args.name {
+                Some(name) => name,
+                None => pack_args
+                    .dir
+                    .canonicalize()?
+                    .file_name()
+                    .context("Package directory has no name")?
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+
+            let path = package::pack(&pack_args.dir, &name, &pack_args.version, &pack_args.out)?;
+            println!("Packed {name} {} into {path:?}", pack_args.version);
+        }
+        Commands::Init(init_args) => {
+            scaffold::init(&init_args.dir)?;
+            println!("Created a new site in {:?}", init_args.dir);
+        }
+        Commands::New(NewCommand::Post { title }) => {
+            let path = scaffold::new_post(Path::new(&config.site), &title)?;
+            println!("Created {path:?}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn serve(
+    config: Config,
+    args: ServeArgs,
+    plugins: plugin::Plugins,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let context = Arc::new(config);
+
+    let site_dir = &context.site;
+    let static_dir = &context.static_dir;
+    let port = context.port;
+
+    // Tailwind finds the utilities pages use by scanning the build directory.
+    if args.memory && context.tailwind.is_some() {
+        return Err("--memory can't be used with Tailwind, which reads the build directory".into());
+    }
+    let site = if args.memory {
+        Site::Memory(output::Memory::default())
+    } else {
+        // Create build directory if it doesn't exist
+        fs_err::create_dir_all(&context.build).expect("Failed to create build directory");
+        Site::Disk(PathBuf::from(&context.build))
+    };
+
+    let options = gen::BuildOptions {
+        inject_reload: true,
+        keep_going: false,
+        release: false,
+        minify: false,
+        pretty: false,
+        reproducible: false,
+        no_cache: false,
+        timings: false,
+        // One bad fragment shouldn't take the whole page down while writing it.
+        lenient: true,
+    };
+    let mut state = match &site {
+        Site::Memory(memory) => gen::Incremental::in_memory(memory.clone()),
+        Site::Disk(_) => gen::Incremental::default(),
+    };
+    // Pages are told about each build so they can reload or show what went wrong.
+    let live = Arc::new(Live::new());
+
+    // Do initial build
+    let result = gen::process_all_files(&context, options, &mut state, &plugins)
+        .map(|()| live::Refresh::Reload);
+    if let Err(e) = &result {
+        error!("Error processing files: {e}");
+    }
+    live.built(&result);
+    // Read once per build, rather than on every request.
+    let redirect_rules = Arc::new(RwLock::new(site.redirect_rules()));
+
+    let (builds, watcher) = watch(Arc::clone(&context), options, state, plugins, {
+        let live = Arc::clone(&live);
+        let site = site.clone();
+        let redirect_rules = Arc::clone(&redirect_rules);
+        move |result| {
+            *redirect_rules.write().unwrap() = site.redirect_rules();
+            live.built(result)
+        }
+    })?;
+
+    // Set up the router
+    let app = match &site {
+        // Serve the build directory as the root, with the site's
+        // own 404 page for anything missing, like a static host.
+        Site::Disk(build) => Router::new().nest_service(
+            "/",
+            ServeDir::new(build).not_found_service(ServeFile::new(build.join("404.html"))),
+        ),
+        Site::Memory(memory) => {
+            Router::new().nest_service("/", get(serve_memory).with_state(memory.clone()))
+        }
+    };
+    let mut app = app
+        // Inside `clean_url`, so it sees the file a path was resolved to.
+        .layer(middleware::from_fn_with_state(site.clone(), revalidate))
+        .layer(middleware::from_fn_with_state(site.clone(), clean_url))
+        .layer(middleware::from_fn_with_state(redirect_rules, redirect));
+    if args.log {
+        // Inside compression, so sizes are the uncompressed ones.
+        app = app.layer(
+            TraceLayer::new_for_http()
+                .make_span_with(|request: &Request| {
+                    tracing::info_span!("request", method = %request.method(), path = %request.uri())
+                })
+                .on_request(())
+                .on_response(|response: &Response, latency: Duration, _: &tracing::Span| {
+                    let size = response
+                        .headers()
+                        .get(header::CONTENT_LENGTH)
+                        .and_then(|size| size.to_str().ok())
+                        .unwrap_or("-");
+                    tracing::info!(status = response.status().as_u16(), size = %size, ?latency);
+                }),
+        );
+    }
+    let mut app = app
+        .layer(CompressionLayer::new().br(true).gzip(true))
+        .layer(SetResponseHeaderLayer::overriding(
+            // Browsers may keep files, but must check they're current before using them.
+            HeaderName::from_static("cache-control"),
+            HeaderValue::from_static("no-cache"),
+        ));
+    // Configured headers come last, so they can override the ones above.
+    for (name, value) in &context.headers {
+        let name =
+            HeaderName::try_from(name).with_context(|| format!("Invalid header {name:?}"))?;
+        let value = HeaderValue::try_from(value)
+            .with_context(|| format!("Invalid value for header {name}"))?;
+        app = app.layer(SetResponseHeaderLayer::overriding(name, value));
+    }
+    // WebSocket route for hot reload
+    let app = app.route("/ws", get(ws_handler)).route(
+        "/__corvusite/rebuild",
+        // Lets other tools, like a CMS, rebuild the site without touching its files.
+        post({
+            let builds = builds.clone();
+            move || async move {
+                match builds.send(Build::Everything) {
+                    Ok(()) => StatusCode::ACCEPTED,
+                    Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+                }
+            }
+        }),
+    );
+    // Covers the WebSocket too, so reload messages stay private.
+    let app = match &args.auth {
+        Some(credentials) => app.layer(middleware::from_fn_with_state(
+            Arc::new(format!("Basic {}", BASE64_STANDARD.encode(credentials))),
+            basic_auth,
+        )),
+        None => app,
+    }
+    .with_state(Arc::clone(&live));
+
+    // Start the server
+    let listener = bind(args.host, port)?;
+    let addr = listener.local_addr()?;
+    let port = addr.port();
+    let lan: Vec<_> = lan::addresses(args.host)
+        .into_iter()
+        .filter(|ip| !ip.is_loopback())
+        .collect();
+    let files = args.tls_cert.as_deref().zip(args.tls_key.as_deref());
+    let tls = if args.tls || files.is_some() {
+        let names: Vec<_> = ["localhost".to_string(), args.host.to_string()]
+            .into_iter()
+            .chain(lan.iter().map(IpAddr::to_string))
+            .collect();
+        Some(tls::config(files, &names).await?)
+    } else {
+        None
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+
+    info!("Server running on {scheme}://{addr}");
+    let urls: Vec<_> = lan
+        .into_iter()
+        .map(|ip| format!("{scheme}://{}", SocketAddr::new(ip, port)))
+        .collect();
+    for url in &urls {
+        info!("  On your network: {url}");
+    }
+    info!("  Static files directory: {static_dir}");
+    info!("  HTML files directory: {site_dir}");
+    match &site {
+        Site::Disk(build) => info!("  Build directory: {}", build.display()),
+        Site::Memory(_) => info!("  Build directory: none, the site is kept in memory"),
+    }
+
+    if args.qr {
+        match urls.first() {
+            // Kept off stdout, which may be carrying JSON logs.
+            Some(url) => eprintln!("{}", lan::qr(url)?),
+            None => warn!("No network address to show a QR code for; try --host 0.0.0.0"),
+        }
+    }
+
+    // A failed build has nothing worth opening yet; the page
+    // can be opened by hand once the error is fixed.
+    if args.open && result.is_ok() {
+        let host = match args.host.is_unspecified() {
+            true => IpAddr::from([127, 0, 0, 1]),
+            false => args.host,
+        };
+        let url = format!("{scheme}://{}", SocketAddr::new(host, port));
+        if let Err(e) = open(&url) {
+            warn!("Failed to open {url}: {e}");
+        }
+    }
+
+    // On ctrl-c, pages are told to let go of their connections
+    // so that the server can finish the requests it's serving.
+    let shutdown = async move {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("Shutting down");
+        live.close();
+    };
+    match tls {
+        Some(tls) => {
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                async move {
+                    shutdown.await;
+                    handle.graceful_shutdown(None);
+                }
+            });
+            axum_server::from_tcp_rustls(listener, tls)
+                .handle(handle)
+                .serve(app.into_make_service())
+                .await?
+        }
+        None => {
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            axum::serve(listener, app.into_make_service())
+                .with_graceful_shutdown(shutdown)
+                .await?;
+        }
+    }
+
+    // A build in progress, or queued, still finishes, so the
+    // build directory is never left behind half-written.
+    builds.send(Build::Stop).unwrap_or(());
+    if tokio::task::spawn_blocking(move || watcher.join())
+        .await
+        .is_err()
+    {
+        error!("The watcher thread panicked");
+    }
+
+    Ok(())
+}
+
+/// Watch the site's sources on a thread of its own, rebuilding what they
+/// affect and passing each build's result to `built`.
+///
+/// Returns the channel the thread takes work from, and the thread itself.
+fn watch(
+    context: Arc<Config>,
+    options: gen::BuildOptions,
+    mut state: gen::Incremental,
+    plugins: plugin::Plugins,
+    built: impl Fn(&anyhow::Result<live::Refresh>) + Send + 'static,
+) -> anyhow::Result<(std::sync::mpsc::Sender<Build>, std::thread::JoinHandle<()>)> {
+    let ignore = context.watch.matcher(std::env::current_dir()?)?;
+    // Builds write beside the build directory too, and mustn't trigger
+    // more builds when it's somewhere being watched.
+    let outputs = output::outputs(&std::path::absolute(&context.build)?)?;
+
+    // Changes are built on the watcher thread, so batches arriving
+    // mid-build queue up there rather than starting builds of their own.
+    let (builds, rx) = std::sync::mpsc::channel();
+
+    // Set up file watcher for HTML directory
+    let watcher = std::thread::spawn({
+        let tx = builds.clone();
+
+        move || {
+            let mut watcher = new_debouncer(
+                Duration::from_millis(context.watch.debounce_ms),
+                None,
+                move |res: DebounceEventResult| match res {
+                    Ok(events) => {
+                        let changed: Vec<_> = events
+                            .iter()
+                            .filter(|e| {
+                                matches!(
+                                    e.kind,
+                                    EventKind::Modify(_)
+                                        | EventKind::Create(_)
+                                        | EventKind::Remove(_)
+                                )
+                            })
+                            .flat_map(|e| e.paths.iter())
+                            .filter(|p| !outputs.iter().any(|o| p.starts_with(o)))
+                            .filter(|p| !is_ignored(&ignore, p))
+                            .cloned()
+                            .collect();
+
+                        if !changed.is_empty() {
+                            tx.send(Build::Changed(changed)).unwrap_or(());
+                        }
+                    }
+                    Err(e) => error!("Watch error: {e:?}"),
+                },
+            )
+            .unwrap();
+
+            // Watch both HTML and static directories
+            watcher
+                .watch(Path::new(&context.site), RecursiveMode::Recursive)
+                .unwrap();
+
+            fs_err::create_dir_all(&context.static_dir).unwrap();
+            watcher
+                .watch(Path::new(&context.static_dir), RecursiveMode::Recursive)
+                .unwrap();
+
+            // Data files are optional.
+            if Path::new(&context.data).is_dir() {
+                watcher
+                    .watch(Path::new(&context.data), RecursiveMode::Recursive)
+                    .unwrap();
+            }
+
+            for path in &context.watch.paths {
+                if let Err(e) = watcher.watch(path, RecursiveMode::Recursive) {
+                    warn!("Failed to watch {path:?}: {e}");
+                }
+            }
+
+            let mut pending: Option<live::Refresh> = None;
+            let mut next = None;
+            while let Some(build) = next.take().or_else(|| rx.recv().ok()) {
+                let mut everything = false;
+                let mut stop = false;
+                let mut changed = Vec::new();
+                for build in std::iter::once(build).chain(rx.try_iter()) {
+                    match build {
+                        Build::Changed(paths) => changed.extend(paths),
+                        Build::Everything => everything = true,
+                        Build::Stop => stop = true,
+                    }
+                }
+                changed.sort();
+                changed.dedup();
+                if !everything && changed.is_empty() {
+                    // Only a stop was asked for.
+                    break;
+                }
+
+                let result = if everything {
+                    gen::process_all_files(&context, options, &mut state, &plugins)
+                        .map(|()| live::Refresh::Reload)
+                } else {
+                    gen::rebuild(&context, options, &mut state, &changed, &plugins)
+                };
+                match result {
+                    Ok(refresh) => {
+                        pending = Some(pending.map_or(refresh, |p| p.and(refresh)));
+                    }
+                    Err(e) => {
+                        error!("Error processing files: {e}");
+                        pending = None;
+                        built(&Err(e));
+                    }
+                }
+
+                // Pages only reload once the changes have settled, rather
+                // than after every batch of a long run of them.
+                match rx.try_recv() {
+                    Ok(more) => next = Some(more),
+                    Err(_) => {
+                        if let Some(refresh) = pending.take() {
+                            built(&Ok(refresh));
+                        }
+                    }
+                }
+
+                // Stopping waits for the build it arrived with, not for any
+                // changes that came in after.
+                if stop {
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok((builds, watcher))
+}
+
+/// Listen on `port`, or the first free port after it when it's taken.
+fn bind(host: IpAddr, port: u16) -> std::io::Result<std::net::TcpListener> {
+    let mut candidate = port;
+    loop {
+        match std::net::TcpListener::bind((host, candidate)) {
+            Ok(listener) => {
+                if candidate != port {
+                    warn!("Port {port} is in use, so using {candidate} instead");
+                }
+                listener.set_nonblocking(true)?;
+                return Ok(listener);
+            }
+            Err(e)
+                if e.kind() == std::io::ErrorKind::AddrInUse
+                    && candidate < port.saturating_add(100) =>
+            {
+                candidate += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Open `url` in the default browser.
+fn open(url: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Turn away requests without the expected `Authorization` header.
+async fn basic_auth(
+    axum::extract::State(expected): axum::extract::State<Arc<String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .map(|v| v.as_bytes())
+        .unwrap_or_default();
+
+    // Hashes compare in constant time, so the comparison can't leak the credentials.
+    if blake3::hash(given) == blake3::hash(expected.as_bytes()) {
+        next.run(request).await
+    } else {
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="corvusite""#)],
+        )
+            .into_response()
+    }
+}
+
+/// Where the server finds the built site.
+#[derive(Clone)]
+enum Site {
+    /// The build directory.
+    Disk(PathBuf),
+    /// A build kept in memory by `serve --memory`.
+    Memory(output::Memory),
+}
+
+impl Site {
+    async fn is_file(&self, path: &str) -> bool {
+        match self {
+            Site::Disk(build) => tokio::fs::metadata(build.join(path))
+                .await
+                .is_ok_and(|m| m.is_file()),
+            Site::Memory(memory) => memory.get(Path::new(path)).is_some(),
+        }
+    }
+
+    /// The build's redirect rules, or none if it has no `_redirects`.
+    fn redirect_rules(&self) -> String {
+        let data = match self {
+            Site::Disk(build) => std::fs::read(build.join(redirects::FILE)).ok(),
+            Site::Memory(memory) => memory
+                .get(Path::new(redirects::FILE))
+                .map(|data| data.to_vec()),
+        };
+        data.map(|data| String::from_utf8_lossy(&data).into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// The file a request for `path` is served from, relative to the build,
+/// or `None` if it would leave it.
+fn site_path(path: &str) -> Option<PathBuf> {
+    let path = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+    let mut relative = PathBuf::from(path.trim_start_matches('/'));
+    if path.ends_with('/') {
+        relative.push("index.html");
+    }
+    relative
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)))
+        .then_some(relative)
+}
+
+/// Serve an in-memory build the way `ServeDir` serves the build directory,
+/// tagging files with a hash of their contents in place of a modification time.
+async fn serve_memory(
+    axum::extract::State(memory): axum::extract::State<output::Memory>,
+    request: Request,
+) -> Response {
+    let relative = site_path(request.uri().path());
+    let Some(data) = relative.as_ref().and_then(|path| memory.get(path)) else {
+        return match memory.get(Path::new("404.html")) {
+            Some(page) => (
+                StatusCode::NOT_FOUND,
+                [(header::CONTENT_TYPE, "text/html")],
+                page.to_vec(),
+            )
+                .into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        };
+    };
+
+    let etag = conditional::etag(&data);
+    let current = request
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| conditional::matches(v, &etag));
+    if current {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    let mime = mime_guess::from_path(relative.unwrap_or_default()).first_or_octet_stream();
+    (
+        [
+            (header::CONTENT_TYPE, mime.to_string()),
+            (header::ETAG, etag),
+        ],
+        data.to_vec(),
+    )
+        .into_response()
+}
+
+/// Answer requests for moved pages with real redirects rather than the
+/// meta refresh stubs written for static hosts.
+async fn redirect(
+    axum::extract::State(rules): axum::extract::State<Arc<RwLock<String>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let to = redirects::lookup(&rules.read().unwrap(), request.uri().path()).map(str::to_owned);
+
+    match to {
+        Some(to) => (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, to)]).into_response(),
+        None => next.run(request).await,
+    }
+}
+
+/// Work for the watcher thread.
+enum Build {
+    /// Rebuild what these files affect.
+    Changed(Vec<PathBuf>),
+    /// Rebuild the whole site.
+    Everything,
+    /// Finish any builds queued before this, then stop watching.
+    Stop,
+}
+
+/// Tag files in the build directory with an `ETag` from their metadata
+/// and answer requests for unchanged ones with `304 Not Modified`.
+/// In-memory builds are tagged by [`serve_memory`].
+///
+/// `Last-Modified` only has a resolution of a second, which a quick
+/// rebuild can fall within, so the file service isn't left to answer
+/// `If-Modified-Since` itself.
+async fn revalidate(
+    axum::extract::State(site): axum::extract::State<Site>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Site::Disk(build) = site else {
+        return next.run(request).await;
+    };
+    request.headers_mut().remove(header::IF_MODIFIED_SINCE);
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+
+    // Read before the file is, so a rebuild in between can't tag old
+    // contents as new.
+    let metadata = match site_path(request.uri().path()) {
+        Some(path) => tokio::fs::metadata(build.join(path)).await.ok(),
+        None => None,
+    };
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let Some(etag) = metadata.as_ref().and_then(conditional::file_etag) else {
+        return response;
+    };
+
+    let current = if_none_match
+        .as_ref()
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| conditional::matches(v, &etag));
+    let Ok(etag) = HeaderValue::from_str(&etag) else {
+        return response;
+    };
+    if current {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    response.headers_mut().insert(header::ETAG, etag);
+    response
+}
+
+/// Resolve extensionless paths the way static hosts do, redirecting
+/// `/about` to `/about/` when there's an `about/index.html`, so relative
+/// links resolve against the directory, or serving `about.html`.
+async fn clean_url(
+    axum::extract::State(site): axum::extract::State<Site>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    if !path.ends_with('/') && Path::new(path).extension().is_none() {
+        let query = request
+            .uri()
+            .query()
+            .map(|q| format!("?{q}"))
+            .unwrap_or_default();
+        let directory = format!("{path}/index.html");
+        if site.is_file(directory.trim_start_matches('/')).await {
+            return (
+                StatusCode::PERMANENT_REDIRECT,
+                [(header::LOCATION, format!("{path}/{query}"))],
+            )
+                .into_response();
+        }
+
+        let file = format!("{path}.html");
+        if site.is_file(file.trim_start_matches('/')).await {
+            if let Ok(uri) = format!("{file}{query}").parse() {
+                *request.uri_mut() = uri;
+            }
+        }
+    }
+
+    next.run(request).await
+}
+
+// WebSocket handler for live reload
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    axum::extract::State(live): axum::extract::State<Arc<Live>>,
+) -> impl IntoResponse {
+    ws.on_upgrade(|socket| handle_ws_client(socket, live))
+}
+
+async fn handle_ws_client(mut socket: WebSocket, live: Arc<Live>) {
+    let (error, mut rx) = live.subscribe();
+
+    // Pages loaded after a failed build still show the failure.
+    if let Some(error) = error {
+        if socket.send(Message::Text(error.to_json())).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        let message = tokio::select! {
+            message = rx.recv() => match message {
+                Ok(message) => message,
+                Err(_) => break,
+            },
+            () = live.closed() => {
+                let _ = socket.send(Message::Close(None)).await;
+                break;
+            }
+        };
+        if matches!(message, live::Message::Reload) {
+            debug!("Sent reload");
+        }
+        if socket.send(Message::Text(message.to_json())).await.is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use crate::diagnostics::location;
+use crate::output::Output;
+use foldhash::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use wincomp::Document;
+
+/// The links and anchors of an expanded page.
+pub struct Page {
+    /// Where the page is written, relative to the build directory.
+    pub path: PathBuf,
+    pub links: Vec<Link>,
+    pub ids: HashSet<String>,
+}
+
+pub struct Link {
+    pub href: String,
+    /// The one-based line and column of the link in the page's source,
+    /// if it was written there rather than by a component.
+    pub location: Option<(usize, usize)>,
+}
+
+impl Page {
+    /// Collect the links and anchors of `document`, locating links in
+    /// `source`, the markup it was parsed from, when there is one.
+    pub fn collect(path: PathBuf, document: &Document, source: Option<&str>) -> Self {
+        let links = document
+            .select("a[href], Link[href]")
+            .filter_map(|e| {
+                let href = e.attribute("href")?;
+                let location = source.and_then(|source| Some(location(source, e.offset?)));
+                Some(Link {
+                    href: href.to_owned(),
+                    location,
+                })
+            })
+            .collect();
+        let ids = document
+            .select("[id]")
+            .filter_map(|e| e.attribute("id"))
+            .map(str::to_owned)
+            .collect();
+
+        Self { path, links, ids }
+    }
+}
+
+/// A link whose target isn't part of the build.
+pub struct BrokenLink {
+    pub href: String,
+    pub message: &'static str,
+    pub location: Option<(usize, usize)>,
+}
+
+/// Check `page`'s internal links against the files in `output`, and their
+/// fragments against `anchors`, the ids of each page by relative path.
+pub fn check(
+    output: &Output,
+    page: &Page,
+    anchors: &HashMap<PathBuf, HashSet<String>>,
+) -> Vec<BrokenLink> {
+    let base = crate::sitemap::page_url(&page.path);
+    let mut broken = Vec::new();
+
+    for Link { href, location } in &page.links {
+        let Some((path, fragment)) = resolve(&base, href) else {
+            continue;
+        };
+
+        let Some(target) = target(output, &path) else {
+            broken.push(BrokenLink {
+                href: href.clone(),
+                message: "no such page",
+                location: *location,
+            });
+            continue;
+        };
+
+        // Only pages expanded by some build have known anchors.
+        let ids = anchors.get(&target);
+        if let (Some(fragment), Some(ids)) = (fragment, ids) {
+            if !ids.contains(fragment) {
+                broken.push(BrokenLink {
+                    href: href.clone(),
+                    message: "no such anchor",
+                    location: *location,
+                });
+            }
+        }
+    }
+
+    broken
+}
+
+/// The absolute path and fragment an internal `href` on the page at
+/// `base` points to, or `None` if it leads off the site.
+fn resolve<'a>(base: &str, href: &'a str) -> Option<(String, Option<&'a str>)> {
+    let scheme = href
+        .find(':')
+        .is_some_and(|i| !href[..i].contains(['/', '?', '#']));
+    if href.is_empty() || href == "#" || scheme || href.starts_with("//") {
+        return None;
+    }
+
+    let (href, fragment) = match href.split_once('#') {
+        Some((href, fragment)) => (href, Some(fragment)),
+        None => (href, None),
+    };
+    let href = href.split('?').next().unwrap_or_default();
+
+    let joined = if href.is_empty() {
+        base.to_owned()
+    } else if href.starts_with('/') {
+        href.to_owned()
+    } else {
+        format!("{}{href}", &base[..=base.rfind('/')?])
+    };
+
+    // Normalize `.` and `..` segments, keeping any trailing slash.
+    let mut segments = Vec::new();
+    for segment in joined.split('/').skip(1) {
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut path = format!("/{}", segments.join("/"));
+    if matches!(joined.rsplit('/').next(), Some("." | "..")) && !path.ends_with('/') {
+        path.push('/');
+    }
+
+    Some((path, fragment))
+}
+
+/// The file in `output` serving `path`, relative to the build directory.
+fn target(output: &Output, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    let index = relative.join("index.html");
+    let is_file = |path: &Path| output.is_file(&output.root().join(path));
+
+    if path.ends_with('/') {
+        is_file(&index).then_some(index)
+    } else if is_file(relative) {
+        Some(relative.to_owned())
+    } else {
+        is_file(&index).then_some(index)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolves_internal_links() {
+        let resolve = |base, href| resolve(base, href).map(|(p, f)| (p, f.map(String::from)));
+
+        assert_eq!(resolve("/blog/a/", "https://example.com"), None);
+        assert_eq!(resolve("/blog/a/", "mailto:me@example.com"), None);
+        assert_eq!(resolve("/blog/a/", "#"), None);
+        assert_eq!(
+            resolve("/blog/a/", "#top"),
+            Some(("/blog/a/".into(), Some("top".into())))
+        );
+        assert_eq!(
+            resolve("/blog/a/", "../b/?page=2#intro"),
+            Some(("/blog/b/".into(), Some("intro".into())))
+        );
+        assert_eq!(
+            resolve("/about.html", "img/me.png"),
+            Some(("/img/me.png".into(), None))
+        );
+        assert_eq!(resolve("/blog/a/", ".."), Some(("/blog/".into(), None)));
+    }
+
+    #[test]
+    fn locates_links() {
+        let source = "<main>\n  <a href=\"/\">Home</a>\n</main>";
+        let document = Document::new(source).unwrap();
+        let mut generated = Document::new("<a href=\"/\">Home</a>").unwrap();
+        wincomp::element::unlocate(&mut generated.nodes);
+
+        let page = Page::collect("index.html".into(), &document, Some(source));
+        assert_eq!(page.links[0].href, "/");
+        assert_eq!(page.links[0].location, Some((2, 3)));
+
+        let page = Page::collect("index.html".into(), &generated, Some(source));
+        assert_eq!(page.links[0].location, None);
+    }
+}
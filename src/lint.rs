@@ -0,0 +1,238 @@
+use crate::config::LintConfig;
+use anyhow::{bail, Error};
+use foldhash::HashSet;
+use markcomp::pull::Frontmatter;
+use std::path::{Path, PathBuf};
+use wincomp::element::{Element, Node};
+
+/// A problem found in a post's prose.
+pub struct Lint {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+pub struct Linter<'a> {
+    config: &'a LintConfig,
+    dictionary: Option<HashSet<String>>,
+    banned: Vec<String>,
+}
+
+/// The readable text of a rendered post.
+#[derive(Default)]
+struct Prose {
+    headings: Vec<String>,
+    text: String,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(config: &'a LintConfig) -> Result<Self, Error> {
+        let dictionary = match &config.dictionary {
+            Some(path) => {
+                let words = fs_err::read_to_string(path)?;
+                let words = words
+                    .lines()
+                    .chain(config.allow.iter().map(String::as_str))
+                    .map(|w| w.trim().to_lowercase())
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                Some(words)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            config,
+            dictionary,
+            banned: config.banned.iter().map(|b| b.to_lowercase()).collect(),
+        })
+    }
+
+    /// Check a post's frontmatter and rendered body.
+    pub fn check(
+        &self,
+        path: &Path,
+        frontmatter: &Frontmatter,
+        body: &[u8],
+    ) -> Result<Vec<Lint>, Error> {
+        let mut lints = Vec::new();
+        let mut report = |message: String| {
+            lints.push(Lint {
+                path: path.to_owned(),
+                message,
+            })
+        };
+
+        if self.config.require_description && frontmatter.description.trim().is_empty() {
+            report("Missing description".into());
+        }
+
+        let body = std::str::from_utf8(body)?;
+        let document = match wincomp::Document::new(body) {
+            Ok(d) => d,
+            Err(e) => bail!("Error linting {path:?}: {e}"),
+        };
+
+        let mut prose = Prose::default();
+        collect_prose(&document.nodes, &mut prose, None);
+
+        if let Some(max) = self.config.max_heading_length {
+            for heading in &prose.headings {
+                let length = heading.chars().count();
+                if length > max {
+                    report(format!(
+                        "Heading \"{heading}\" is {length} characters long (max {max})"
+                    ));
+                }
+            }
+        }
+
+        let text = prose.text.to_lowercase();
+        for phrase in &self.banned {
+            if text.contains(phrase.as_str()) {
+                report(format!("Banned phrase \"{phrase}\""));
+            }
+        }
+
+        if let Some(dictionary) = &self.dictionary {
+            let mut misspelled = Vec::new();
+            for word in words(&text) {
+                if !dictionary.contains(word) && !misspelled.contains(&word) {
+                    misspelled.push(word);
+                }
+            }
+
+            for word in misspelled {
+                report(format!("Unknown word \"{word}\""));
+            }
+        }
+
+        Ok(lints)
+    }
+}
+
+fn collect_prose(nodes: &[Node<'_>], prose: &mut Prose, mut heading: Option<&mut String>) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => {
+                if let Some(heading) = heading.as_deref_mut() {
+                    heading.push_str(text);
+                }
+                prose.text.push_str(text);
+            }
+            Node::Element(element) => collect_element(element, prose, heading.as_deref_mut()),
+            Node::Comment(_) | Node::Raw(_) => {}
+        }
+    }
+}
+
+fn collect_element(element: &Element<'_>, prose: &mut Prose, heading: Option<&mut String>) {
+    match element.name.as_ref() {
+        "code" | "pre" | "script" | "style" => {}
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let mut text = String::new();
+            collect_prose(&element.children, prose, Some(&mut text));
+            prose.headings.push(text.trim().to_owned());
+            prose.text.push(' ');
+        }
+        _ => {
+            collect_prose(&element.children, prose, heading);
+            prose.text.push(' ');
+        }
+    }
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphabetic() && c != '\'')
+        .map(|w| w.trim_matches('\''))
+        .filter(|w| !w.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lint(config: &LintConfig, frontmatter: &str, body: &str) -> Vec<String> {
+        let frontmatter = markcomp::pull::frontmatter(&format!("---\n{frontmatter}\n---\n"))
+            .unwrap()
+            .unwrap();
+        Linter::new(config)
+            .unwrap()
+            .check(Path::new("post.md"), &frontmatter, body.as_bytes())
+            .unwrap()
+            .into_iter()
+            .map(|lint| lint.message)
+            .collect()
+    }
+
+    #[test]
+    fn requires_descriptions() {
+        let config = LintConfig {
+            require_description: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            lint(&config, "title: Post\ndescription: \"  \"", "<p>Text</p>"),
+            ["Missing description"]
+        );
+        assert!(lint(&config, "title: Post\ndescription: A post", "<p>Text</p>").is_empty());
+    }
+
+    #[test]
+    fn limits_heading_length() {
+        let config = LintConfig {
+            max_heading_length: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            lint(
+                &config,
+                "title: Post",
+                "<h2>Short</h2><h3>Far too <em>long</em></h3><p>Any length is fine here</p>"
+            ),
+            ["Heading \"Far too long\" is 12 characters long (max 10)"]
+        );
+    }
+
+    #[test]
+    fn bans_phrases_outside_code() {
+        let config = LintConfig {
+            banned: vec!["Simply".into(), "obviously".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            lint(
+                &config,
+                "title: Post",
+                "<p>SIMPLY run it.</p><pre><code>obviously()</code></pre>"
+            ),
+            ["Banned phrase \"simply\""]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_words_once() {
+        let dictionary =
+            std::env::temp_dir().join(format!("corvusite-lint-{}.txt", std::process::id()));
+        fs_err::write(&dictionary, "the\ncat\nsat\n").unwrap();
+        let config = LintConfig {
+            dictionary: Some(dictionary.clone()),
+            allow: vec!["Rust".into()],
+            ..Default::default()
+        };
+        let lints = lint(
+            &config,
+            "title: Post",
+            "<h1>The cat</h1><p>The cat sat on Rust, the catt's catt.</p><code>ignored</code>",
+        );
+        fs_err::remove_file(&dictionary).unwrap();
+
+        assert_eq!(
+            lints,
+            [
+                "Unknown word \"on\"",
+                "Unknown word \"catt's\"",
+                "Unknown word \"catt\""
+            ]
+        );
+    }
+}
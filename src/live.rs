@@ -0,0 +1,211 @@
+use crate::diagnostics::Diagnostic;
+use serde::Serialize;
+use std::sync::Mutex;
+use tokio::sync::{broadcast, watch};
+
+/// Connects to the dev server and follows its messages, reloading after
+/// a successful build and showing an overlay over the page after a failed one.
+///
+/// Reloads keep the page's scroll position and which `<details>` are open.
+/// If the server goes away, the script keeps trying to reconnect, backing
+/// off exponentially, and reloads once the server is back.
+pub const SCRIPT: &str = r##"
+        <script>
+            // Kept out of the global scope, where pages' own scripts live.
+            (() => {
+                const saved = `corvusite-view:${location.pathname}`;
+                const view = JSON.parse(sessionStorage.getItem(saved));
+                if (view) {
+                    sessionStorage.removeItem(saved);
+                    document.querySelectorAll("details").forEach((details, i) => {
+                        details.open = view.open.includes(i);
+                    });
+                    // Images and fonts can still change the layout until the page loads.
+                    window.scrollTo(view.x, view.y);
+                    window.addEventListener("load", () => window.scrollTo(view.x, view.y));
+                }
+
+                const reload = () => {
+                    const open = [];
+                    document.querySelectorAll("details").forEach((details, i) => {
+                        if (details.open) open.push(i);
+                    });
+                    sessionStorage.setItem(saved, JSON.stringify({ x: scrollX, y: scrollY, open }));
+                    location.reload();
+                };
+
+                const connect = (delay, reconnecting) => {
+                    const scheme = location.protocol === "https:" ? "wss" : "ws";
+                    const ws = new WebSocket(`${scheme}://${location.host}/ws`);
+                    ws.onopen = () => {
+                        delay = 250;
+                        // The site may have changed while the server was down.
+                        if (reconnecting) reload();
+                    };
+                    ws.onclose = () => {
+                        setTimeout(() => connect(Math.min(delay * 2, 10000), true), delay);
+                    };
+                    ws.onmessage = receive;
+                };
+
+                const receive = (event) => {
+                    const message = JSON.parse(event.data);
+                    if (message.kind === "reload") {
+                        reload();
+                    } else if (message.kind === "css") {
+                        // A fresh query string makes the browser fetch each stylesheet again.
+                        for (const link of document.querySelectorAll('link[rel="stylesheet"]')) {
+                            const url = new URL(link.href);
+                            if (url.origin === location.origin) {
+                                url.searchParams.set("t", Date.now());
+                                link.href = url;
+                            }
+                        }
+                        document.getElementById("corvusite-error")?.remove();
+                    } else if (message.kind === "error") {
+                        let overlay = document.getElementById("corvusite-error");
+                        if (!overlay) {
+                            overlay = document.createElement("div");
+                            overlay.id = "corvusite-error";
+                            overlay.style.cssText = "position: fixed; inset: 0; z-index: 2147483647; overflow: auto; padding: 2rem; background: rgba(20, 20, 20, 0.95); color: #f0f0f0; font: 14px/1.5 monospace;";
+                            document.body.append(overlay);
+                        }
+                        const place = document.createElement("div");
+                        place.style.color = "#ff7b72";
+                        place.textContent = message.place || "Build failed";
+                        const text = document.createElement("pre");
+                        text.style.whiteSpace = "pre-wrap";
+                        text.textContent = message.message;
+                        const snippet = document.createElement("pre");
+                        snippet.style.color = "#8b949e";
+                        snippet.textContent = message.snippet || "";
+                        overlay.replaceChildren(place, text, snippet);
+                    }
+                };
+
+                connect(250, false);
+            })();
+        </script>
+    "##;
+
+/// How open pages pick up a successful build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refresh {
+    Reload,
+    /// Only stylesheets changed, so pages can swap them in place
+    /// and keep their scroll position and form state.
+    Styles,
+}
+
+impl Refresh {
+    /// How to pick up both this build's changes and `other`'s at once.
+    pub fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (Refresh::Styles, Refresh::Styles) => Refresh::Styles,
+            _ => Refresh::Reload,
+        }
+    }
+}
+
+/// A message for the pages open in the browser.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Message {
+    Reload,
+    Css,
+    /// The last build failed, and pages are stale until the next one succeeds.
+    Error {
+        place: String,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        snippet: Option<String>,
+    },
+}
+
+impl Message {
+    pub fn error(error: &anyhow::Error) -> Self {
+        let diagnostic = Diagnostic::from_error(error);
+        Self::Error {
+            place: diagnostic.place(),
+            message: diagnostic.message,
+            snippet: diagnostic.snippet,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Messages should serialize")
+    }
+}
+
+/// Tells connected pages about each build, remembering a
+/// failure so that pages loaded afterwards can show it too.
+pub struct Live {
+    tx: broadcast::Sender<Message>,
+    error: Mutex<Option<Message>>,
+    /// Set once the server is shutting down.
+    closing: watch::Sender<bool>,
+}
+
+impl Live {
+    pub fn new() -> Self {
+        Self {
+            tx: broadcast::channel(16).0,
+            error: Mutex::default(),
+            closing: watch::channel(false).0,
+        }
+    }
+
+    /// Tell every connection to close, as the server is shutting down.
+    pub fn close(&self) {
+        self.closing.send_replace(true);
+    }
+
+    /// Wait until the server starts shutting down.
+    pub async fn closed(&self) {
+        let _ = self.closing.subscribe().wait_for(|closing| *closing).await;
+    }
+
+    /// Report the result of a build.
+    pub fn built(&self, result: &anyhow::Result<Refresh>) {
+        let message = match result {
+            Ok(Refresh::Reload) => Message::Reload,
+            Ok(Refresh::Styles) => Message::Css,
+            Err(e) => Message::error(e),
+        };
+
+        // Held while sending, so subscribers never miss or repeat a message.
+        let mut error = self.error.lock().unwrap();
+        *error = matches!(message, Message::Error { .. }).then(|| message.clone());
+        self.tx.send(message).unwrap_or(0);
+    }
+
+    /// Subscribe to messages, starting with the current failure, if any.
+    pub fn subscribe(&self) -> (Option<Message>, broadcast::Receiver<Message>) {
+        let error = self.error.lock().unwrap();
+        (error.clone(), self.tx.subscribe())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_failures_until_fixed() {
+        let live = Live::new();
+        live.built(&Err(Diagnostic::error("site/index.html", "Unclosed <div>")
+            .at(2, 3)
+            .into()));
+
+        let (current, _) = live.subscribe();
+        assert_eq!(
+            current.unwrap().to_json(),
+            r#"{"kind":"error","place":"site/index.html:2:3","message":"Unclosed <div>"}"#
+        );
+
+        let (_, mut rx) = live.subscribe();
+        live.built(&Ok(Refresh::Styles));
+        assert!(live.subscribe().0.is_none());
+        assert_eq!(rx.try_recv().unwrap().to_json(), r#"{"kind":"css"}"#);
+    }
+}
@@ -0,0 +1,579 @@
+use foldhash::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Built files by their path relative to the build directory.
+type Files = HashMap<PathBuf, Arc<[u8]>>;
+
+/// A build kept in memory rather than written to disk, which the
+/// server can answer requests from directly.
+#[derive(Clone, Default)]
+pub struct Memory(Arc<RwLock<Files>>);
+
+impl Memory {
+    /// The file at `path`, relative to the build directory.
+    pub fn get(&self, path: &Path) -> Option<Arc<[u8]>> {
+        self.0.read().unwrap().get(path).cloned()
+    }
+}
+
+/// The build directory, or a staged copy of it.
+///
+/// Files whose contents haven't changed are left untouched, so
+/// their modification times only move when they really change.
+pub struct Output {
+    root: PathBuf,
+    /// The directory a staged build replaces when committed.
+    target: Option<PathBuf>,
+    /// Every file produced by the current build, changed or not.
+    produced: Mutex<HashSet<PathBuf>>,
+    /// Files changed or removed by the current build, relative to the root.
+    touched: Mutex<HashSet<PathBuf>>,
+    /// Files the stage was behind the target in before this build.
+    stale: Vec<PathBuf>,
+    /// The files of an in-memory build, and where they're committed to.
+    memory: Option<(Memory, Mutex<Files>)>,
+}
+
+impl Output {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            target: None,
+            produced: Mutex::default(),
+            touched: Mutex::default(),
+            stale: Vec::new(),
+            memory: None,
+        }
+    }
+
+    /// Stage a build in memory, starting from the files in `memory`,
+    /// which are only replaced once the build is committed.
+    ///
+    /// Paths still live under `root`, though nothing is written there.
+    pub fn in_memory(root: impl AsRef<Path>, memory: &Memory) -> std::io::Result<Self> {
+        let files = memory.0.read().unwrap().clone();
+        let mut output = Self::new(std::path::absolute(root)?);
+        output.memory = Some((memory.clone(), Mutex::new(files)));
+        Ok(output)
+    }
+
+    /// Stage a build in a sibling of the directory at `target`, which is
+    /// only replaced once the build is committed, so a failed build never
+    /// leaves it half-written.
+    ///
+    /// The stage is kept between builds, holding the one before the
+    /// current build, so only the files that build touched are hard
+    /// linked in again. Anything else, like a build that was interrupted,
+    /// starts the stage over from a link of every file.
+    pub fn stage(target: impl AsRef<Path>) -> std::io::Result<Self> {
+        let target = std::path::absolute(target)?;
+        let (root, current) = buffers(&target)?;
+
+        // Taken now, so a build that never records what it touched
+        // leaves the next one to start over.
+        let pending = sibling(&target, "pending")?;
+        let stale = match fs_err::read_to_string(&pending) {
+            Ok(list) => {
+                fs_err::remove_file(&pending)?;
+                Some(list.lines().map(PathBuf::from).collect::<Vec<_>>())
+            }
+            Err(_) => None,
+        };
+
+        let stale = match (stale, &current) {
+            (Some(stale), Some(current)) if root.is_dir() => {
+                for path in &stale {
+                    sync(&current.join(path), &root.join(path))?;
+                }
+                stale
+            }
+            _ => {
+                if root.exists() {
+                    fs_err::remove_dir_all(&root)?;
+                }
+                fs_err::create_dir_all(&root)?;
+                if let Some(current) = &current {
+                    link_all(current, &root)?;
+                }
+                Vec::new()
+            }
+        };
+
+        let mut output = Self::new(root);
+        output.target = Some(target);
+        output.stale = stale;
+        Ok(output)
+    }
+
+    /// Swap a staged build into place.
+    pub fn commit(self) -> std::io::Result<()> {
+        if let Some((memory, files)) = self.memory {
+            *memory.0.write().unwrap() = files.into_inner().unwrap();
+            return Ok(());
+        }
+        let Some(target) = &self.target else {
+            return Ok(());
+        };
+
+        swap(&self.root, target)?;
+        // What was the target is now the stage, behind in what this build touched.
+        let touched = self.touched.into_inner().unwrap();
+        record(target, touched.iter())
+    }
+
+    /// Throw away a staged build, leaving the target as it was.
+    pub fn abandon(self) {
+        if let Some(target) = &self.target {
+            // The stage is behind in what it already was, and ahead in
+            // what this build touched.
+            let touched = self.touched.into_inner().unwrap();
+            let _ = record(target, self.stale.iter().chain(&touched));
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where `path` in this build will be once it's committed, for
+    /// messages and anything else that outlives the stage.
+    pub fn logical(&self, path: &Path) -> PathBuf {
+        match (&self.target, path.strip_prefix(&self.root)) {
+            (Some(target), Ok(relative)) => target.join(relative),
+            _ => path.to_owned(),
+        }
+    }
+
+    /// Note that this build changed or removed the file at `path`.
+    fn touch(&self, path: &Path) {
+        if let Ok(relative) = path.strip_prefix(&self.root) {
+            self.touched.lock().unwrap().insert(relative.to_owned());
+        }
+    }
+
+    /// Where `path` is kept in memory, if the build is.
+    fn files(&self, path: &Path) -> Option<(&Mutex<Files>, PathBuf)> {
+        let (_, files) = self.memory.as_ref()?;
+        let key = path.strip_prefix(&self.root).unwrap_or(path);
+        Some((files, key.to_owned()))
+    }
+
+    /// Keep `contents` at `key` in memory, returning whether it changed.
+    fn remember(files: &Mutex<Files>, key: PathBuf, contents: &[u8]) -> bool {
+        let mut files = files.lock().unwrap();
+        if files
+            .get(&key)
+            .is_some_and(|existing| **existing == *contents)
+        {
+            return false;
+        }
+        files.insert(key, contents.into());
+        true
+    }
+
+    /// Read a file produced by this or an earlier build.
+    pub fn read(&self, path: impl AsRef<Path>) -> std::io::Result<Vec<u8>> {
+        let path = path.as_ref();
+        let Some((files, key)) = self.files(path) else {
+            return fs_err::read(path);
+        };
+        match files.lock().unwrap().get(&key) {
+            Some(contents) => Ok(contents.to_vec()),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{path:?} isn't in the build"),
+            )),
+        }
+    }
+
+    pub fn read_to_string(&self, path: impl AsRef<Path>) -> std::io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn is_file(&self, path: &Path) -> bool {
+        match self.files(path) {
+            Some((files, key)) => files.lock().unwrap().contains_key(&key),
+            None => path.is_file(),
+        }
+    }
+
+    /// Remove the file or directory at `path`, if any.
+    pub fn remove(&self, path: &Path) -> std::io::Result<()> {
+        if let Some((files, key)) = self.files(path) {
+            files.lock().unwrap().retain(|p, _| !p.starts_with(&key));
+            return Ok(());
+        }
+
+        self.touch(path);
+        if path.is_dir() {
+            fs_err::remove_dir_all(path)
+        } else {
+            unlink(path)
+        }
+    }
+
+    /// Write `contents` to `path`, returning whether the file changed.
+    pub fn write(
+        &self,
+        path: impl AsRef<Path>,
+        contents: impl AsRef<[u8]>,
+    ) -> std::io::Result<bool> {
+        let path = path.as_ref();
+        let contents = contents.as_ref();
+        self.produced.lock().unwrap().insert(path.to_owned());
+        if let Some((files, key)) = self.files(path) {
+            return Ok(Self::remember(files, key, contents));
+        }
+
+        if fs_err::read(path).is_ok_and(|existing| existing == contents) {
+            return Ok(false);
+        }
+
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        self.touch(path);
+        unlink(path)?;
+        fs_err::write(path, contents)?;
+
+        Ok(true)
+    }
+
+    /// Copy `source` to `path` unless the copy already matches in size
+    /// and is at least as new, returning whether the file was copied.
+    pub fn copy(&self, source: &Path, path: &Path) -> std::io::Result<bool> {
+        self.produced.lock().unwrap().insert(path.to_owned());
+        if let Some((files, key)) = self.files(path) {
+            return Ok(Self::remember(files, key, &fs_err::read(source)?));
+        }
+
+        let metadata = fs_err::metadata(source)?;
+        if let Ok(existing) = std::fs::metadata(path) {
+            if existing.len() == metadata.len() && existing.modified()? >= metadata.modified()? {
+                return Ok(false);
+            }
+        }
+
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        self.touch(path);
+        unlink(path)?;
+        fs_err::copy(source, path)?;
+
+        Ok(true)
+    }
+
+    /// Every file produced so far.
+    pub fn produced(&self) -> Vec<PathBuf> {
+        self.produced.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Treat `path` as no longer produced, so it's removed when pruning.
+    pub fn discard(&self, path: &Path) {
+        self.produced.lock().unwrap().remove(path);
+    }
+
+    /// Remove every file not produced by this build, along with any
+    /// directories left empty, returning the number of files removed.
+    pub fn prune(&self) -> std::io::Result<usize> {
+        let produced = self.produced.lock().unwrap();
+        if let Some((_, files)) = &self.memory {
+            let mut files = files.lock().unwrap();
+            let before = files.len();
+            files.retain(|path, _| produced.contains(&self.root.join(path)));
+            return Ok(before - files.len());
+        }
+        let mut removed = 0;
+
+        for entry in walkdir::WalkDir::new(&self.root).contents_first(true) {
+            let entry = entry?;
+            let path = entry.path();
+
+            if entry.file_type().is_dir() {
+                if path != self.root && fs_err::read_dir(path)?.next().is_none() {
+                    fs_err::remove_dir(path)?;
+                }
+            } else if !produced.contains(path) {
+                self.touch(path);
+                fs_err::remove_file(path)?;
+                removed += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// A hidden directory beside `dir`, like `.build.a` for `build`.
+pub fn sibling(dir: &Path, suffix: &str) -> std::io::Result<PathBuf> {
+    let name = dir.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("The build directory {dir:?} has no name"),
+        )
+    })?;
+    Ok(dir.with_file_name(format!(".{}.{suffix}", name.to_string_lossy())))
+}
+
+/// Every path a build of `target` writes to, the target included.
+pub fn outputs(target: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut outputs = ["a", "b", "staging", "old", "link", "pending"]
+        .into_iter()
+        .map(|suffix| sibling(target, suffix))
+        .collect::<Result<Vec<_>, _>>()?;
+    outputs.push(target.to_owned());
+    Ok(outputs)
+}
+
+/// The directory to stage a build of `target` in, and the one holding
+/// the current build, if any.
+///
+/// Builds alternate between two directories, with `target` a link to
+/// the current one, so they can be swapped without it going missing.
+#[cfg(unix)]
+fn buffers(target: &Path) -> std::io::Result<(PathBuf, Option<PathBuf>)> {
+    let (a, b) = (sibling(target, "a")?, sibling(target, "b")?);
+    let current = match std::fs::read_link(target) {
+        Ok(link) => target.with_file_name(link),
+        // A build directory from before builds were swapped by link.
+        Err(_) => return Ok((a, target.is_dir().then(|| target.to_owned()))),
+    };
+    let stage = if current == a { b } else { a };
+    Ok((stage, current.is_dir().then_some(current)))
+}
+
+#[cfg(not(unix))]
+fn buffers(target: &Path) -> std::io::Result<(PathBuf, Option<PathBuf>)> {
+    Ok((
+        sibling(target, "staging")?,
+        target.is_dir().then(|| target.to_owned()),
+    ))
+}
+
+/// Make the staged build at `root` the target, leaving the previous
+/// build at `root`, if there was one.
+#[cfg(unix)]
+fn swap(root: &Path, target: &Path) -> std::io::Result<()> {
+    let link = sibling(target, "link")?;
+    unlink(&link)?;
+    let name = root.file_name().expect("staged builds are named");
+    fs_err::os::unix::fs::symlink(name, &link)?;
+
+    if target.is_symlink() || !target.exists() {
+        // Renaming over a link replaces it in one step.
+        return fs_err::rename(&link, target);
+    }
+
+    // A build directory from before builds were swapped by link is
+    // briefly missing, just this once.
+    let old = sibling(target, "old")?;
+    if old.exists() {
+        fs_err::remove_dir_all(&old)?;
+    }
+    fs_err::rename(target, &old)?;
+    fs_err::rename(&link, target)?;
+    fs_err::remove_dir_all(&old)
+}
+
+#[cfg(not(unix))]
+fn swap(root: &Path, target: &Path) -> std::io::Result<()> {
+    let old = sibling(target, "old")?;
+    if old.exists() {
+        fs_err::remove_dir_all(&old)?;
+    }
+    // The target is briefly missing between the renames, but never incomplete.
+    if target.exists() {
+        fs_err::rename(target, &old)?;
+    }
+    fs_err::rename(root, target)?;
+    if old.exists() {
+        fs_err::rename(&old, root)?;
+    }
+    Ok(())
+}
+
+/// Note which files the stage of `target` is behind in, for the next
+/// build to catch up on.
+fn record<'a>(target: &Path, paths: impl Iterator<Item = &'a PathBuf>) -> std::io::Result<()> {
+    let mut list = String::new();
+    for path in paths {
+        // Paths that can't be listed leave the next build to start over.
+        let Some(path) = path.to_str().filter(|p| !p.contains('\n')) else {
+            return Ok(());
+        };
+        list.push_str(path);
+        list.push('\n');
+    }
+    fs_err::write(sibling(target, "pending")?, list)
+}
+
+/// Bring `to` up to date with `from`, which may have been removed.
+fn sync(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(to) {
+        Ok(metadata) if metadata.is_dir() => fs_err::remove_dir_all(to)?,
+        Ok(_) => fs_err::remove_file(to)?,
+        Err(_) => {}
+    }
+
+    if from.is_dir() {
+        fs_err::create_dir_all(to)?;
+        link_all(from, to)
+    } else if from.is_file() {
+        if let Some(parent) = to.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        link(from, to)
+    } else {
+        Ok(())
+    }
+}
+
+/// Hard link every file in `from` into `to`.
+fn link_all(from: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in walkdir::WalkDir::new(from).min_depth(1) {
+        let entry = entry?;
+        let path = to.join(entry.path().strip_prefix(from).unwrap());
+        if entry.file_type().is_dir() {
+            fs_err::create_dir_all(&path)?;
+        } else {
+            link(entry.path(), &path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hard link `from` to `to`, or copy it where links aren't supported.
+fn link(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::hard_link(from, to).is_err() {
+        fs_err::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Remove the file at `path`, if any, rather than writing through a hard
+/// link into the build it was staged from.
+fn unlink(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_changes_and_prunes_orphans() {
+        let root = std::env::temp_dir().join(format!("corvusite-output-{}", std::process::id()));
+        let _ = fs_err::remove_dir_all(&root);
+        fs_err::create_dir_all(root.join("old")).unwrap();
+        fs_err::write(root.join("old/page.html"), "stale").unwrap();
+        fs_err::write(root.join("same.html"), "same").unwrap();
+
+        let output = Output::new(&root);
+        assert!(!output.write(root.join("same.html"), "same").unwrap());
+        assert!(output.write(root.join("new/page.html"), "new").unwrap());
+        assert_eq!(output.prune().unwrap(), 1);
+
+        assert!(root.join("same.html").exists());
+        assert!(root.join("new/page.html").exists());
+        assert!(!root.join("old").exists());
+
+        fs_err::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn commits_staged_builds() {
+        let root = std::env::temp_dir().join(format!("corvusite-stage-{}", std::process::id()));
+        let _ = fs_err::remove_dir_all(&root);
+        fs_err::create_dir_all(&root).unwrap();
+        fs_err::write(root.join("page.html"), "old").unwrap();
+
+        let output = Output::stage(&root).unwrap();
+        output
+            .write(output.root().join("page.html"), "new")
+            .unwrap();
+        assert_eq!(
+            fs_err::read_to_string(root.join("page.html")).unwrap(),
+            "old"
+        );
+        output.abandon();
+        assert_eq!(
+            fs_err::read_to_string(root.join("page.html")).unwrap(),
+            "old"
+        );
+
+        let output = Output::stage(&root).unwrap();
+        output
+            .write(output.root().join("page.html"), "new")
+            .unwrap();
+        output.commit().unwrap();
+        assert_eq!(
+            fs_err::read_to_string(root.join("page.html")).unwrap(),
+            "new"
+        );
+
+        // The next stage catches up on the page, and the one after on the
+        // pages an abandoned build touched.
+        let output = Output::stage(&root).unwrap();
+        assert_eq!(
+            output.logical(&output.root().join("page.html")),
+            root.join("page.html")
+        );
+        assert_eq!(
+            output
+                .read_to_string(output.root().join("page.html"))
+                .unwrap(),
+            "new"
+        );
+        output
+            .write(output.root().join("draft.html"), "draft")
+            .unwrap();
+        output.remove(&output.root().join("page.html")).unwrap();
+        output.abandon();
+
+        let output = Output::stage(&root).unwrap();
+        assert!(!output.is_file(&output.root().join("draft.html")));
+        output
+            .write(output.root().join("other.html"), "other")
+            .unwrap();
+        output.commit().unwrap();
+        assert_eq!(
+            fs_err::read_to_string(root.join("page.html")).unwrap(),
+            "new"
+        );
+        assert_eq!(
+            fs_err::read_to_string(root.join("other.html")).unwrap(),
+            "other"
+        );
+
+        for path in outputs(&root).unwrap() {
+            let _ = fs_err::remove_dir_all(&path).or_else(|_| fs_err::remove_file(&path));
+        }
+    }
+
+    #[test]
+    fn keeps_builds_in_memory() {
+        let root = std::env::temp_dir().join(format!("corvusite-memory-{}", std::process::id()));
+        let memory = Memory::default();
+
+        let output = Output::in_memory(&root, &memory).unwrap();
+        assert!(output.write(root.join("old.html"), "old").unwrap());
+        output.commit().unwrap();
+
+        let output = Output::in_memory(&root, &memory).unwrap();
+        assert!(output.write(root.join("new.html"), "new").unwrap());
+        assert_eq!(output.read_to_string(root.join("old.html")).unwrap(), "old");
+        assert_eq!(output.prune().unwrap(), 1);
+        assert!(memory.get(Path::new("new.html")).is_none());
+        output.commit().unwrap();
+
+        assert!(memory.get(Path::new("old.html")).is_none());
+        assert_eq!(&*memory.get(Path::new("new.html")).unwrap(), b"new");
+        assert!(!root.exists());
+    }
+}
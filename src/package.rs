@@ -0,0 +1,120 @@
+use anyhow::{anyhow, bail, Context, Error};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const MANIFEST: &str = "corvusite-package.toml";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+}
+
+/// A component library bundled by `corvusite pack`.
+#[derive(Debug)]
+pub struct Package {
+    pub manifest: Manifest,
+    pub components: Vec<String>,
+    pub css: Vec<u8>,
+}
+
+fn is_packable(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.ends_with(".mod.html") || path.ends_with(".css")
+}
+
+/// Bundle every component and stylesheet under `dir` into
+/// `{out_dir}/{name}-{version}.tar.gz`, returning the archive's path.
+pub fn pack(dir: &Path, name: &str, version: &str, out_dir: &Path) -> Result<PathBuf, Error> {
+    let manifest = toml::to_string(&Manifest {
+        name: name.to_owned(),
+        version: version.to_owned(),
+    })?;
+
+    fs_err::create_dir_all(out_dir)?;
+    let out_path = out_dir.join(format!("{name}-{version}.tar.gz"));
+    let file = fs_err::File::create(&out_path)?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let mut append = |path: &Path, data: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        // Leave the mtime zeroed so identical inputs produce identical archives.
+        header.set_mtime(0);
+        archive.append_data(&mut header, path, data)
+    };
+
+    append(Path::new(MANIFEST), manifest.as_bytes())?;
+
+    let mut count = 0;
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || !is_packable(path) {
+            continue;
+        }
+
+        let data = fs_err::read(path)?;
+        append(path.strip_prefix(dir)?, &data)?;
+        count += 1;
+    }
+
+    if count == 0 {
+        bail!("No components or stylesheets found in {dir:?}");
+    }
+
+    archive.into_inner()?.finish()?;
+
+    Ok(out_path)
+}
+
+impl Package {
+    pub fn load(path: &Path, version: Option<&str>) -> Result<Self, Error> {
+        let file = fs_err::File::open(path)?;
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+        let mut manifest = None;
+        let mut components = Vec::new();
+        let mut css = Vec::new();
+
+        for entry in archive
+            .entries()
+            .with_context(|| format!("Failed to read package {path:?}"))?
+        {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.to_path_buf();
+
+            if entry_path == Path::new(MANIFEST) {
+                let mut raw = String::new();
+                entry.read_to_string(&mut raw)?;
+                manifest = Some(toml::from_str::<Manifest>(&raw)?);
+            } else if entry_path.to_string_lossy().ends_with(".mod.html") {
+                let mut raw = String::new();
+                entry.read_to_string(&mut raw)?;
+                components.push(raw);
+            } else if entry_path.to_string_lossy().ends_with(".css") {
+                entry.read_to_end(&mut css)?;
+            }
+        }
+
+        let manifest = manifest.ok_or(anyhow!("Package {path:?} has no manifest"))?;
+
+        if let Some(version) = version {
+            if manifest.version != version {
+                bail!(
+                    "Package {path:?} is version {}, but {version} was requested",
+                    manifest.version
+                );
+            }
+        }
+
+        Ok(Self {
+            manifest,
+            components,
+            css,
+        })
+    }
+}
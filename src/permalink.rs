@@ -0,0 +1,53 @@
+use anyhow::{bail, Error};
+
+/// The permalink posts use unless configured otherwise.
+pub const DEFAULT: &str = "/blog/{slug}/";
+
+/// Fill in a permalink pattern like `/{year}/{month}/{slug}/`
+/// with a post's date and slug.
+pub fn expand(pattern: &str, date: jiff::civil::Date, slug: &str) -> Result<String, Error> {
+    if !pattern.starts_with('/') || !pattern.ends_with('/') {
+        bail!("Permalink {pattern:?} must start and end with `/`");
+    }
+
+    let mut url = String::with_capacity(pattern.len() + slug.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        url.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            bail!("Unclosed placeholder in permalink {pattern:?}");
+        };
+        match &rest[start + 1..start + end] {
+            "year" => url.push_str(&date.year().to_string()),
+            "month" => url.push_str(&format!("{:02}", date.month())),
+            "day" => url.push_str(&format!("{:02}", date.day())),
+            "slug" => url.push_str(slug),
+            other => bail!(
+                "Unknown placeholder {{{other}}} in permalink {pattern:?}, \
+                expected one of {{year}}, {{month}}, {{day}}, or {{slug}}"
+            ),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    url.push_str(rest);
+
+    Ok(url)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expands_placeholders() {
+        let date = jiff::civil::date(2024, 5, 3);
+
+        assert_eq!(expand(DEFAULT, date, "hello").unwrap(), "/blog/hello/");
+        assert_eq!(
+            expand("/{year}/{month}/{day}/{slug}/", date, "hello").unwrap(),
+            "/2024/05/03/hello/"
+        );
+        assert!(expand("/{title}/", date, "hello").is_err());
+        assert!(expand("/{slug}.html", date, "hello").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::output::Output;
+use anyhow::{Context, Error};
+use std::path::Path;
+use wincomp::transform::Transforms;
+use wincomp::Component;
+
+/// Custom transforms run at each stage of a build.
+///
+/// Every hook does nothing by default, so plugins only implement the ones they need.
+pub trait Plugin: Send + Sync {
+    /// A name for error messages.
+    fn name(&self) -> &str;
+
+    /// Called once for each component, site or packaged, before any page is expanded.
+    fn on_component_loaded(&self, _name: &str, _component: &mut Component) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called once per build to add passes run over every expanded page,
+    /// like `transforms.add("img", ...)`, after those of earlier plugins.
+    fn register_transforms(&self, _transforms: &mut Transforms) {}
+
+    /// Called with each finished page's markup, already minified or indented as the
+    /// build asks, just before it's written to `path`.
+    fn on_page_rendered(&self, _path: &Path, _page: &mut String) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called once every page and `output.css` have been written.
+    fn on_assets_written(&self, _output: &Output) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The plugins registered for a build, run in registration order.
+///
+/// Plugins are registered by collecting them, like
+/// `[Box::new(MyPlugin) as Box<dyn Plugin>].into_iter().collect()`,
+/// and passed to [`crate::run`] from a binary of your own.
+#[derive(Default)]
+pub struct Plugins(Vec<Box<dyn Plugin>>);
+
+impl FromIterator<Box<dyn Plugin>> for Plugins {
+    fn from_iter<I: IntoIterator<Item = Box<dyn Plugin>>>(plugins: I) -> Self {
+        Self(plugins.into_iter().collect())
+    }
+}
+
+impl Plugins {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn component_loaded(&self, name: &str, component: &mut Component) -> Result<(), Error> {
+        for plugin in &self.0 {
+            plugin
+                .on_component_loaded(name, component)
+                .with_context(|| {
+                    format!("Plugin {:?} failed on component {name}", plugin.name())
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Every plugin's transforms, in registration order.
+    pub fn transforms(&self) -> Transforms {
+        let mut transforms = Transforms::default();
+        for plugin in &self.0 {
+            plugin.register_transforms(&mut transforms);
+        }
+        transforms
+    }
+
+    pub fn page_rendered(&self, path: &Path, page: &mut String) -> Result<(), Error> {
+        for plugin in &self.0 {
+            plugin
+                .on_page_rendered(path, page)
+                .with_context(|| format!("Plugin {:?} failed on page {path:?}", plugin.name()))?;
+        }
+        Ok(())
+    }
+
+    pub fn assets_written(&self, output: &Output) -> Result<(), Error> {
+        for plugin in &self.0 {
+            plugin.on_assets_written(output).with_context(|| {
+                format!("Plugin {:?} failed after writing assets", plugin.name())
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds `rel="noopener"` to external links opened in a new tab, so the
+/// pages they open don't get a handle on this one.
+///
+/// Browsers imply it for `target="_blank"` these days, so it's only
+/// added when registered, like any other plugin.
+pub struct Noopener;
+
+impl Plugin for Noopener {
+    fn name(&self) -> &str {
+        "noopener"
+    }
+
+    fn register_transforms(&self, transforms: &mut Transforms) {
+        transforms.add("a[target=_blank][href]", |a| {
+            let external = a
+                .attribute("href")
+                .is_some_and(|href| href.starts_with("http") || href.starts_with("//"));
+            if !external {
+                return;
+            }
+            match a.attribute("rel") {
+                None | Some("") => a.set_attribute("rel", Some("noopener".into())),
+                Some(rel) if !rel.split_ascii_whitespace().any(|r| r == "noopener") => {
+                    let rel = format!("{rel} noopener");
+                    a.set_attribute("rel", Some(rel.into()))
+                }
+                Some(_) => {}
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Banner;
+
+    impl Plugin for Banner {
+        fn name(&self) -> &str {
+            "banner"
+        }
+
+        fn on_page_rendered(&self, path: &Path, page: &mut String) -> Result<(), Error> {
+            if path.ends_with("broken.html") {
+                anyhow::bail!("no banner for you");
+            }
+            *page = page.replacen("<body>", "<body><p>Preview</p>", 1);
+            Ok(())
+        }
+
+        fn register_transforms(&self, transforms: &mut Transforms) {
+            transforms.add("img", |img| {
+                img.set_attribute("loading", Some("lazy".into()))
+            });
+        }
+    }
+
+    #[test]
+    fn runs_hooks_in_order() {
+        let plugins: Plugins = [Box::new(Banner) as Box<dyn Plugin>, Box::new(Banner)]
+            .into_iter()
+            .collect();
+
+        let mut page = "<html><body></body></html>".to_string();
+        plugins
+            .page_rendered(Path::new("index.html"), &mut page)
+            .unwrap();
+        assert_eq!(
+            page,
+            "<html><body><p>Preview</p><p>Preview</p></body></html>"
+        );
+
+        let mut component = Component::new("<Card><div></div></Card>").unwrap();
+        plugins.component_loaded("Card", &mut component).unwrap();
+
+        let error = plugins
+            .page_rendered(Path::new("broken.html"), &mut page)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"Plugin "banner" failed on page "broken.html""#
+        );
+    }
+
+    #[test]
+    fn registers_transforms() {
+        let source = r#"<a href="https://example.com" target="_blank">A</a><a href="/" target="_blank">B</a><a href="//example.com" target="_blank" rel="me">C</a><img src="a.png">"#;
+        let render = |plugins: Plugins| {
+            let mut document = wincomp::Document::new(source).unwrap();
+            plugins.transforms().apply(&mut document);
+
+            let mut output = Vec::new();
+            document.write_fragment(&mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let plugins = [Box::new(Banner) as Box<dyn Plugin>].into_iter().collect();
+        assert_eq!(
+            render(plugins),
+            r#"<a href="https://example.com" target="_blank">A</a><a href="/" target="_blank">B</a><a href="//example.com" target="_blank" rel="me">C</a><img src="a.png" loading="lazy">"#
+        );
+
+        let plugins = [Box::new(Noopener) as Box<dyn Plugin>, Box::new(Banner)]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            render(plugins),
+            r#"<a href="https://example.com" target="_blank" rel="noopener">A</a><a href="/" target="_blank">B</a><a href="//example.com" target="_blank" rel="me noopener">C</a><img src="a.png" loading="lazy">"#
+        );
+    }
+}
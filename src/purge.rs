@@ -0,0 +1,315 @@
+use crate::config::PurgeConfig;
+use crate::output::Output;
+use crate::tags::{tag_end, Tokens};
+use anyhow::Error;
+use foldhash::HashSet;
+
+/// Strip the rules in the build's `output.css` that no generated page uses.
+pub fn run(config: &PurgeConfig, output: &Output) -> Result<(), Error> {
+    let stylesheet = output.root().join("output.css");
+    let Ok(css) = output.read_to_string(&stylesheet) else {
+        return Ok(());
+    };
+
+    let mut used = Used::default();
+    used.keep(&config.safelist);
+    for path in output.produced() {
+        if path.extension().is_some_and(|e| e == "html") {
+            used.scan(&output.read_to_string(&path)?);
+        }
+    }
+
+    output.write(&stylesheet, purge(&css, &used))?;
+    Ok(())
+}
+
+/// The tags, classes, and ids appearing in a set of pages.
+#[derive(Default)]
+pub struct Used {
+    tags: HashSet<String>,
+    classes: HashSet<String>,
+    ids: HashSet<String>,
+}
+
+impl Used {
+    /// Record everything used by the tags in `html`.
+    pub fn scan(&mut self, html: &str) {
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            rest = &rest[start..];
+            if !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+                rest = &rest[1..];
+                continue;
+            }
+
+            let end = tag_end(rest);
+            let inner = rest[1..end].trim_end_matches('>').trim_end_matches('/');
+            rest = &rest[end..];
+
+            let mut tokens = Tokens(inner);
+            if let Some(name) = tokens.next() {
+                self.tags.insert(name.to_ascii_lowercase());
+            }
+            for token in tokens {
+                let Some((name, value)) = token.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim_matches(|c| c == '"' || c == '\'');
+                match name.to_ascii_lowercase().as_str() {
+                    "class" => self
+                        .classes
+                        .extend(value.split_whitespace().map(String::from)),
+                    "id" => {
+                        self.ids.insert(value.to_owned());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Treat `names` as used wherever they appear, for markup added by scripts.
+    pub fn keep(&mut self, names: &[String]) {
+        for name in names {
+            self.tags.insert(name.to_ascii_lowercase());
+            self.classes.insert(name.clone());
+            self.ids.insert(name.clone());
+        }
+    }
+
+    /// Whether some element could match `selector`.
+    fn matches(&self, selector: &str) -> bool {
+        let mut chars = selector.chars().peekable();
+        // Whether the next identifier starts a compound selector, and so names a tag.
+        let mut compound_start = true;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '.' | '#' => {
+                    let name = identifier(&mut chars);
+                    let used = if c == '.' {
+                        self.classes.contains(&name)
+                    } else {
+                        self.ids.contains(&name)
+                    };
+                    if !used {
+                        return false;
+                    }
+                    compound_start = false;
+                }
+                // Attributes and the arguments of `:not()` and friends are assumed to match.
+                '[' | '(' => {
+                    let (open, close) = if c == '[' { ('[', ']') } else { ('(', ')') };
+                    let mut depth = 1;
+                    for c in chars.by_ref() {
+                        if c == open {
+                            depth += 1;
+                        } else if c == close {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                    }
+                    compound_start = false;
+                }
+                ':' => {
+                    if chars.peek() == Some(&':') {
+                        chars.next();
+                    }
+                    identifier(&mut chars);
+                    compound_start = false;
+                }
+                c if c.is_whitespace() || matches!(c, '>' | '+' | '~') => compound_start = true,
+                c if compound_start && (c.is_alphabetic() || c == '-' || c == '_') => {
+                    let mut name = c.to_string();
+                    name.push_str(&identifier(&mut chars));
+                    if !self.tags.contains(&name.to_ascii_lowercase()) {
+                        return false;
+                    }
+                    compound_start = false;
+                }
+                _ => compound_start = false,
+            }
+        }
+
+        true
+    }
+}
+
+/// Read a CSS identifier, resolving escapes like Tailwind's `md\:flex`.
+fn identifier(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut name = String::new();
+    while let Some(&c) = chars.peek() {
+        if c == '\\' {
+            chars.next();
+            name.extend(chars.next());
+        } else if c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii() {
+            name.push(c);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    name
+}
+
+/// Remove the rules in `css` whose selectors can't match anything in `used`.
+///
+/// At-rules with nested rules, like `@media`, are purged recursively
+/// and dropped when left empty. Other at-rules are kept as they are.
+pub fn purge(css: &str, used: &Used) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+
+    loop {
+        rest = skip_trivia(rest);
+        if rest.is_empty() {
+            break;
+        }
+
+        let Some(open) = find_outside_strings(rest, |c| c == '{' || c == ';') else {
+            output.push_str(rest);
+            break;
+        };
+
+        // A statement like `@import url(a.css);`.
+        if rest.as_bytes()[open] == b';' {
+            output.push_str(&rest[..=open]);
+            rest = &rest[open + 1..];
+            continue;
+        }
+
+        let prelude = rest[..open].trim();
+        let close = block_end(&rest[open..]) + open;
+        let block = &rest[open + 1..close.saturating_sub(1).max(open + 1)];
+        let whole = &rest[..close];
+        rest = &rest[close..];
+
+        if let Some(at_rule) = prelude.strip_prefix('@') {
+            let name = at_rule
+                .split(|c: char| !c.is_alphanumeric() && c != '-')
+                .next();
+            if matches!(
+                name,
+                Some("media" | "supports" | "layer" | "container" | "document")
+            ) {
+                let inner = purge(block, used);
+                if !inner.trim().is_empty() {
+                    output.push_str(prelude);
+                    output.push('{');
+                    output.push_str(&inner);
+                    output.push('}');
+                }
+            } else {
+                output.push_str(whole);
+            }
+            continue;
+        }
+
+        let selectors = split_selectors(prelude);
+        if selectors.iter().any(|s| used.matches(s)) {
+            output.push_str(whole);
+        }
+    }
+
+    output
+}
+
+/// Skip whitespace and comments.
+fn skip_trivia(mut css: &str) -> &str {
+    loop {
+        css = css.trim_start();
+        match css.strip_prefix("/*") {
+            Some(comment) => css = comment.split_once("*/").map_or("", |(_, after)| after),
+            None => return css,
+        }
+    }
+}
+
+/// The byte offset of the first character matching `predicate` outside of strings.
+fn find_outside_strings(css: &str, predicate: impl Fn(char) -> bool) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in css.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, c) if predicate(c) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The byte offset just past the `}` closing the block opening `css`.
+fn block_end(css: &str) -> usize {
+    let mut depth = 0;
+    let mut offset = 0;
+    while let Some(i) = find_outside_strings(&css[offset..], |c| c == '{' || c == '}') {
+        let i = offset + i;
+        if css.as_bytes()[i] == b'{' {
+            depth += 1;
+        } else {
+            depth -= 1;
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+        offset = i + 1;
+    }
+    css.len()
+}
+
+/// Split a selector list on its top-level commas.
+fn split_selectors(prelude: &str) -> Vec<&str> {
+    let mut selectors = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in prelude.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                selectors.push(prelude[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    selectors.push(prelude[start..].trim());
+    selectors
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn purges_unused_rules() {
+        let mut used = Used::default();
+        used.scan(r#"<!DOCTYPE html><body><nav id="top" class="nav md:flex"><a href="/">Home</a></nav></body>"#);
+        used.keep(&["open".into()]);
+
+        let css = r#"
+            /* layout */
+            body { margin: 0 }
+            .nav > a:hover, .sidebar { color: red }
+            .sidebar .item { content: "}" }
+            #top::before, table { display: none }
+            .nav.open { display: block }
+            @media (min-width: 768px) { .md\:flex { display: flex } .unused { display: none } }
+            @media print { .unused { color: black } }
+            @font-face { font-family: "A"; src: url(a.woff2) }
+            a:not(.external) { text-decoration: none }
+            h1 { font-size: 2rem }
+        "#;
+
+        assert_eq!(
+            purge(css, &used),
+            r#"body { margin: 0 }.nav > a:hover, .sidebar { color: red }#top::before, table { display: none }.nav.open { display: block }@media (min-width: 768px){.md\:flex { display: flex }}@font-face { font-family: "A"; src: url(a.woff2) }a:not(.external) { text-decoration: none }"#
+        );
+    }
+}
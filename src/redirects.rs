@@ -0,0 +1,58 @@
+use crate::gen::escape_html;
+use std::path::PathBuf;
+
+/// Netlify-style redirect rules, written to the build's root.
+pub const FILE: &str = "_redirects";
+
+/// The stub page served from `from`, relative to the build directory.
+pub fn stub_path(from: &str) -> PathBuf {
+    let relative = from.trim_start_matches('/');
+    if relative.ends_with(".html") {
+        PathBuf::from(relative)
+    } else {
+        PathBuf::from(relative).join("index.html")
+    }
+}
+
+/// A page that immediately sends visitors, and search engines, on to `to`.
+pub fn stub(to: &str) -> String {
+    let to = escape_html(to);
+    format!(
+        r#"<!DOCTYPE html><html lang="en"><head><meta charset="utf-8"><title>Redirecting…</title><link rel="canonical" href="{to}"><meta http-equiv="refresh" content="0; url={to}"></head><body><a href="{to}">Redirecting to {to}</a></body></html>"#
+    )
+}
+
+/// Render `_redirects`, with one permanent redirect per line.
+pub fn rules<'a>(redirects: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    redirects
+        .into_iter()
+        .map(|(from, to)| format!("{from} {to} 301\n"))
+        .collect()
+}
+
+/// Where the rules in `rules` send `path`, ignoring trailing slashes.
+pub fn lookup<'a>(rules: &'a str, path: &str) -> Option<&'a str> {
+    rules.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let (from, to) = (parts.next()?, parts.next()?);
+        (from.trim_end_matches('/') == path.trim_end_matches('/')).then_some(to)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn writes_and_reads_rules() {
+        assert_eq!(stub_path("/old/"), PathBuf::from("old/index.html"));
+        assert_eq!(stub_path("/old"), PathBuf::from("old/index.html"));
+        assert_eq!(stub_path("/old.html"), PathBuf::from("old.html"));
+
+        let rules = rules([("/old/", "/new/"), ("/feed", "/feed.xml")]);
+        assert_eq!(rules, "/old/ /new/ 301\n/feed /feed.xml 301\n");
+        assert_eq!(lookup(&rules, "/old"), Some("/new/"));
+        assert_eq!(lookup(&rules, "/feed/"), Some("/feed.xml"));
+        assert_eq!(lookup(&rules, "/new/"), None);
+    }
+}
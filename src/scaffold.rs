@@ -0,0 +1,263 @@
+use anyhow::{bail, Error};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const CONFIG: &str = r#"# site = "site"
+# static = "static"
+# build = "build"
+# port = 3000
+# base_url = "https://example.com"
+"#;
+
+const INDEX: &str = r#"<Shell>
+  <h1>Hello, world!</h1>
+  <p>Edit <code>site/index.html</code> to get started, or read the <a href="/blog/">blog</a>.</p>
+  <Ui.Callout>Components live in <code>site/components</code>.</Ui.Callout>
+</Shell>
+"#;
+
+const CSS: &str = r#"body {
+  max-width: 700px;
+  margin: 2rem auto;
+  padding: 0 1rem;
+  font-family: system-ui, sans-serif;
+  line-height: 1.4;
+}
+
+.callout {
+  padding: 0.75rem 1rem;
+  border-left: 3px solid currentColor;
+}
+"#;
+
+const SHELL: &str = r#"<Shell>
+  <html lang="en">
+    <ShellHead></ShellHead>
+    <ShellBody>
+      <children />
+    </ShellBody>
+  </html>
+</Shell>
+"#;
+
+const SHELL_HEAD: &str = r#"<ShellHead title>
+  <head>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+    <If cond="title"><title>{title} | My Site</title><Else /><title>My Site</title></If>
+    <children />
+  </head>
+</ShellHead>
+"#;
+
+const SHELL_BODY: &str = r#"<ShellBody>
+  <body>
+    <header>
+      <nav><a href="/">Home</a> <a href="/blog/">Blog</a></nav>
+    </header>
+    <main>
+      <children />
+    </main>
+  </body>
+</ShellBody>
+"#;
+
+const LINK: &str = r#"<Link href><a href="href"><children /></a></Link>
+"#;
+
+const CALLOUT: &str = r#"<Callout>
+  <aside class="callout">
+    <children />
+  </aside>
+</Callout>
+"#;
+
+const BLOG_SHELL: &str = r#"<BlogShell>
+  <Shell>
+    <h1>Articles</h1>
+    <children />
+  </Shell>
+</BlogShell>
+"#;
+
+const BLOG_CARD: &str = r#"<BlogCard>
+  <div class="blog-card">
+    <children />
+  </div>
+</BlogCard>
+"#;
+
+const BLOG_LINK: &str = r#"<BlogLink href>
+  <a href="href"><children /></a>
+</BlogLink>
+"#;
+
+const BLOG_DATE: &str = r#"<BlogDate>
+  <small><children /></small>
+</BlogDate>
+"#;
+
+const BLOG_DESCRIPTION: &str = r#"<BlogDescription>
+  <p><children /></p>
+</BlogDescription>
+"#;
+
+const FOOTNOTES: &str = r#"<Footnotes>
+  <hr />
+  <ol>
+    <children />
+  </ol>
+</Footnotes>
+"#;
+
+const FOOTNOTE_REF: &str = r#"<FootnoteRef href id>
+  <sup><a href="href" id="id"><children /></a></sup>
+</FootnoteRef>
+"#;
+
+const FOOTNOTE_RET: &str = r#"<FootnoteRet href>
+  <a href="href"> ↩ </a>
+</FootnoteRet>
+"#;
+
+fn post(date: &str) -> String {
+    format!(
+        r#"---
+title: Hello, world
+date: {date}
+description: My first post.
+---
+
+# Hello, world
+
+This post lives in `site/blog/hello-world.md`. Each markdown file in `site/blog`
+becomes a page like `/blog/hello-world/` and is listed on the [blog index](/blog/).
+"#
+    )
+}
+
+fn today() -> Result<String, Error> {
+    Ok(jiff::fmt::strtime::format("%D", jiff::Zoned::now().date())?)
+}
+
+/// Create a new site skeleton in `dir`, refusing to overwrite existing files.
+pub fn init(dir: &Path) -> Result<(), Error> {
+    let post = post(&today()?);
+
+    let files = [
+        ("corvusite.toml", CONFIG),
+        ("site/index.html", INDEX),
+        ("site/index.css", CSS),
+        ("site/components/shell.mod.html", SHELL),
+        ("site/components/shell-head.mod.html", SHELL_HEAD),
+        ("site/components/shell-body.mod.html", SHELL_BODY),
+        ("site/components/link.mod.html", LINK),
+        ("site/components/ui/callout.mod.html", CALLOUT),
+        ("site/blog/components/blog-shell.mod.html", BLOG_SHELL),
+        ("site/blog/components/blog-card.mod.html", BLOG_CARD),
+        ("site/blog/components/blog-link.mod.html", BLOG_LINK),
+        ("site/blog/components/blog-date.mod.html", BLOG_DATE),
+        (
+            "site/blog/components/blog-description.mod.html",
+            BLOG_DESCRIPTION,
+        ),
+        ("site/blog/components/footnotes.mod.html", FOOTNOTES),
+        ("site/blog/components/footnote-ref.mod.html", FOOTNOTE_REF),
+        ("site/blog/components/footnote-ret.mod.html", FOOTNOTE_RET),
+        ("site/blog/hello-world.md", &post),
+    ];
+
+    if let Some((path, _)) = files.iter().find(|(path, _)| dir.join(path).exists()) {
+        bail!("{:?} already exists", dir.join(path));
+    }
+
+    for (path, contents) in files {
+        let path = dir.join(path);
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        fs_err::write(path, contents)?;
+    }
+    fs_err::create_dir_all(dir.join("static"))?;
+
+    Ok(())
+}
+
+/// Lowercase `title`, joining its alphanumeric runs with dashes.
+fn slugify(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Create an empty blog post under `site_dir`, returning its path.
+pub fn new_post(site_dir: &Path, title: &str) -> Result<PathBuf, Error> {
+    let slug = slugify(title);
+    if slug.is_empty() {
+        bail!("Can't derive a file name from the title {title:?}");
+    }
+
+    let dir = site_dir.join("blog");
+    fs_err::create_dir_all(&dir)?;
+    let path = dir.join(format!("{slug}.md"));
+
+    let mut file = match std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            bail!("{path:?} already exists")
+        }
+        Err(e) => return Err(Error::new(e).context(format!("Failed to create {path:?}"))),
+    };
+
+    let title = title.replace('\\', "\\\\").replace('"', "\\\"");
+    write!(
+        file,
+        "---\ntitle: \"{title}\"\ndate: {}\ndescription: \"\"\n---\n\n",
+        today()?
+    )?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slugifies_titles() {
+        assert_eq!(slugify("My Title"), "my-title");
+        assert_eq!(slugify("  Rust: the   Good Parts! "), "rust-the-good-parts");
+        assert_eq!(slugify("Über café"), "über-café");
+        assert_eq!(slugify("?!"), "");
+    }
+
+    #[test]
+    fn titles_pages_after_the_site() {
+        let head = wincomp::Component::new(SHELL_HEAD).unwrap();
+        let title = |page| {
+            let mut document = wincomp::Document::new(page).unwrap();
+            document
+                .expand(|name| (name == "ShellHead").then_some(&head))
+                .unwrap();
+            let mut output = Vec::new();
+            document.write_fragment(&mut output).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            let start = output.find("<title>").unwrap();
+            let end = output.find("</title>").unwrap();
+            output[start..end + "</title>".len()].to_owned()
+        };
+
+        assert_eq!(
+            title(r#"<ShellHead title="First post"></ShellHead>"#),
+            "<title>First post | My Site</title>"
+        );
+        assert_eq!(title("<ShellHead></ShellHead>"), "<title>My Site</title>");
+    }
+}
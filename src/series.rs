@@ -0,0 +1,135 @@
+use crate::gen::escape_html;
+use anyhow::{bail, Error};
+use foldhash::HashMap;
+
+/// An article's place within a series.
+pub struct Part<'a> {
+    pub series: &'a str,
+    pub part: u32,
+    pub title: &'a str,
+    pub url: String,
+    /// Whether the part is on the site, rather than scheduled or a draft.
+    pub published: bool,
+}
+
+/// Generated series markup for a single article.
+#[derive(Default)]
+pub struct Markup {
+    /// A `<SeriesIndex>` listing every part, placed before the article.
+    pub index: String,
+    /// A `<SeriesNav>` linking the neighboring parts, placed after the article.
+    pub nav: String,
+}
+
+/// Link the parts of each series together, returning the markup
+/// for each item of `parts` in order.
+///
+/// Parts must be numbered contiguously from one, counting unpublished
+/// ones, which keep their number but aren't listed or linked to.
+pub fn link(parts: &[Option<Part<'_>>]) -> Result<Vec<Markup>, Error> {
+    let mut series: HashMap<&str, Vec<&Part<'_>>> = HashMap::default();
+    for part in parts.iter().flatten() {
+        series.entry(part.series).or_default().push(part);
+    }
+
+    for (name, parts) in series.iter_mut() {
+        parts.sort_by_key(|p| p.part);
+
+        if parts.first().is_some_and(|p| p.part == 0) {
+            bail!("Series \"{name}\" has a part 0, but parts are numbered from 1");
+        }
+
+        for (expected, part) in (1..).zip(parts.iter()) {
+            if part.part < expected {
+                bail!("Series \"{name}\" has more than one part {}", part.part);
+            } else if part.part > expected {
+                bail!("Series \"{name}\" is missing part {expected}");
+            }
+        }
+    }
+
+    Ok(parts
+        .iter()
+        .map(|part| {
+            let Some(part) = part.as_ref().filter(|p| p.published) else {
+                return Markup::default();
+            };
+
+            let total = series[part.series].len();
+            let siblings: Vec<_> = series[part.series].iter().filter(|p| p.published).collect();
+            let position = siblings
+                .iter()
+                .position(|p| p.part == part.part)
+                .unwrap_or_default();
+            let name = escape_html(part.series);
+
+            let mut index = format!(
+                r#"<SeriesIndex name="{name}" part="{}" total="{total}">"#,
+                part.part
+            );
+            for sibling in &siblings {
+                let current = if sibling.part == part.part {
+                    "page"
+                } else {
+                    "false"
+                };
+                index.push_str(&format!(
+                    r#"<SeriesEntry href="{}" part="{}" current="{current}">{}</SeriesEntry>"#,
+                    sibling.url,
+                    sibling.part,
+                    escape_html(sibling.title),
+                ));
+            }
+            index.push_str("</SeriesIndex>");
+
+            let mut nav = format!(
+                r#"<SeriesNav name="{name}" part="{}" total="{total}">"#,
+                part.part
+            );
+            if let Some(prev) = position.checked_sub(1).map(|i| siblings[i]) {
+                nav.push_str(&format!(
+                    r#"<SeriesPrev href="{}">{}</SeriesPrev>"#,
+                    prev.url,
+                    escape_html(prev.title),
+                ));
+            }
+            if let Some(next) = siblings.get(position + 1) {
+                nav.push_str(&format!(
+                    r#"<SeriesNext href="{}">{}</SeriesNext>"#,
+                    next.url,
+                    escape_html(next.title),
+                ));
+            }
+            nav.push_str("</SeriesNav>");
+
+            Markup { index, nav }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn skips_unpublished_parts() {
+        let part = |number, published| {
+            Some(Part {
+                series: "Rust",
+                part: number,
+                title: "Part",
+                url: format!("/{number}/"),
+                published,
+            })
+        };
+        let markup = link(&[part(1, true), part(2, false), part(3, true)]).unwrap();
+
+        assert!(markup[0].index.contains(r#"total="3""#));
+        assert!(!markup[0].index.contains("/2/"));
+        assert!(markup[0].nav.contains(r#"<SeriesNext href="/3/">"#));
+        assert!(markup[1].index.is_empty());
+        assert!(markup[2].nav.contains(r#"<SeriesPrev href="/1/">"#));
+
+        assert!(link(&[part(1, true), part(3, true)]).is_err());
+    }
+}
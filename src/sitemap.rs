@@ -0,0 +1,124 @@
+use crate::gen::escape_html;
+use anyhow::{bail, Error};
+use jiff::civil::Date;
+use markcomp::pull::Frontmatter;
+use std::fmt::Write;
+
+/// The values the sitemap protocol allows for `changefreq`.
+const CHANGEFREQS: &[&str] = &[
+    "always", "hourly", "daily", "weekly", "monthly", "yearly", "never",
+];
+
+#[derive(Default)]
+pub struct Entry {
+    /// The page's path relative to the site root, like `/blog/my-post/`.
+    pub path: String,
+    pub lastmod: Option<Date>,
+    pub priority: Option<f32>,
+    pub changefreq: Option<String>,
+    /// Pages opted out of the sitemap are still known to exist, for translations.
+    pub hidden: bool,
+}
+
+impl Entry {
+    /// Take the sitemap settings from a page's frontmatter.
+    pub fn with_frontmatter(mut self, frontmatter: &Frontmatter) -> Result<Self, Error> {
+        if let Some(priority) = frontmatter.sitemap_priority {
+            if !(0.0..=1.0).contains(&priority) {
+                bail!("`sitemap_priority` must be between 0.0 and 1.0, not {priority}");
+            }
+        }
+        if let Some(changefreq) = &frontmatter.sitemap_changefreq {
+            if !CHANGEFREQS.contains(&changefreq.as_str()) {
+                bail!(
+                    "`sitemap_changefreq` must be one of {}, not {changefreq:?}",
+                    CHANGEFREQS.join(", ")
+                );
+            }
+        }
+
+        self.priority = frontmatter.sitemap_priority;
+        self.changefreq = frontmatter.sitemap_changefreq.clone();
+        self.hidden = !frontmatter.sitemap;
+        Ok(self)
+    }
+}
+
+/// Render a sitemap listing every entry under `base_url`.
+pub fn sitemap(base_url: &str, entries: &[Entry]) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let mut output = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#,
+    );
+
+    for entry in entries.iter().filter(|e| !e.hidden) {
+        let _ = write!(
+            output,
+            "<url><loc>{}</loc>",
+            escape_html(&format!("{base_url}{}", entry.path))
+        );
+        if let Some(lastmod) = entry.lastmod {
+            let _ = write!(output, "<lastmod>{lastmod}</lastmod>");
+        }
+        if let Some(changefreq) = &entry.changefreq {
+            let _ = write!(output, "<changefreq>{changefreq}</changefreq>");
+        }
+        if let Some(priority) = entry.priority {
+            let _ = write!(output, "<priority>{priority}</priority>");
+        }
+        output.push_str("</url>");
+    }
+
+    output.push_str("</urlset>");
+    output
+}
+
+/// The URL a page at `path` (relative to the build directory) is served from.
+pub fn page_url(path: &std::path::Path) -> String {
+    let segments: Vec<_> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let url = format!("/{}", segments.join("/"));
+
+    match url.strip_suffix("index.html") {
+        Some(directory) => directory.to_owned(),
+        None => url,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn renders_entries() {
+        assert_eq!(page_url(Path::new("index.html")), "/");
+        assert_eq!(page_url(Path::new("about/index.html")), "/about/");
+        assert_eq!(page_url(Path::new("contact.html")), "/contact.html");
+
+        let entries = [
+            Entry {
+                path: "/a/".into(),
+                lastmod: Some(jiff::civil::date(2024, 1, 2)),
+                ..Default::default()
+            },
+            Entry {
+                path: "/b/".into(),
+                priority: Some(0.8),
+                changefreq: Some("weekly".into()),
+                ..Default::default()
+            },
+            Entry {
+                path: "/c/".into(),
+                hidden: true,
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            sitemap("https://example.com/", &entries),
+            r#"<?xml version="1.0" encoding="UTF-8"?><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>https://example.com/a/</loc><lastmod>2024-01-02</lastmod></url><url><loc>https://example.com/b/</loc><changefreq>weekly</changefreq><priority>0.8</priority></url></urlset>"#
+        );
+    }
+}
@@ -0,0 +1,63 @@
+/// The byte offset just past the `>` closing the tag at the start of `input`.
+pub(crate) fn tag_end(input: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in input.char_indices().skip(1) {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+    input.len()
+}
+
+/// Whitespace-separated tokens, keeping quoted values intact.
+pub(crate) struct Tokens<'a>(pub(crate) &'a str);
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0 = self.0.trim_start();
+        if self.0.is_empty() {
+            return None;
+        }
+
+        let mut quote = None;
+        let end = self
+            .0
+            .char_indices()
+            .find(|&(_, c)| match (quote, c) {
+                (Some(q), c) if c == q => {
+                    quote = None;
+                    false
+                }
+                (None, '"' | '\'') => {
+                    quote = Some(c);
+                    false
+                }
+                (None, c) => c.is_whitespace(),
+                _ => false,
+            })
+            .map_or(self.0.len(), |(i, _)| i);
+
+        let (token, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_tags() {
+        let tag = r#"<a href="/a b" class='x y'>text"#;
+        assert_eq!(tag_end(tag), tag.len() - 4);
+
+        let tokens: Vec<_> = Tokens(&tag[1..tag_end(tag) - 1]).collect();
+        assert_eq!(tokens, ["a", r#"href="/a b""#, "class='x y'"]);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::config::TailwindConfig;
+use anyhow::{bail, Context, Error};
+use std::path::Path;
+use std::process::Command;
+
+/// Run the Tailwind CLI over the generated pages in `build_dir`,
+/// returning the stylesheet it prints.
+pub fn run(config: &TailwindConfig, build_dir: &Path) -> Result<Vec<u8>, Error> {
+    let Some((program, base)) = config.command.split_first() else {
+        bail!("`tailwind.command` must name a program");
+    };
+
+    let mut command = Command::new(program);
+    command.args(base);
+    if let Some(input) = &config.input {
+        command.arg("--input").arg(input);
+    }
+    let build = build_dir.to_string_lossy();
+    command.args(config.args.iter().map(|a| a.replace("{build}", &build)));
+
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run Tailwind with {:?}", config.command))?;
+    if !output.status.success() {
+        bail!(
+            "Tailwind exited with {}:\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn captures_stylesheet() {
+        let config = TailwindConfig {
+            command: [
+                "sh",
+                "-c",
+                r#"printf '.p-4{padding:1rem}/* %s */' "$1""#,
+                "sh",
+            ]
+            .map(String::from)
+            .into(),
+            input: None,
+            args: vec!["{build}/**/*.html".into()],
+        };
+
+        assert_eq!(
+            run(&config, Path::new("build")).unwrap(),
+            b".p-4{padding:1rem}/* build/**/*.html */"
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many of each stage's slowest files to list.
+const SLOWEST: usize = 3;
+
+/// How long each stage of a build took, and which files were slowest,
+/// for `build --timings`.
+///
+/// Stages running on several threads add up the time spent on each
+/// file, so their totals can exceed the time the build really took.
+pub struct Timings {
+    /// `None` when timings weren't asked for, so nothing is recorded.
+    stages: Option<Mutex<Vec<Stage>>>,
+}
+
+struct Stage {
+    name: &'static str,
+    total: Duration,
+    files: Vec<(PathBuf, Duration)>,
+}
+
+impl Timings {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            stages: enabled.then(Mutex::default),
+        }
+    }
+
+    /// Time `work` as part of `stage`.
+    pub fn time<T>(&self, stage: &'static str, work: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let value = work();
+        self.record(stage, None, start.elapsed());
+        value
+    }
+
+    /// Add `elapsed` to `stage`, as time spent on `file` if there is one.
+    pub fn record(&self, stage: &'static str, file: Option<&Path>, elapsed: Duration) {
+        let Some(stages) = &self.stages else {
+            return;
+        };
+
+        let mut stages = stages.lock().unwrap();
+        let index = match stages.iter().position(|s| s.name == stage) {
+            Some(index) => index,
+            None => {
+                stages.push(Stage {
+                    name: stage,
+                    total: Duration::ZERO,
+                    files: Vec::new(),
+                });
+                stages.len() - 1
+            }
+        };
+        let stage = &mut stages[index];
+        stage.total += elapsed;
+        if let Some(file) = file {
+            stage.files.push((file.to_owned(), elapsed));
+        }
+    }
+
+    /// A table of the stages in the order they first ran, or `None`
+    /// if timings weren't asked for.
+    pub fn report(&self) -> Option<String> {
+        let stages = self.stages.as_ref()?.lock().unwrap();
+        let width = stages.iter().map(|s| s.name.len()).max().unwrap_or(0);
+
+        let mut report = String::from("Build timings:\n");
+        for stage in stages.iter() {
+            let _ = write!(
+                report,
+                "  {:width$}  {:>10}",
+                stage.name,
+                format!("{:.2?}", stage.total)
+            );
+            if !stage.files.is_empty() {
+                let _ = write!(report, "  ({} files)", stage.files.len());
+            }
+            report.push('\n');
+
+            let mut files: Vec<_> = stage.files.iter().collect();
+            files.sort_by_key(|(_, elapsed)| std::cmp::Reverse(*elapsed));
+            for (file, elapsed) in files.into_iter().take(SLOWEST) {
+                let _ = writeln!(
+                    report,
+                    "  {:width$}  {:>10}  {}",
+                    "",
+                    format!("{elapsed:.2?}"),
+                    file.display()
+                );
+            }
+        }
+        Some(report)
+    }
+
+    /// Print the [`report`](Self::report), if timings were asked for.
+    ///
+    /// Logs can be JSON on stdout, so the table goes to stderr.
+    pub fn print(&self) {
+        if let Some(report) = self.report() {
+            eprint!("{report}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_stages_and_slowest_files() {
+        assert!(Timings::new(false).report().is_none());
+
+        let timings = Timings::new(true);
+        timings.record("static copy", None, Duration::from_millis(5));
+        for (file, ms) in [("a.html", 1), ("b.html", 4), ("c.html", 2), ("d.html", 3)] {
+            timings.record(
+                "expansion",
+                Some(Path::new(file)),
+                Duration::from_millis(ms),
+            );
+        }
+
+        let report = timings.report().unwrap();
+        let lines: Vec<_> = report.lines().map(str::trim).collect();
+        assert_eq!(
+            lines,
+            [
+                "Build timings:",
+                "static copy      5.00ms",
+                "expansion       10.00ms  (4 files)",
+                "4.00ms  b.html",
+                "3.00ms  d.html",
+                "2.00ms  c.html",
+            ]
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cache;
+use anyhow::{Context, Error};
+use axum_server::tls_rustls::RustlsConfig;
+use std::path::{Path, PathBuf};
+
+/// Load the certificate and key in `files`, or a self-signed pair
+/// covering `names` when there are none, for serving over HTTPS.
+pub async fn config(
+    files: Option<(&Path, &Path)>,
+    names: &[String],
+) -> Result<RustlsConfig, Error> {
+    // Only one provider is compiled in, but rustls still wants it chosen.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let (cert, key) = match files {
+        Some((cert, key)) => (cert.to_owned(), key.to_owned()),
+        None => self_signed(&Path::new(cache::DIR).join("tls"), names)?,
+    };
+    RustlsConfig::from_pem_file(&cert, &key)
+        .await
+        .with_context(|| format!("Failed to load the certificate {cert:?} and key {key:?}"))
+}
+
+/// A self-signed certificate for `names` in `dir`, generated when missing
+/// or made for other names. It's kept between runs so that browsers told
+/// to trust it keep trusting it.
+fn self_signed(dir: &Path, names: &[String]) -> Result<(PathBuf, PathBuf), Error> {
+    let (cert, key, recorded) = (dir.join("cert.pem"), dir.join("key.pem"), dir.join("names"));
+
+    let names = names.join("\n");
+    let current = fs_err::read_to_string(&recorded).is_ok_and(|r| r == names);
+    if !(current && cert.is_file() && key.is_file()) {
+        let generated = rcgen::generate_simple_self_signed(
+            names.lines().map(String::from).collect::<Vec<_>>(),
+        )?;
+        fs_err::create_dir_all(dir)?;
+        fs_err::write(&cert, generated.cert.pem())?;
+        fs_err::write(&key, generated.key_pair.serialize_pem())?;
+        fs_err::write(&recorded, &names)?;
+    }
+
+    Ok((cert, key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reuses_certificates_for_the_same_names() {
+        let dir = std::env::temp_dir().join(format!("corvusite-tls-{}", std::process::id()));
+        let _ = fs_err::remove_dir_all(&dir);
+        let names = ["localhost".to_string()];
+
+        let (cert, _) = self_signed(&dir, &names).unwrap();
+        let first = fs_err::read_to_string(&cert).unwrap();
+        self_signed(&dir, &names).unwrap();
+        assert_eq!(fs_err::read_to_string(&cert).unwrap(), first);
+
+        self_signed(&dir, &["127.0.0.1".to_string()]).unwrap();
+        assert_ne!(fs_err::read_to_string(&cert).unwrap(), first);
+
+        fs_err::remove_dir_all(&dir).unwrap();
+    }
+}
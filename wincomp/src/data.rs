@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+/// Structured data that templates can read, like the contents of a site's data files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    /// Look up a dotted path, like `nav.links`.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        path.split('.').try_fold(self, |value, key| match value {
+            Value::Map(map) => map.get(key),
+            Value::List(list) => list.get(key.parse::<usize>().ok()?),
+            Value::String(_) => None,
+        })
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Map(BTreeMap::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn looks_up_paths() {
+        let data = Value::Map(
+            [(
+                "nav".to_string(),
+                Value::List(vec![Value::String("Home".into())]),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        assert_eq!(data.get("nav.0").and_then(Value::as_str), Some("Home"));
+        assert_eq!(data.get("nav.1"), None);
+        assert_eq!(data.get("footer"), None);
+    }
+}
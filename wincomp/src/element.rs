@@ -1,8 +1,27 @@
+use std::borrow::Cow;
+use std::ops::Deref;
+
+/// An element, borrowing its name, attributes, and text from the source
+/// it was parsed from where it can. See [`Element::into_owned`].
 #[derive(Debug, Clone)]
 pub struct Element<'s> {
-    pub name: &'s str,
+    pub name: Cow<'s, str>,
     pub attributes: Vec<Attribute<'s>>,
     pub children: Vec<Node<'s>>,
+    /// The byte offset of the opening `<` in the source the element was
+    /// parsed from, or `None` for generated elements and anything copied
+    /// in from another source, like the markup a component expands to.
+    pub offset: Option<usize>,
+}
+
+/// Elements are the same wherever they were parsed from, so a page's
+/// script matches one a component adds.
+impl PartialEq for Element<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.attributes == other.attributes
+            && self.children == other.children
+    }
 }
 
 /// Descent the tree depth-first.
@@ -56,13 +75,101 @@ where
     }
 }
 
-impl<'s> Element<'s> {}
+/// Elements that never have contents or a closing tag, like `<br>`.
+pub const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
 
-#[derive(Debug, Clone)]
+/// Elements whose contents are kept as a single text node, byte for byte,
+/// including `<Markdown>`, whose contents are rendered as markdown later.
+pub const RAW_TEXT_ELEMENTS: [&str; 5] = ["script", "style", "pre", "textarea", "Markdown"];
+
+/// Whether `name` is one of the [`RAW_TEXT_ELEMENTS`].
+pub fn is_raw_text(name: &str) -> bool {
+    RAW_TEXT_ELEMENTS.contains(&name)
+}
+
+/// Whether `name` is one of the [`VOID_ELEMENTS`].
+///
+/// Names are compared exactly, so components like `<Link>` aren't void.
+pub fn is_void(name: &str) -> bool {
+    VOID_ELEMENTS.contains(&name)
+}
+
+impl<'s> Element<'s> {
+    /// An element without attributes or children.
+    pub fn new(name: impl Into<Cow<'s, str>>) -> Self {
+        Self {
+            name: name.into(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+            offset: None,
+        }
+    }
+
+    /// Set the attribute called `name`, replacing its value if it's
+    /// already there.
+    pub fn set_attribute(&mut self, name: impl Into<Cow<'s, str>>, value: Option<Cow<'s, str>>) {
+        let name = name.into();
+        match self.attributes.iter_mut().find(|a| a.name == name) {
+            Some(attribute) => attribute.value = value,
+            None => self.attributes.push(Attribute {
+                name,
+                value,
+                offset: None,
+            }),
+        }
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) -> Option<Attribute<'s>> {
+        let index = self.attributes.iter().position(|a| a.name == name)?;
+        Some(self.attributes.remove(index))
+    }
+
+    /// Insert `node` before the child at `index`.
+    ///
+    /// Panics if `index` is past the last child, like [`Vec::insert`].
+    pub fn insert_child(&mut self, index: usize, node: Node<'s>) {
+        self.children.insert(index, node);
+    }
+
+    pub fn append_child(&mut self, node: Node<'s>) {
+        self.children.push(node);
+    }
+
+    /// Remove and return the child at `index`.
+    ///
+    /// Panics if there's no such child, like [`Vec::remove`].
+    pub fn remove_child(&mut self, index: usize) -> Node<'s> {
+        self.children.remove(index)
+    }
+
+    /// Copy whatever this element borrows, so it no longer depends on
+    /// its source and can be kept past it, like in a cache of parsed
+    /// components.
+    pub fn into_owned(self) -> Element<'static> {
+        Element {
+            name: Cow::Owned(self.name.into_owned()),
+            attributes: self
+                .attributes
+                .into_iter()
+                .map(Attribute::into_owned)
+                .collect(),
+            children: self.children.into_iter().map(Node::into_owned).collect(),
+            offset: self.offset,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Node<'s> {
-    Text(&'s str),
+    /// Text between tags, with its character references decoded.
+    Text(Text<'s>),
     Element(Element<'s>),
-    Comment(&'s str),
+    Comment(Cow<'s, str>),
+    /// Trusted markup, written without escaping.
+    Raw(Cow<'s, str>),
 }
 
 impl<'s> Node<'s> {
@@ -83,10 +190,173 @@ impl<'s> Node<'s> {
             _ => None,
         }
     }
+
+    /// Put `node` in this one's place, returning what was there.
+    pub fn replace_with(&mut self, node: Node<'s>) -> Node<'s> {
+        std::mem::replace(self, node)
+    }
+
+    /// Move this node into `wrapper`, after its children, and put
+    /// the wrapper in its place.
+    pub fn wrap(&mut self, mut wrapper: Element<'s>) {
+        wrapper
+            .children
+            .push(self.replace_with(Node::Text("".into())));
+        *self = Node::Element(wrapper);
+    }
+
+    /// Copy whatever this node borrows. See [`Element::into_owned`].
+    pub fn into_owned(self) -> Node<'static> {
+        match self {
+            Self::Text(text) => Node::Text(text.into_owned()),
+            Self::Element(element) => Node::Element(element.into_owned()),
+            Self::Comment(text) => Node::Comment(Cow::Owned(text.into_owned())),
+            Self::Raw(text) => Node::Raw(Cow::Owned(text.into_owned())),
+        }
+    }
+}
+
+/// A run of text, which reads like the `str` it holds.
+#[derive(Debug, Clone)]
+pub struct Text<'s> {
+    pub content: Cow<'s, str>,
+    /// The byte offset of the text in the source it was parsed from, like
+    /// [`Element::offset`]. A decoded reference starts at its `&`.
+    pub offset: Option<usize>,
+}
+
+impl PartialEq for Text<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.content == other.content
+    }
+}
+
+impl Deref for Text<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.content
+    }
+}
+
+impl AsRef<str> for Text<'_> {
+    fn as_ref(&self) -> &str {
+        &self.content
+    }
+}
+
+impl<'s> From<Cow<'s, str>> for Text<'s> {
+    fn from(content: Cow<'s, str>) -> Self {
+        Self {
+            content,
+            offset: None,
+        }
+    }
+}
+
+impl<'s> From<&'s str> for Text<'s> {
+    fn from(content: &'s str) -> Self {
+        Cow::Borrowed(content).into()
+    }
+}
+
+impl From<String> for Text<'_> {
+    fn from(content: String) -> Self {
+        Cow::<str>::Owned(content).into()
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Text<'_> {
+    /// Copy whatever this text borrows. See [`Element::into_owned`].
+    pub fn into_owned(self) -> Text<'static> {
+        Text {
+            content: Cow::Owned(self.content.into_owned()),
+            offset: self.offset,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Attribute<'s> {
-    pub name: &'s str,
-    pub value: Option<&'s str>,
+    pub name: Cow<'s, str>,
+    pub value: Option<Cow<'s, str>>,
+    /// The byte offset of the attribute's name in the source it was
+    /// parsed from, like [`Element::offset`].
+    pub offset: Option<usize>,
+}
+
+impl PartialEq for Attribute<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.value == other.value
+    }
+}
+
+impl Attribute<'_> {
+    /// Copy whatever this attribute borrows. See [`Element::into_owned`].
+    pub fn into_owned(self) -> Attribute<'static> {
+        Attribute {
+            name: Cow::Owned(self.name.into_owned()),
+            value: self.value.map(|value| Cow::Owned(value.into_owned())),
+            offset: self.offset,
+        }
+    }
+}
+
+/// Forget where `nodes` came from, since they're being copied into
+/// markup parsed from another source.
+pub fn unlocate(nodes: &mut [Node<'_>]) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => text.offset = None,
+            Node::Element(element) => {
+                element.offset = None;
+                for attribute in &mut element.attributes {
+                    attribute.offset = None;
+                }
+                unlocate(&mut element.children);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Document;
+
+    #[test]
+    fn records_offsets() {
+        let source =
+            "<main>\n  <!-- note -->\n  <a href=\"/\" class=x>Home &amp; away</a>\n</main>";
+        let text_offsets = |nodes: &[Node]| {
+            let offsets = nodes.iter().map(|node| match node {
+                Node::Text(text) => text.offset,
+                _ => None,
+            });
+            offsets.collect::<Vec<_>>()
+        };
+        for document in [Document::new(source).unwrap(), Document::lenient(source).0] {
+            let main = document.nodes[0].element().unwrap();
+            assert_eq!(main.offset, Some(0));
+            assert_eq!(main.children[0], Node::Text("\n  ".into()));
+            assert_eq!(text_offsets(&main.children)[0], Some(6));
+
+            let a = main.children[3].element().unwrap();
+            assert_eq!(a.offset, Some(25));
+            let attributes = a.attributes.iter().map(|attribute| attribute.offset);
+            assert_eq!(attributes.collect::<Vec<_>>(), [Some(28), Some(37)]);
+            assert_eq!(text_offsets(&a.children), [Some(45), Some(50), Some(55)]);
+        }
+
+        let mut nodes = Document::new(source).unwrap().nodes;
+        unlocate(&mut nodes);
+        let main = nodes[0].element().unwrap();
+        assert_eq!(main.offset, None);
+        assert_eq!(text_offsets(&main.children)[0], None);
+        let a = main.children[3].element().unwrap();
+        assert_eq!(a.offset, None);
+        assert_eq!(a.attributes[0].offset, None);
+        assert_eq!(text_offsets(&a.children), [None, None, None]);
+    }
 }
@@ -0,0 +1,111 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// HTML's named character references, like `amp` for `&`, by name.
+fn named() -> &'static HashMap<&'static str, &'static str> {
+    static NAMED: OnceLock<HashMap<&str, &str>> = OnceLock::new();
+    NAMED.get_or_init(|| {
+        entities::ENTITIES
+            .iter()
+            .filter_map(|e| Some((e.entity.strip_prefix('&')?.strip_suffix(';')?, e.characters)))
+            .collect()
+    })
+}
+
+/// The character reference at the start of `text`, like `&amp;`,
+/// `&#38;`, or `&#x26;`, as its length and the text it stands for.
+///
+/// Numeric references to characters HTML doesn't allow stand for
+/// U+FFFD, as they do in browsers.
+pub fn reference(text: &str) -> Option<(usize, Cow<'static, str>)> {
+    let end = text.find(';')?;
+    let body = text.strip_prefix('&')?.get(..end - 1)?;
+
+    let number = match body.strip_prefix('#') {
+        Some(number) => number,
+        None => return Some((end + 1, Cow::Borrowed(named().get(body)?))),
+    };
+    let (digits, radix) = match number.strip_prefix(['x', 'X']) {
+        Some(hex) => (hex, 16),
+        None => (number, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+
+    let c = u32::from_str_radix(digits, radix)
+        .ok()
+        .filter(|&code| code != 0)
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+    Some((end + 1, Cow::Owned(c.to_string())))
+}
+
+/// Split `text` around its character references, decoding each one,
+/// along with where each piece starts in `text`.
+///
+/// The pieces between references stay borrowed from `text`.
+pub fn split(text: &str) -> Vec<(usize, Cow<'_, str>)> {
+    let mut pieces = Vec::new();
+    let mut rest = 0;
+    let mut cursor = 0;
+
+    while let Some(start) = text[cursor..].find('&').map(|s| s + cursor) {
+        match reference(&text[start..]) {
+            Some((len, value)) => {
+                pieces.push((rest, Cow::Borrowed(&text[rest..start])));
+                pieces.push((start, value));
+                rest = start + len;
+                cursor = rest;
+            }
+            None => cursor = start + 1,
+        }
+    }
+
+    pieces.push((rest, Cow::Borrowed(&text[rest..])));
+    pieces.retain(|(_, piece)| !piece.is_empty());
+    pieces
+}
+
+/// Decode the character references in `text`, like an attribute value,
+/// keeping it borrowed when there are none.
+pub fn decode(text: &str) -> Cow<'_, str> {
+    let mut pieces = split(text);
+    match pieces.len() {
+        0 => Cow::Borrowed(""),
+        1 => pieces.remove(0).1,
+        _ => Cow::Owned(pieces.into_iter().map(|(_, piece)| piece).collect()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pieces(text: &str) -> Vec<Cow<'_, str>> {
+        split(text).into_iter().map(|(_, piece)| piece).collect()
+    }
+
+    #[test]
+    fn decodes_references() {
+        assert_eq!(pieces("Tom &amp; Jerry"), ["Tom ", "&", " Jerry"]);
+        assert_eq!(pieces("&copy;&nbsp;2024"), ["©", "\u{a0}", "2024"]);
+        assert_eq!(pieces("&hearts;&NotSubset;"), ["♥", "⊂\u{20d2}"]);
+        assert_eq!(pieces("&#38;&#x26;&#0;"), ["&", "&", "\u{fffd}"]);
+        let starts: Vec<_> = split("a &amp; b")
+            .into_iter()
+            .map(|(start, _)| start)
+            .collect();
+        assert_eq!(starts, [0, 2, 7]);
+        assert!(matches!(reference("&#38;"), Some((5, Cow::Owned(_)))));
+        assert_eq!(
+            pieces("a & b &unknown; &#-1; &#xZ;"),
+            ["a & b &unknown; &#-1; &#xZ;"]
+        );
+
+        assert_eq!(decode("/search?q=1&amp;page=2"), "/search?q=1&page=2");
+        assert_eq!(decode("&quot;"), "\"");
+        assert_eq!(decode(""), "");
+    }
+}
@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+
+/// Escape an attribute value for writing between double quotes.
+///
+/// Values are decoded when parsed, so this escapes every `&` and quote.
+/// A `\"`, which the parser allows inside values, becomes `&quot;` too.
+pub fn attribute(value: &str) -> Cow<'_, str> {
+    escape(value, |rest| {
+        if rest.starts_with("\\\"") {
+            Some(("&quot;", 2))
+        } else if rest.starts_with('"') {
+            Some(("&quot;", 1))
+        } else {
+            None
+        }
+    })
+}
+
+/// Escape text for writing between tags.
+pub fn text(text: &str) -> Cow<'_, str> {
+    escape(text, |rest| rest.starts_with('<').then_some(("&lt;", 1)))
+}
+
+fn escape<'a>(
+    value: &'a str,
+    special: impl Fn(&str) -> Option<(&'static str, usize)>,
+) -> Cow<'a, str> {
+    let mut escaped = String::new();
+    let mut written = 0;
+
+    let mut index = 0;
+    while index < value.len() {
+        let rest = &value[index..];
+        let replacement = if rest.starts_with('&') {
+            Some(("&amp;", 1))
+        } else if rest.starts_with('\u{a0}') {
+            // Written out, since it's indistinguishable from a space.
+            Some(("&nbsp;", '\u{a0}'.len_utf8()))
+        } else {
+            special(rest)
+        };
+
+        match replacement {
+            Some((replacement, len)) => {
+                escaped.push_str(&value[written..index]);
+                escaped.push_str(replacement);
+                index += len;
+                written = index;
+            }
+            None => index += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    if written == 0 {
+        return Cow::Borrowed(value);
+    }
+    escaped.push_str(&value[written..]);
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn escapes_decoded_values() {
+        assert_eq!(attribute("/search?q=1&page=2"), "/search?q=1&amp;page=2");
+        assert_eq!(
+            attribute(r#"say \"hi\" & "bye""#),
+            "say &quot;hi&quot; &amp; &quot;bye&quot;"
+        );
+        assert_eq!(
+            attribute("&amp; &#38; &hearts; a\u{a0}b"),
+            "&amp;amp; &amp;#38; &amp;hearts; a&nbsp;b"
+        );
+        assert!(matches!(attribute("plain"), Cow::Borrowed(_)));
+
+        assert_eq!(text("1 < 2 & 3 \"ok\""), "1 &lt; 2 &amp; 3 \"ok\"");
+    }
+}
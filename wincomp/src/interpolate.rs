@@ -0,0 +1,97 @@
+use crate::element::{Node, Text};
+use std::borrow::Cow;
+
+/// Substitute `{prop}` placeholders in text nodes.
+///
+/// Text borrowed from its source is split into several adjacent
+/// nodes rather than concatenated, so it stays borrowed.
+pub fn interpolate<'s>(nodes: &mut Vec<Node<'s>>, lookup: &impl Fn(&str) -> Option<Cow<'s, str>>) {
+    let mut index = 0;
+    while index < nodes.len() {
+        let Node::Text(text) = &nodes[index] else {
+            index += 1;
+            continue;
+        };
+
+        let mut pieces = Vec::new();
+        let mut rest = 0;
+        let mut cursor = 0;
+        while let Some(start) = text[cursor..].find('{').map(|s| s + cursor) {
+            let Some(end) = text[start..].find('}').map(|e| e + start) else {
+                break;
+            };
+
+            match lookup(&text[start + 1..end]) {
+                Some(value) => {
+                    pieces.push(piece(text, rest..start));
+                    pieces.push(Text::from(value));
+                    rest = end + 1;
+                    cursor = rest;
+                }
+                None => cursor = start + 1,
+            }
+        }
+
+        if pieces.is_empty() {
+            index += 1;
+            continue;
+        }
+
+        pieces.push(piece(text, rest..text.len()));
+        pieces.retain(|p| !p.is_empty());
+
+        let len = pieces.len();
+        nodes.splice(index..=index, pieces.into_iter().map(Node::Text));
+        index += len;
+    }
+}
+
+/// Part of `text`, still borrowed from its source if `text` is.
+fn piece<'s>(text: &Text<'s>, range: std::ops::Range<usize>) -> Text<'s> {
+    let offset = text.offset.map(|offset| offset + range.start);
+    let content = match &text.content {
+        Cow::Borrowed(text) => Cow::Borrowed(&text[range]),
+        Cow::Owned(text) => Cow::Owned(text[range].to_owned()),
+    };
+    Text { content, offset }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_pieces_borrowed() {
+        let mut nodes = vec![Node::Text(Text {
+            content: Cow::Borrowed("{title} | {date}{missing}"),
+            offset: Some(10),
+        })];
+        interpolate(&mut nodes, &|name| {
+            (name != "missing").then(|| name.to_uppercase().into())
+        });
+
+        let texts: Vec<_> = nodes
+            .iter()
+            .map(|node| match node {
+                Node::Text(text) => (&*text.content, text.offset),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                ("TITLE", None),
+                (" | ", Some(17)),
+                ("DATE", None),
+                ("{missing}", Some(26))
+            ]
+        );
+        assert!(matches!(
+            &nodes[1],
+            Node::Text(Text {
+                content: Cow::Borrowed(_),
+                ..
+            })
+        ));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::element::{Element, Node};
+use crate::data::Value;
+use crate::element::{Attribute, Element, Node};
+use crate::interpolate::interpolate;
+use std::borrow::Cow;
 use winnow::{
     ascii::multispace0,
     combinator::{delimited, terminated},
@@ -6,48 +9,420 @@ use winnow::{
     Parser,
 };
 
+pub mod data;
 pub mod element;
+pub mod entity;
+pub mod escape;
+pub mod interpolate;
+pub mod minify;
 pub mod parse;
+pub mod pretty;
+pub mod scope;
+pub mod select;
+pub mod transform;
+
+/// The attribute marking where a component spreads the attributes
+/// it doesn't declare, as in `<button {..}>`.
+pub const SPREAD: &str = "{..}";
 
 pub struct Document<'s> {
     pub nodes: Vec<Node<'s>>,
 }
 
+#[derive(Clone)]
 pub struct Component<'s> {
     pub root: Element<'s>,
+    /// Attributes declared like `<Card title!>`, which every use must set.
+    pub required: Vec<Cow<'s, str>>,
+    /// The component's own `<style>` blocks, rewritten to only apply
+    /// to its elements, and the class marking them.
+    pub style: Option<(String, String)>,
+    /// The component's top-level `<script>` blocks, which expansion
+    /// adds to the page once however often the component is used.
+    pub scripts: Vec<Element<'s>>,
 }
 
 impl<'s> Component<'s> {
-    pub fn new(mut source: &'s str) -> Result<Self, ParseError<&'s str, ContextError>> {
-        let root = delimited(multispace0, parse::element, multispace0).parse(&mut source)?;
+    /// Parse a component declared like `<Card title>…</Card>`, whose
+    /// children are its template.
+    pub fn new(source: &'s str) -> Result<Self, ParseError<&'s str, ContextError>> {
+        let mut root = delimited(multispace0, parse::element, multispace0).parse(source)?;
+        parse::locate(&mut root, source.len());
+        Ok(Self::from_root(root))
+    }
+
+    /// Parse a component named `name` whose template is all of `source`,
+    /// so it can expand to several siblings without a wrapper element.
+    ///
+    /// Without a declaration it has no props, so every attribute a use
+    /// sets goes wherever the template spreads them with `{..}`.
+    pub fn fragment(
+        name: &'s str,
+        source: &'s str,
+    ) -> Result<Self, ParseError<&'s str, ContextError>> {
+        let mut children = terminated(parse::nodes, multispace0).parse(source)?;
+        parse::locate_nodes(&mut children, source.len());
+        Ok(Self::from_root(Element {
+            name: name.into(),
+            attributes: Vec::new(),
+            children,
+            offset: None,
+        }))
+    }
+
+    /// Copy whatever the component borrows from its source, so it can
+    /// be kept past it, like across rebuilds.
+    pub fn into_owned(self) -> Component<'static> {
+        Component {
+            root: self.root.into_owned(),
+            required: self
+                .required
+                .into_iter()
+                .map(|name| Cow::Owned(name.into_owned()))
+                .collect(),
+            style: self.style,
+            scripts: self.scripts.into_iter().map(Element::into_owned).collect(),
+        }
+    }
+
+    fn from_root(mut root: Element<'s>) -> Self {
+        let mut required = Vec::new();
+        for attribute in &mut root.attributes {
+            if attribute.name.ends_with('!') {
+                let len = attribute.name.len() - 1;
+                match &mut attribute.name {
+                    Cow::Borrowed(name) => *name = &name[..len],
+                    Cow::Owned(name) => name.truncate(len),
+                }
+                required.push(attribute.name.clone());
+            }
+        }
+
+        // Top-level `<style>` blocks are lifted out of the markup.
+        let mut css = String::new();
+        root.children.retain(|child| match child.element() {
+            Some(style) if style.name == "style" => {
+                for text in &style.children {
+                    if let Node::Text(text) = text {
+                        css.push_str(text);
+                    }
+                }
+                false
+            }
+            _ => true,
+        });
+        let style = (!css.trim().is_empty()).then(|| {
+            let class = scope::class(&css);
+            (scope::rewrite(&css, &class), class)
+        });
+
+        let mut scripts = Vec::new();
+        for child in std::mem::take(&mut root.children) {
+            match child {
+                Node::Element(script) if script.name == "script" => scripts.push(script),
+                child => root.children.push(child),
+            }
+        }
+
+        Self {
+            root,
+            required,
+            style,
+            scripts,
+        }
+    }
+}
+
+/// How many levels deep components may nest before expansion gives up.
+pub const MAX_DEPTH: usize = 256;
+
+/// A component used in a way its declaration doesn't allow.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpandError {
+    MissingAttribute {
+        component: String,
+        attribute: String,
+    },
+    /// Components including themselves until they nest more than
+    /// [`MAX_DEPTH`] levels deep, like `["A", "B", "A"]`.
+    Cycle {
+        path: Vec<String>,
+    },
+    TooDeep,
+    /// A `<For each>` path that isn't in the data or any enclosing loop's item.
+    UnknownData {
+        path: String,
+    },
+    /// An `<If cond>` that names neither a prop of the component it's in,
+    /// nor a field of a `<For>` item, like a misspelled prop.
+    UnknownCondition {
+        cond: String,
+        component: Option<String>,
+    },
+}
 
-        Ok(Self { root })
+impl std::fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingAttribute {
+                component,
+                attribute,
+            } => write!(f, "<{component}> requires the `{attribute}` attribute"),
+            Self::Cycle { path } => {
+                write!(f, "Components include themselves: {}", path.join(" -> "))
+            }
+            Self::TooDeep => write!(f, "Components nest more than {MAX_DEPTH} levels deep"),
+            Self::UnknownData { path } => {
+                write!(f, "<For each=\"{path}\"> doesn't match any data")
+            }
+            Self::UnknownCondition {
+                cond,
+                component: Some(component),
+            } => write!(
+                f,
+                "<If cond=\"{cond}\"> doesn't name a prop of <{component}>"
+            ),
+            Self::UnknownCondition {
+                cond,
+                component: None,
+            } => write!(
+                f,
+                "<If cond=\"{cond}\"> is outside any component, so it has no prop to check"
+            ),
+        }
     }
 }
 
+impl std::error::Error for ExpandError {}
+
 impl<'s> Document<'s> {
-    pub fn new(mut source: &'s str) -> Result<Self, ParseError<&'s str, ContextError>> {
-        let nodes = terminated(parse::nodes, multispace0).parse(&mut source)?;
+    pub fn new(source: &'s str) -> Result<Self, ParseError<&'s str, ContextError>> {
+        let mut nodes = terminated(parse::nodes, multispace0).parse(source)?;
+        parse::locate_nodes(&mut nodes, source.len());
 
         Ok(Self { nodes })
     }
 
-    pub fn expand<F>(&mut self, mut components: F)
+    /// Parse `source` like [`Document::new`], but work around malformed
+    /// markup, like unclosed tags or a stray `</div>`, rather than failing.
+    ///
+    /// Well-formed markup parses the same either way. Anything that had
+    /// to be worked around is returned alongside the document, so one bad
+    /// fragment, like a pasted embed, doesn't cost the whole page.
+    pub fn lenient(source: &'s str) -> (Self, Vec<parse::Recovery>) {
+        let mut recovered = Vec::new();
+        let mut input = source;
+        let nodes = parse::lenient_nodes(&mut input, source, &mut Vec::new(), &mut recovered);
+        (Self { nodes }, recovered)
+    }
+
+    /// Copy whatever the document borrows from its source. See
+    /// [`Element::into_owned`].
+    pub fn into_owned(self) -> Document<'static> {
+        Document {
+            nodes: self.nodes.into_iter().map(Node::into_owned).collect(),
+        }
+    }
+
+    pub fn expand<F>(&mut self, components: F) -> Result<(), ExpandError>
+    where
+        F: FnMut(&str) -> Option<&Component<'s>>,
+    {
+        self.expand_with_data(components, None)
+    }
+
+    /// Expand components, repeating `<For each="path">` elements
+    /// once for every item in the list at `path` in `data`, or in an
+    /// enclosing loop's item (or in a comma-separated `each="a, b, c"`),
+    /// and keeping
+    /// either side of an `<If cond="prop">…<Else/>…</If>` depending on
+    /// whether the component's `prop`, or a loop item's `cond="{field}"`,
+    /// is set. Any other condition is an error, since it can't be checked.
+    ///
+    /// Components' scripts are added once to the end of the `<body>`,
+    /// or of the document if it has none, and the children of any
+    /// `<Head>` are moved into the `<head>`.
+    pub fn expand_with_data<F>(
+        &mut self,
+        components: F,
+        data: Option<&'s Value>,
+    ) -> Result<(), ExpandError>
+    where
+        F: FnMut(&str) -> Option<&Component<'s>>,
+    {
+        self.expand_inner(components, data, true)
+    }
+
+    /// Expand a fragment that ends up inside another page, like an
+    /// article's body in a feed, as [`Self::expand_with_data`] would a page.
+    ///
+    /// The fragment has no `<head>` of its own, so the children of any
+    /// `<Head>` are dropped rather than moved into one, and components'
+    /// scripts are left to the page, rather than added to each fragment.
+    pub fn expand_fragment_with_data<F>(
+        &mut self,
+        components: F,
+        data: Option<&'s Value>,
+    ) -> Result<(), ExpandError>
     where
         F: FnMut(&str) -> Option<&Component<'s>>,
     {
-        loop {
-            if !Self::expand_recurse(&mut self.nodes, &mut components) {
-                break;
+        self.expand_inner(components, data, false)
+    }
+
+    fn expand_inner<F>(
+        &mut self,
+        mut components: F,
+        data: Option<&'s Value>,
+        page: bool,
+    ) -> Result<(), ExpandError>
+    where
+        F: FnMut(&str) -> Option<&Component<'s>>,
+    {
+        let mut scripts = Vec::new();
+        Self::expand_recurse(
+            &mut self.nodes,
+            &mut components,
+            data,
+            &mut scripts,
+            &mut Vec::new(),
+        )?;
+        let hoisted = self.take_head();
+        if page {
+            self.hoist(scripts);
+            self.hoist_head(hoisted);
+        }
+        Ok(())
+    }
+
+    /// Remove every `<Head>`, returning the elements among their children.
+    fn take_head(&mut self) -> Vec<Element<'s>> {
+        fn take<'s>(nodes: &mut Vec<Node<'s>>, hoisted: &mut Vec<Element<'s>>) {
+            let mut index = 0;
+            while index < nodes.len() {
+                match &mut nodes[index] {
+                    Node::Element(element) if element.name == "Head" => {
+                        let Node::Element(head) = nodes.remove(index) else {
+                            unreachable!()
+                        };
+                        for child in head.children {
+                            if let Node::Element(child) = child {
+                                hoisted.push(child);
+                            }
+                        }
+                    }
+                    Node::Element(element) => {
+                        if !element::is_raw_text(&element.name) {
+                            take(&mut element.children, hoisted);
+                        }
+                        index += 1;
+                    }
+                    _ => index += 1,
+                }
+            }
+        }
+
+        let mut hoisted = Vec::new();
+        take(&mut self.nodes, &mut hoisted);
+        hoisted
+    }
+
+    /// Move the children taken from every `<Head>` into the document's `<head>`.
+    ///
+    /// Each replaces whatever is already there with the same key, like
+    /// a `<title>`, `<meta name="description">`, or `<link rel href>`, so
+    /// a page can override a layout's defaults. Other elements are only
+    /// added once.
+    fn hoist_head(&mut self, hoisted: Vec<Element<'s>>) {
+        if hoisted.is_empty() {
+            return;
+        }
+
+        let Some(head) = self.find_mut(|e| e.name == "head") else {
+            self.nodes
+                .splice(0..0, hoisted.into_iter().map(Node::Element));
+            return;
+        };
+
+        for element in hoisted {
+            let key = head_key(&element);
+            let existing = head.children.iter().position(|child| {
+                child.element().is_some_and(|child| match key {
+                    Some(key) => head_key(child) == Some(key),
+                    None => *child == element,
+                })
+            });
+            match (existing, key) {
+                (Some(existing), Some(_)) => {
+                    head.children[existing].replace_with(Node::Element(element));
+                }
+                (Some(_), None) => {}
+                (None, _) => head.append_child(Node::Element(element)),
             }
         }
     }
 
-    fn expand_recurse<F>(nodes: &mut Vec<Node<'s>>, components: &mut F) -> bool
+    /// Capitalized elements, like a misspelled `<Buton>`, which are left
+    /// after expansion because no component matched them. Each name is
+    /// listed once, in the order it first appears.
+    pub fn unresolved(&self) -> Vec<&str> {
+        fn collect<'a>(nodes: &'a [Node], names: &mut Vec<&'a str>) {
+            for element in nodes.iter().filter_map(Node::element) {
+                // Prefixed or dotted names, like `<icons:ArrowRight>` or
+                // `<ui.Button>`, are judged by their last part.
+                let name = element.name.as_ref();
+                let local = name.rsplit([':', '.']).next().unwrap_or(name);
+                if local.starts_with(char::is_uppercase) && !names.contains(&name) {
+                    names.push(name);
+                }
+                collect(&element.children, names);
+            }
+        }
+
+        let mut names = Vec::new();
+        collect(&self.nodes, &mut names);
+        names
+    }
+
+    /// The first element, depth-first, matching `predicate`.
+    pub fn find_mut<F>(&mut self, mut predicate: F) -> Option<&mut Element<'s>>
+    where
+        F: FnMut(&Element<'s>) -> bool,
+    {
+        self.nodes
+            .iter_mut()
+            .filter_map(Node::element_mut)
+            .find_map(|element| element::find_mut(element, &mut predicate))
+    }
+
+    fn hoist(&mut self, mut scripts: Vec<Element<'s>>) {
+        // Pages may already include a script by hand.
+        for element in self.nodes.iter_mut().filter_map(Node::element_mut) {
+            element::walk(element, &mut |element| {
+                scripts.retain(|script| script != element);
+            });
+        }
+        if scripts.is_empty() {
+            return;
+        }
+
+        let scripts = scripts.into_iter().map(Node::Element);
+        match self.find_mut(|e| e.name == "body") {
+            Some(body) => body.children.extend(scripts),
+            None => self.nodes.extend(scripts),
+        }
+    }
+
+    fn expand_recurse<F>(
+        nodes: &mut Vec<Node<'s>>,
+        components: &mut F,
+        data: Option<&'s Value>,
+        scripts: &mut Vec<Element<'s>>,
+        path: &mut Vec<String>,
+    ) -> Result<(), ExpandError>
     where
         F: FnMut(&str) -> Option<&Component<'s>>,
     {
-        let mut mutated = false;
         let mut index = 0;
         while index < nodes.len() {
             let Some(child) = nodes[index].element_mut() else {
@@ -55,32 +430,131 @@ impl<'s> Document<'s> {
                 continue;
             };
 
-            if let Some(component) = components(child.name) {
-                mutated = true;
+            if let Some(component) = components(&child.name) {
+                // Components may include themselves behind an `<If>`, so a cycle
+                // only shows once the nesting runs too deep.
+                if path.len() >= MAX_DEPTH {
+                    return Err(match first_cycle(path) {
+                        Some(path) => ExpandError::Cycle { path },
+                        None => ExpandError::TooDeep,
+                    });
+                }
+                if let Some(missing) = component
+                    .required
+                    .iter()
+                    .find(|name| !child.attributes.iter().any(|a| a.name == **name))
+                {
+                    return Err(ExpandError::MissingAttribute {
+                        component: child.name.to_string(),
+                        attribute: missing.to_string(),
+                    });
+                }
+
+                for script in &component.scripts {
+                    if !scripts.contains(script) {
+                        let mut script = script.clone();
+                        element::walk(&mut script, &mut |e| e.offset = None);
+                        scripts.push(script);
+                    }
+                }
+
+                // Attributes the use leaves out keep their declared values as defaults.
                 let declared_attributes = &component.root.attributes;
                 let mut replacement_attributes = Vec::with_capacity(declared_attributes.len());
 
                 for attribute in declared_attributes {
                     if let Some(attr) = child.attributes.iter().find(|a| a.name == attribute.name) {
-                        replacement_attributes.push(*attr);
+                        replacement_attributes.push(attr.clone());
                     } else {
-                        replacement_attributes.push(*attribute);
+                        replacement_attributes.push(attribute.clone());
                     }
                 }
 
+                // Props given without a value, like `<Card featured>`, are set too.
+                let conditions: Vec<_> = declared_attributes
+                    .iter()
+                    .map(|declared| {
+                        let set = match child.attributes.iter().find(|a| a.name == declared.name) {
+                            Some(given) => given.value.is_none() || truthy(given.value.as_deref()),
+                            None => truthy(declared.value.as_deref()),
+                        };
+                        (&declared.name, set)
+                    })
+                    .collect();
+
+                // Attributes the component doesn't declare, like `id` or `aria-*`,
+                // go wherever its template spreads them with `{..}`.
+                let rest: Vec<_> = child
+                    .attributes
+                    .iter()
+                    .filter(|a| !declared_attributes.iter().any(|d| d.name == a.name))
+                    .cloned()
+                    .collect();
+
                 let mut component_copy = component.root.clone();
+                element::unlocate(&mut component_copy.children);
 
                 // Assign properties
+                let mut unknown = None;
                 element::walk(&mut component_copy, &mut |element| {
+                    if element.name == "If" {
+                        for attr in element.attributes.iter_mut().filter(|a| a.name == "cond") {
+                            let Some(cond) = attr.value.as_deref() else {
+                                continue;
+                            };
+                            if let Some((_, set)) =
+                                conditions.iter().find(|(name, _)| *name == cond)
+                            {
+                                attr.value = Some(if *set { "true" } else { "false" }.into());
+                            } else if !settled(cond) {
+                                unknown.get_or_insert_with(|| cond.to_owned());
+                            }
+                        }
+                    }
+
                     for attr in element.attributes.iter_mut() {
                         if let Some(value) = replacement_attributes.iter().find_map(|a| {
-                            attr.value.is_some_and(|v| v == a.name).then_some(a.value)
+                            let named = attr.value.as_ref().is_some_and(|v| *v == a.name);
+                            named.then(|| a.value.clone())
                         }) {
                             attr.value = value;
                         }
                     }
+
+                    if element.attributes.iter().any(|a| a.name == SPREAD) {
+                        // The caller's attributes win over the template's.
+                        element
+                            .attributes
+                            .retain(|attr| !rest.iter().any(|r| r.name == attr.name));
+                        let spread = element.attributes.iter().position(|a| a.name == SPREAD);
+                        if let Some(spread) = spread {
+                            element
+                                .attributes
+                                .splice(spread..=spread, rest.iter().cloned());
+                        }
+                    }
+
+                    if let Some((_, class)) = &component.style {
+                        scope_element(element, class);
+                    }
+
+                    if !element::is_raw_text(&element.name) {
+                        interpolate(&mut element.children, &|name| {
+                            replacement_attributes
+                                .iter()
+                                .find(|a| a.name == name)
+                                .map(|a| a.value.clone().unwrap_or_default())
+                        });
+                    }
                 });
 
+                if let Some(cond) = unknown {
+                    return Err(ExpandError::UnknownCondition {
+                        cond,
+                        component: Some(child.name.to_string()),
+                    });
+                }
+
                 let mut children = std::mem::take(&mut child.children);
 
                 let mut inner_index = 0;
@@ -100,15 +574,74 @@ impl<'s> Document<'s> {
                 });
 
                 if let Some(outlet) = outlet {
-                    outlet.children.remove(inner_index);
-                    outlet.children.append(&mut children)
+                    outlet
+                        .children
+                        .splice(inner_index..=inner_index, children.drain(..));
                 }
 
-                nodes.remove(index);
+                // Expand what the component holds before moving on, so `path`
+                // follows how deep each component nests.
+                path.push(child.name.to_string());
+                Self::expand_recurse(
+                    &mut component_copy.children,
+                    components,
+                    data,
+                    scripts,
+                    path,
+                )?;
+                path.pop();
 
-                for (i, child) in component_copy.children.into_iter().enumerate() {
-                    nodes.insert(index + i, child);
-                }
+                let len = component_copy.children.len();
+                nodes.splice(index..=index, component_copy.children);
+                index += len;
+                continue;
+            } else if child.name == "For" {
+                let repeated = repeat(child, data, &[])?;
+
+                // The repeated nodes may hold components of their own.
+                nodes.splice(index..=index, repeated);
+                continue;
+            } else if child.name == "If" {
+                let cond = match child
+                    .attributes
+                    .iter()
+                    .find(|a| a.name == "cond")
+                    .and_then(|a| a.value.as_deref())
+                {
+                    Some("true") => true,
+                    Some("false") | Some("") => false,
+                    // A placeholder no loop filled in, like a field some items leave out.
+                    Some(cond) if cond.starts_with('{') => false,
+                    Some(cond) => {
+                        return Err(ExpandError::UnknownCondition {
+                            cond: cond.to_owned(),
+                            component: None,
+                        })
+                    }
+                    None => {
+                        return Err(ExpandError::MissingAttribute {
+                            component: "If".into(),
+                            attribute: "cond".into(),
+                        })
+                    }
+                };
+                let mut branches = std::mem::take(&mut child.children);
+                let otherwise = branches
+                    .iter()
+                    .position(|n| n.element().is_some_and(|e| e.name == "Else"));
+
+                let kept = match (cond, otherwise) {
+                    (true, Some(otherwise)) => {
+                        branches.truncate(otherwise);
+                        branches
+                    }
+                    (true, None) => branches,
+                    (false, Some(otherwise)) => branches.split_off(otherwise + 1),
+                    (false, None) => Vec::new(),
+                };
+
+                nodes.splice(index..=index, kept);
+                continue;
             }
 
             // TODO: technically this can panic if the component has no children
@@ -117,53 +650,370 @@ impl<'s> Document<'s> {
                 continue;
             };
 
-            mutated |= Self::expand_recurse(&mut child.children, components);
+            Self::expand_recurse(&mut child.children, components, data, scripts, path)?;
 
             index += 1;
         }
 
-        mutated
+        Ok(())
+    }
+}
+
+/// The first stretch of `path` that starts and ends with the same
+/// component, like `["A", "B", "A"]`.
+fn first_cycle(path: &[String]) -> Option<Vec<String>> {
+    (1..path.len()).find_map(|end| {
+        let start = path[..end].iter().position(|p| *p == path[end])?;
+        Some(path[start..=end].to_vec())
+    })
+}
+
+/// Add a scoped component's class to one of its elements.
+///
+/// Components and the control elements are left alone, since they
+/// don't end up in the page themselves.
+fn scope_element(element: &mut Element, class: &str) {
+    let control = matches!(
+        element.name.as_ref(),
+        "children" | "If" | "Else" | "For" | "script" | "style"
+    );
+    if control || element.name.starts_with(char::is_uppercase) {
+        return;
+    }
+
+    match element.attributes.iter_mut().find(|a| a.name == "class") {
+        Some(attr) => {
+            let existing = attr.value.as_deref().unwrap_or_default();
+            attr.value = Some(format!("{existing} {class}").into());
+        }
+        None => element.attributes.push(Attribute {
+            name: "class".into(),
+            value: Some(class.to_owned().into()),
+            offset: None,
+        }),
+    }
+}
+
+/// What identifies an element in the `<head>`, like `("meta", "name",
+/// "description")`, for elements there should only be one of.
+fn head_key<'a>(element: &'a Element) -> Option<(&'a str, &'a str, &'a str)> {
+    let attribute = |name| {
+        element
+            .attributes
+            .iter()
+            .find(|a| a.name == name)
+            .map(|a| a.value.as_deref().unwrap_or(""))
+    };
+
+    match element.name.as_ref() {
+        "title" | "base" => Some((&element.name, "", "")),
+        "meta" if attribute("charset").is_some() => Some(("meta", "charset", "")),
+        "meta" => ["name", "property", "http-equiv", "itemprop"]
+            .into_iter()
+            .find_map(|name| Some(("meta", name, attribute(name)?))),
+        "link" => Some(("link", attribute("rel")?, attribute("href")?)),
+        "script" => Some(("script", "src", attribute("src")?)),
+        _ => None,
     }
 }
 
-impl Element<'_> {
-    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
-        write!(writer, "<{}", self.name)?;
+/// Whether an attribute's value counts as set for `<If cond>`.
+fn truthy(value: Option<&str>) -> bool {
+    value.is_some_and(|v| !v.is_empty() && v != "false")
+}
 
-        for attribute in self.attributes.iter() {
-            write!(writer, " {}", attribute.name)?;
+/// Whether an `<If cond>` is already decided, as `true` or `false`, or
+/// waits on the `{field}` of a `<For>` item.
+fn settled(cond: &str) -> bool {
+    matches!(cond, "true" | "false") || cond.starts_with('{')
+}
 
-            if let Some(value) = attribute.value {
-                write!(writer, r#"="{value}""#)?;
+/// One repetition of a `<For>` element.
+#[derive(Clone, Copy)]
+enum Item<'a, 's> {
+    Data(&'s Value),
+    /// One of the items listed in `each`, which is copied when used.
+    Text(&'a str),
+}
+
+impl<'s> Item<'_, 's> {
+    /// Resolve a placeholder like `{field}`, or `{item.field}` and
+    /// `{item}` when the loop names its items with `as="item"`.
+    fn lookup(self, alias: Option<&str>, placeholder: &str) -> Option<Cow<'s, str>> {
+        let path = match alias {
+            Some(alias) if placeholder == alias => {
+                return match self {
+                    Item::Data(value) => value.as_str().map(Cow::Borrowed),
+                    Item::Text(text) => Some(Cow::Owned(text.to_owned())),
+                };
+            }
+            Some(alias) => placeholder.strip_prefix(alias)?.strip_prefix('.')?,
+            None => placeholder,
+        };
+
+        match self {
+            Item::Data(value) => value.get(path)?.as_str().map(Cow::Borrowed),
+            Item::Text(_) => None,
+        }
+    }
+
+    /// Whether the field named by an `<If cond="{field}">` is set, or
+    /// `None` if the placeholder belongs to an enclosing loop's item.
+    fn is_set(self, alias: Option<&str>, placeholder: &str) -> Option<bool> {
+        match alias {
+            Some(alias) => {
+                let ours = placeholder == alias
+                    || placeholder
+                        .strip_prefix(alias)
+                        .is_some_and(|rest| rest.starts_with('.'));
+                ours.then(|| truthy(self.lookup(Some(alias), placeholder).as_deref()))
             }
+            // Unnamed items only claim the fields they have.
+            None => self
+                .lookup(None, placeholder)
+                .map(|value| truthy(Some(&value))),
         }
+    }
+}
+
+/// The items of enclosing `<For>` loops, innermost last, with the
+/// names they're given by `as`.
+type Scope<'a, 's> = [(Option<&'a str>, Item<'a, 's>)];
 
-        if self.children.is_empty() {
-            write!(writer, "/>")?;
+/// Repeat the children of the `<For>` element `element` once for each
+/// of its items, along with any loops nested inside it.
+fn repeat<'a, 's>(
+    element: &'a Element<'s>,
+    data: Option<&'s Value>,
+    scope: &Scope<'a, 's>,
+) -> Result<Vec<Node<'s>>, ExpandError> {
+    let attribute = |name| {
+        element
+            .attributes
+            .iter()
+            .find(|a| a.name == name)
+            .and_then(|a| a.value.as_deref())
+    };
+    let alias = attribute("as");
+
+    let items: Vec<Item> = match attribute("each") {
+        Some(each) => match resolve(each, data, scope) {
+            Some(Value::List(items)) => items.iter().map(Item::Data).collect(),
+            // A single value is a list of one.
+            Some(value) => vec![Item::Data(value)],
+            None if each.contains(',') => each
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(Item::Text)
+                .collect(),
+            None => {
+                return Err(ExpandError::UnknownData {
+                    path: each.to_owned(),
+                })
+            }
+        },
+        None => {
+            return Err(ExpandError::MissingAttribute {
+                component: "For".into(),
+                attribute: "each".into(),
+            })
+        }
+    };
+
+    let mut repeated = Vec::new();
+    for item in items {
+        let mut copy = element.children.clone();
+        let mut inner = scope.to_vec();
+        inner.push((alias, item));
+        repeat_nested(&mut copy, data, &inner)?;
+        for element in copy.iter_mut().filter_map(Node::element_mut) {
+            element::walk(element, &mut |element| {
+                if element.name != "If" {
+                    return;
+                }
+                for attr in element.attributes.iter_mut().filter(|a| a.name == "cond") {
+                    let placeholder = attr
+                        .value
+                        .as_deref()
+                        .and_then(|v| v.strip_prefix('{')?.strip_suffix('}'));
+                    if let Some(set) = placeholder.and_then(|p| item.is_set(alias, p)) {
+                        attr.value = Some(if set { "true" } else { "false" }.into());
+                    }
+                }
+            });
+        }
+        substitute(&mut copy, &|placeholder| item.lookup(alias, placeholder));
+        repeated.extend(copy);
+    }
+    Ok(repeated)
+}
+
+/// Repeat the `<For>` loops in `nodes`, which can list the fields of
+/// the items in `scope`.
+fn repeat_nested<'s>(
+    nodes: &mut Vec<Node<'s>>,
+    data: Option<&'s Value>,
+    scope: &Scope<'_, 's>,
+) -> Result<(), ExpandError> {
+    let mut index = 0;
+    while index < nodes.len() {
+        match &mut nodes[index] {
+            Node::Element(element) if element.name == "For" => {
+                let repeated = repeat(element, data, scope)?;
+                let len = repeated.len();
+                nodes.splice(index..=index, repeated);
+                index += len;
+            }
+            Node::Element(element) => {
+                if !element::is_raw_text(&element.name) {
+                    repeat_nested(&mut element.children, data, scope)?;
+                }
+                index += 1;
+            }
+            _ => index += 1,
+        }
+    }
+    Ok(())
+}
+
+/// The value at `path`, looked up in the items of enclosing loops,
+/// innermost first, before the data. Items are reached through their
+/// name, like `post.tags`, or directly, like `tags`, if they have none.
+fn resolve<'s>(path: &str, data: Option<&'s Value>, scope: &Scope<'_, 's>) -> Option<&'s Value> {
+    scope
+        .iter()
+        .rev()
+        .find_map(|(alias, item)| {
+            let Item::Data(value) = item else {
+                return None;
+            };
+            match alias {
+                Some(alias) if path == *alias => Some(*value),
+                Some(alias) => value.get(path.strip_prefix(alias)?.strip_prefix('.')?),
+                None => value.get(path),
+            }
+        })
+        .or_else(|| data?.get(path))
+}
+
+/// Substitute `{field}` placeholders in text, and attribute values
+/// consisting of a single placeholder, throughout `nodes`.
+fn substitute<'s>(nodes: &mut Vec<Node<'s>>, lookup: &impl Fn(&str) -> Option<Cow<'s, str>>) {
+    interpolate(nodes, lookup);
+
+    for element in nodes.iter_mut().filter_map(Node::element_mut) {
+        element::walk(element, &mut |element| {
+            for attr in element.attributes.iter_mut() {
+                let placeholder = attr
+                    .value
+                    .as_deref()
+                    .and_then(|v| v.strip_prefix('{')?.strip_suffix('}'));
+                if let Some(value) = placeholder.and_then(lookup) {
+                    attr.value = Some(value);
+                }
+            }
+
+            if !element::is_raw_text(&element.name) {
+                interpolate(&mut element.children, lookup);
+            }
+        });
+    }
+}
+
+/// How [`Document::write_with`] writes a page.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// Keep comments, like conditional comments or license headers,
+    /// which are dropped otherwise.
+    pub comments: bool,
+}
+
+impl Element<'_> {
+    pub fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.write_with(writer, &WriteOptions::default())
+    }
+
+    pub fn write_with<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> std::io::Result<()> {
+        self.write_start(writer)?;
+
+        // Void elements can't be closed, and others can't close themselves.
+        if element::is_void(&self.name) {
+            write!(writer, ">")?;
+        } else if self.children.is_empty() {
+            write!(writer, "></{}>", self.name)?;
         } else {
             write!(writer, ">")?;
 
-            Document::write_element(writer, &self.children)?;
+            // Raw text was never parsed as HTML, so it's written as is.
+            let raw = element::is_raw_text(&self.name);
+            Document::write_element(writer, &self.children, raw, options)?;
 
             write!(writer, "</{}>", self.name)?;
         }
 
         Ok(())
     }
+
+    /// Write the start of the opening tag, up to its closing `>`.
+    fn write_start<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "<{}", self.name)?;
+
+        for attribute in self.attributes.iter() {
+            write!(writer, " {}", attribute.name)?;
+
+            if let Some(value) = &attribute.value {
+                write!(writer, r#"="{}""#, escape::attribute(value))?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Document<'_> {
     pub fn write<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.write_with(writer, &WriteOptions::default())
+    }
+
+    pub fn write_with<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        options: &WriteOptions,
+    ) -> std::io::Result<()> {
         write!(writer, "<!DOCTYPE html>")?;
-        Self::write_element(writer, &self.nodes)
+        Self::write_element(writer, &self.nodes, false, options)
     }
 
-    fn write_element<W: std::io::Write>(writer: &mut W, nodes: &[Node<'_>]) -> std::io::Result<()> {
+    /// Write the document's nodes without a doctype.
+    pub fn write_fragment<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        Self::write_element(writer, &self.nodes, false, &WriteOptions::default())
+    }
+
+    fn write_element<W: std::io::Write>(
+        writer: &mut W,
+        nodes: &[Node<'_>],
+        raw: bool,
+        options: &WriteOptions,
+    ) -> std::io::Result<()> {
         for node in nodes {
             match node {
-                Node::Element(element) => element.write(writer)?,
+                Node::Element(element) => element.write_with(writer, options)?,
+                Node::Text(t) if raw => {
+                    writer.write_all(t.as_bytes())?;
+                }
                 Node::Text(t) => {
-                    writer.write(t.as_bytes())?;
+                    writer.write_all(escape::text(t).as_bytes())?;
+                }
+                Node::Raw(markup) => {
+                    writer.write_all(markup.as_bytes())?;
+                }
+                Node::Comment(comment) if options.comments => {
+                    write!(writer, "<!--{comment}-->")?;
                 }
                 Node::Comment(_) => {}
             }
@@ -172,3 +1022,662 @@ impl Document<'_> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render(document: &str, component: &str) -> String {
+        let component = Component::new(component).unwrap();
+        let mut document = Document::new(document).unwrap();
+        document
+            .expand(|name| (name == component.root.name).then_some(&component))
+            .unwrap();
+
+        let mut output = Vec::new();
+        document.write(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn interpolates_text() {
+        let output = render(
+            r#"<PageTitle title="Home" />"#,
+            r#"<PageTitle title="Untitled" date><title>{title} | {date}{missing}</title></PageTitle>"#,
+        );
+
+        assert_eq!(output, "<!DOCTYPE html><title>Home | {missing}</title>");
+    }
+
+    #[test]
+    fn places_children_at_the_outlet() {
+        let output = render(
+            r#"<Panel><b>Body</b></Panel>"#,
+            r#"<Panel><section><h2>Title</h2><children /><footer>End</footer></section></Panel>"#,
+        );
+
+        assert_eq!(
+            output,
+            "<!DOCTYPE html><section><h2>Title</h2><b>Body</b><footer>End</footer></section>"
+        );
+    }
+
+    #[test]
+    fn interpolates_nested_text_but_not_scripts() {
+        let output = render(
+            r#"<Badge label="New" />"#,
+            r#"<Badge label><span class="badge"><b>{label}</b></span><script>let o = {label};</script></Badge>"#,
+        );
+
+        assert_eq!(
+            output,
+            r#"<!DOCTYPE html><span class="badge"><b>New</b></span><script>let o = {label};</script>"#
+        );
+    }
+
+    #[test]
+    fn defaults_and_requires_attributes() {
+        let card = r#"<Card title! width="300px"><div data-width="width">{title}</div></Card>"#;
+
+        assert_eq!(
+            render(r#"<Card title="Hello" />"#, card),
+            r#"<!DOCTYPE html><div data-width="300px">Hello</div>"#
+        );
+        assert_eq!(
+            render(r#"<Card title="Wide" width="500px" />"#, card),
+            r#"<!DOCTYPE html><div data-width="500px">Wide</div>"#
+        );
+
+        let component = Component::new(card).unwrap();
+        let mut document = Document::new(r#"<Card width="500px" />"#).unwrap();
+        assert_eq!(
+            document
+                .expand(|name| (name == "Card").then_some(&component))
+                .unwrap_err()
+                .to_string(),
+            "<Card> requires the `title` attribute"
+        );
+    }
+
+    #[test]
+    fn renders_conditionally() {
+        let link = r#"<Link href icon><If cond="href"><a href="href">Go</a><Else/><span>Go</span></If><If cond="icon"><img src="icon"/></If></Link>"#;
+
+        assert_eq!(
+            render(r#"<Link href="/blog/" icon="go.svg" />"#, link),
+            r#"<!DOCTYPE html><a href="/blog/">Go</a><img src="go.svg">"#
+        );
+        assert_eq!(render("<Link />", link), "<!DOCTYPE html><span>Go</span>");
+        assert_eq!(
+            render(r#"<Link href="" />"#, link),
+            "<!DOCTYPE html><span>Go</span>"
+        );
+
+        let badge = r#"<Badge featured><If cond="featured"><b>Featured</b></If></Badge>"#;
+        assert_eq!(
+            render("<Badge featured />", badge),
+            "<!DOCTYPE html><b>Featured</b>"
+        );
+        assert_eq!(
+            render(r#"<Badge featured="false" />"#, badge),
+            "<!DOCTYPE html>"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_conditions() {
+        let link =
+            Component::new(r#"<Link href><If cond="hreff"><a href="href">Go</a></If></Link>"#)
+                .unwrap();
+        let mut document = Document::new(r#"<Link href="/" />"#).unwrap();
+        let error = document
+            .expand(|name| (name == "Link").then_some(&link))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            r#"<If cond="hreff"> doesn't name a prop of <Link>"#
+        );
+
+        let mut document = Document::new(r#"<If cond="title"><p>Hi</p></If>"#).unwrap();
+        assert!(matches!(
+            document.expand(|_| None),
+            Err(ExpandError::UnknownCondition {
+                component: None,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn renders_conditionally_over_data() {
+        let post = |title: &str, featured: Option<&str>| {
+            let mut fields = vec![("title".to_string(), Value::String(title.into()))];
+            if let Some(featured) = featured {
+                fields.push(("featured".to_string(), Value::String(featured.into())));
+            }
+            Value::Map(fields.into_iter().collect())
+        };
+        let data = Value::Map(
+            [(
+                "posts".to_string(),
+                Value::List(vec![
+                    post("A", Some("yes")),
+                    post("B", Some("false")),
+                    post("C", None),
+                ]),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        for source in [
+            r#"<For each="posts" as="post"><If cond="{post.featured}"><b>{post.title}</b><Else /><i>{post.title}</i></If></For>"#,
+            r#"<For each="posts"><If cond="{featured}"><b>{title}</b><Else /><i>{title}</i></If></For>"#,
+        ] {
+            let mut document = Document::new(source).unwrap();
+            document.expand_with_data(|_| None, Some(&data)).unwrap();
+
+            let mut output = Vec::new();
+            document.write_fragment(&mut output).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "<b>A</b><i>B</i><i>C</i>"
+            );
+        }
+    }
+
+    #[test]
+    fn scopes_component_styles() {
+        let card = Component::new(
+            r#"<Card><style>p { margin: 0 }</style><div class="card"><p>Hi</p><children /></div></Card>"#,
+        )
+        .unwrap();
+        let (css, class) = card.style.as_ref().unwrap();
+        assert_eq!(css, &format!("p.{class} {{ margin: 0 }}"));
+
+        let mut document = Document::new("<Card><p>Slot</p></Card>").unwrap();
+        document
+            .expand(|name| (name == "Card").then_some(&card))
+            .unwrap();
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(r#"<div class="card {class}"><p class="{class}">Hi</p><p>Slot</p></div>"#)
+        );
+    }
+
+    #[test]
+    fn preserves_raw_text() {
+        let code = "<Code title><pre>  {title} <b>kept</b>\n    &lt;as is&gt;</pre><textarea>\n  <i>{title}</i></textarea></Code>";
+
+        assert_eq!(
+            render(r#"<Code title="T" />"#, code),
+            "<!DOCTYPE html><pre>  {title} <b>kept</b>\n    &lt;as is&gt;</pre><textarea>\n  <i>{title}</i></textarea>"
+        );
+    }
+
+    #[test]
+    fn writes_void_elements() {
+        let mut document = Document::new(
+            r#"<head><meta charset="utf-8"><link rel="icon" href="/a.ico"></head><p>one<br>two<wbr/>three</p><video><source src="a.webm"><track src="a.vtt"></video><div></div>"#,
+        )
+        .unwrap();
+        document.expand(|_| None).unwrap();
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<head><meta charset="utf-8"><link rel="icon" href="/a.ico"></head><p>one<br>two<wbr>three</p><video><source src="a.webm"><track src="a.vtt"></video><div></div>"#
+        );
+    }
+
+    #[test]
+    fn escapes_when_writing() {
+        assert_eq!(
+            render(
+                r#"<Quote text="Tom &amp; Jerry < Spike" by="say \"hi\"" />"#,
+                r#"<Quote text by><q title="by">{text}</q><script>a && b</script></Quote>"#
+            ),
+            r#"<!DOCTYPE html><q title="say &quot;hi&quot;">Tom &amp; Jerry &lt; Spike</q><script>a && b</script>"#
+        );
+
+        // Other quoting is normalized to double quotes.
+        assert_eq!(
+            render(
+                r#"<Quote text="" by="" />"#,
+                r#"<Quote text by><q class='a "b"' hidden=hidden>Hi</q></Quote>"#
+            ),
+            r#"<!DOCTYPE html><q class="a &quot;b&quot;" hidden="hidden">Hi</q>"#
+        );
+
+        let mut output = Vec::new();
+        Document {
+            nodes: vec![
+                Node::Raw("<b>trusted</b>".into()),
+                Node::Text("1 < 2".into()),
+            ],
+        }
+        .write_fragment(&mut output)
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "<b>trusted</b>1 &lt; 2");
+    }
+
+    #[test]
+    fn round_trips_entities() {
+        assert_eq!(
+            render(
+                r#"<Footer owner="Tom &amp; Jerry" href="/?a=1&amp;b=2" />"#,
+                r#"<Footer owner href><a href="href">&copy;&nbsp;{owner} &hearts; &amp;hearts; &lt;3</a></Footer>"#
+            ),
+            r#"<!DOCTYPE html><a href="/?a=1&amp;b=2">©&nbsp;Tom &amp; Jerry ♥ &amp;hearts; &lt;3</a>"#
+        );
+    }
+
+    #[test]
+    fn expands_fragments() {
+        let component = Component::fragment(
+            "NavItems",
+            "<li {..}><a href=\"/\">Home</a></li>\n<li><children /></li>\n<style>li { flex: 1 }</style>",
+        )
+        .unwrap();
+        let mut document = Document::new(
+            r#"<ul><NavItems class="first"><a href="/blog/">Blog</a></NavItems></ul>"#,
+        )
+        .unwrap();
+        document
+            .expand(|name| (name == "NavItems").then_some(&component))
+            .unwrap();
+
+        let class = &component.style.as_ref().unwrap().1;
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                r#"<ul><li class="first {class}"><a href="/" class="{class}">Home</a></li>
+<li class="{class}"><a href="/blog/">Blog</a></li>
+</ul>"#
+            )
+        );
+    }
+
+    #[test]
+    fn lists_unresolved_components() {
+        let component = Component::new("<Card><div><children /></div></Card>").unwrap();
+        let mut document = Document::new(
+            "<Card><Crad /><svg><foreignObject /><icons:Nope /><ui.Nope /></svg><Crad /></Card>",
+        )
+        .unwrap();
+        document
+            .expand(|name| (name == "Card").then_some(&component))
+            .unwrap();
+
+        assert_eq!(document.unresolved(), ["Crad", "icons:Nope", "ui.Nope"]);
+    }
+
+    #[test]
+    fn hoists_head_elements() {
+        let component = Component::new(
+            r#"<Post><Head><title>Post</title><meta name="description" content="A post"><link rel="stylesheet" href="/post.css"><style>p{}</style></Head><p>Text</p></Post>"#,
+        )
+        .unwrap();
+        let mut document = Document::new(
+            r#"<html><head><meta charset="utf-8"><title>Site</title><meta name="description" content="A site"></head><body><Post /><Post /></body></html>"#,
+        )
+        .unwrap();
+        document
+            .expand(|name| (name == "Post").then_some(&component))
+            .unwrap();
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<html><head><meta charset="utf-8"><title>Post</title><meta name="description" content="A post"><link rel="stylesheet" href="/post.css"><style>p{}</style></head><body><p>Text</p><p>Text</p></body></html>"#
+        );
+
+        // Fragments have no head of their own to hoist into.
+        let mut fragment = Document::new("<Post />").unwrap();
+        fragment
+            .expand_fragment_with_data(|name| (name == "Post").then_some(&component), None)
+            .unwrap();
+        let mut output = Vec::new();
+        fragment.write_fragment(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "<p>Text</p>");
+    }
+
+    #[test]
+    fn leaves_scripts_out_of_fragments() {
+        let component =
+            Component::new(r#"<Counter><button>0</button><script>count()</script></Counter>"#)
+                .unwrap();
+        let write = |document: &Document| {
+            let mut output = Vec::new();
+            document.write_fragment(&mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let mut page = Document::new("<p><Counter /></p>").unwrap();
+        page.expand_with_data(|name| (name == "Counter").then_some(&component), None)
+            .unwrap();
+        assert_eq!(
+            write(&page),
+            "<p><button>0</button></p><script>count()</script>"
+        );
+
+        let mut fragment = Document::new("<p><Counter /></p>").unwrap();
+        fragment
+            .expand_fragment_with_data(|name| (name == "Counter").then_some(&component), None)
+            .unwrap();
+        assert_eq!(write(&fragment), "<p><button>0</button></p>");
+    }
+
+    #[test]
+    fn mutates_documents() {
+        let mut document =
+            Document::new(r#"<html><head></head><body><img src="a.png"><p>Text</p></body></html>"#)
+                .unwrap();
+
+        let head = document.find_mut(|e| e.name == "head").unwrap();
+        let mut link = Element::new("link");
+        link.set_attribute("rel", Some("stylesheet".into()));
+        link.set_attribute("href", Some("/output.css".into()));
+        head.append_child(Node::Element(link));
+
+        let body = document.find_mut(|e| e.name == "body").unwrap();
+        let img = body.children[0].element_mut().unwrap();
+        img.set_attribute("loading", Some("lazy".into()));
+        img.set_attribute("src", Some("b.png".into()));
+        body.children[0].wrap(Element::new("figure"));
+        body.children[1].replace_with(Node::Text("Replaced".into()));
+        body.insert_child(0, Node::Comment("first".into()));
+        assert_eq!(body.remove_child(0), Node::Comment("first".into()));
+        assert!(body.children[0]
+            .element_mut()
+            .unwrap()
+            .remove_attribute("id")
+            .is_none());
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<html><head><link rel="stylesheet" href="/output.css"></head><body><figure><img src="b.png" loading="lazy"></figure>Replaced</body></html>"#
+        );
+    }
+
+    #[test]
+    fn keeps_comments_when_asked() {
+        let document = Document::new(
+            "<head><!--[if IE]><link rel=\"stylesheet\" href=\"ie.css\"><![endif]--></head>",
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        document.write(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "<!DOCTYPE html><head></head>"
+        );
+
+        let mut output = Vec::new();
+        document
+            .write_with(&mut output, &WriteOptions { comments: true })
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<!DOCTYPE html><head><!--[if IE]><link rel="stylesheet" href="ie.css"><![endif]--></head>"#
+        );
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let components: std::collections::HashMap<_, _> = [
+            ("A", "<A><div><B /></div></A>"),
+            ("B", "<B><C /></B>"),
+            ("C", "<C><A /></C>"),
+        ]
+        .into_iter()
+        .map(|(name, source)| (name, Component::new(source).unwrap()))
+        .collect();
+
+        let mut document = Document::new("<main><B /></main>").unwrap();
+        assert_eq!(
+            document
+                .expand(|name| components.get(name))
+                .unwrap_err()
+                .to_string(),
+            "Components include themselves: B -> C -> A -> B"
+        );
+    }
+
+    #[test]
+    fn expands_recursive_components() {
+        let item = r#"<Item more><li>Item</li><If cond="more"><Item /></If></Item>"#;
+        assert_eq!(
+            render("<ul><Item more /></ul>", item),
+            "<!DOCTYPE html><ul><li>Item</li><li>Item</li></ul>"
+        );
+    }
+
+    #[test]
+    fn hoists_component_scripts_once() {
+        let icon = r#"<Icon><script src="/icon.js"></script><b>!</b></Icon>"#;
+
+        assert_eq!(
+            render("<html><body><Icon /><p><Icon /></p></body></html>", icon),
+            r#"<!DOCTYPE html><html><body><b>!</b><p><b>!</b></p><script src="/icon.js"></script></body></html>"#
+        );
+        assert_eq!(
+            render(
+                r#"<html><body><Icon /><script src="/icon.js"></script></body></html>"#,
+                icon
+            ),
+            r#"<!DOCTYPE html><html><body><b>!</b><script src="/icon.js"></script></body></html>"#
+        );
+        assert_eq!(
+            render("<Icon /><Icon />", icon),
+            r#"<!DOCTYPE html><b>!</b><b>!</b><script src="/icon.js"></script>"#
+        );
+    }
+
+    #[test]
+    fn spreads_undeclared_attributes() {
+        let button =
+            r#"<Button label><button class="button" {..} type="button">{label}</button></Button>"#;
+
+        assert_eq!(
+            render(
+                r#"<Button label="Go" id="go" aria-label="Go home" type="submit" />"#,
+                button
+            ),
+            r#"<!DOCTYPE html><button class="button" id="go" aria-label="Go home" type="submit">Go</button>"#
+        );
+        assert_eq!(
+            render(r#"<Button label="Go" />"#, button),
+            r#"<!DOCTYPE html><button class="button" type="button">Go</button>"#
+        );
+        assert_eq!(
+            render(r#"<Link id="x" />"#, r#"<Link><a href="/">Home</a></Link>"#),
+            r#"<!DOCTYPE html><a href="/">Home</a>"#
+        );
+    }
+
+    #[test]
+    fn repeats_over_named_items() {
+        let data = Value::Map(
+            [(
+                "nav".to_string(),
+                Value::List(vec![Value::Map(
+                    [
+                        ("label".to_string(), Value::String("Home".into())),
+                        (
+                            "tags".to_string(),
+                            Value::List(vec![
+                                Value::String("rust".into()),
+                                Value::String("web".into()),
+                            ]),
+                        ),
+                    ]
+                    .into_iter()
+                    .collect(),
+                )]),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        for (source, expected) in [
+            (
+                r#"<For each="nav" as="link"><a title="{link.label}">{link.label}</a></For>"#,
+                r#"<a title="Home">Home</a>"#,
+            ),
+            (
+                r#"<For each="red, green," as="color"><i class="{color}">{color}</i></For>"#,
+                r#"<i class="red">red</i><i class="green">green</i>"#,
+            ),
+            (r#"<For each="nav.0" as="link">{link.label}</For>"#, "Home"),
+            (
+                r#"<For each="nav" as="link"><For each="link.tags" as="tag"><i>{link.label} {tag}</i></For></For>"#,
+                "<i>Home rust</i><i>Home web</i>",
+            ),
+            (
+                r#"<For each="nav"><For each="tags" as="tag"><i>{label} {tag}</i></For></For>"#,
+                "<i>Home rust</i><i>Home web</i>",
+            ),
+        ] {
+            let mut document = Document::new(source).unwrap();
+            document.expand_with_data(|_| None, Some(&data)).unwrap();
+
+            let mut output = Vec::new();
+            document.write_fragment(&mut output).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected, "{source}");
+        }
+
+        let mut document = Document::new(r#"<For each="missing" as="item">{item}</For>"#).unwrap();
+        assert_eq!(
+            document
+                .expand_with_data(|_| None, Some(&data))
+                .unwrap_err()
+                .to_string(),
+            r#"<For each="missing"> doesn't match any data"#
+        );
+
+        let mut document = Document::new(r#"<For as="item">{item}</For>"#).unwrap();
+        assert_eq!(
+            document
+                .expand_with_data(|_| None, Some(&data))
+                .unwrap_err()
+                .to_string(),
+            "<For> requires the `each` attribute"
+        );
+    }
+
+    #[test]
+    fn repeats_over_data() {
+        let link = |label: &str, href: &str| {
+            Value::Map(
+                [
+                    ("label".to_string(), Value::String(label.into())),
+                    ("href".to_string(), Value::String(href.into())),
+                ]
+                .into_iter()
+                .collect(),
+            )
+        };
+        let data = Value::Map(
+            [(
+                "nav".to_string(),
+                Value::List(vec![link("Home", "/"), link("Blog", "/blog/")]),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        let component = Component::new(
+            r#"<Nav><ul><For each="nav"><li><a href="{href}">{label}</a></li></For></ul></Nav>"#,
+        )
+        .unwrap();
+        let mut document = Document::new("<Nav />").unwrap();
+        document
+            .expand_with_data(|name| (name == "Nav").then_some(&component), Some(&data))
+            .unwrap();
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<ul><li><a href="/">Home</a></li><li><a href="/blog/">Blog</a></li></ul>"#
+        );
+    }
+
+    #[test]
+    fn outlives_sources_once_owned() {
+        let source = String::from(
+            r#"<Card title! tag="div"><p class="card">{title}</p><style>p { margin: 0 }</style></Card>"#,
+        );
+        let component = Component::new(&source).unwrap().into_owned();
+        drop(source);
+
+        let source = String::from(r#"<main><Card title="Owned &amp; kept" /></main>"#);
+        let mut document = Document::new(&source).unwrap().into_owned();
+        drop(source);
+
+        document
+            .expand(|name| (name == "Card").then_some(&component))
+            .unwrap();
+        assert_eq!(component.required, ["title"]);
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        let class = &component.style.as_ref().unwrap().1;
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(r#"<main><p class="card {class}">Owned &amp; kept</p></main>"#)
+        );
+    }
+
+    #[test]
+    fn recovers_from_malformed_markup() {
+        let write = |document: &Document| {
+            let mut output = Vec::new();
+            document.write_fragment(&mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let source = r#"<main><p class="a" "oops">One<div>Two</span></main><b>1 < 2</b><i>"#;
+        let (document, recovered) = Document::lenient(source);
+        assert_eq!(
+            write(&document),
+            r#"<main><p class="a">One<div>Two</div></p></main><b>1 &lt; 2</b><i></i>"#
+        );
+        let recovered: Vec<_> = recovered
+            .iter()
+            .map(|r| (r.offset, r.message.as_str()))
+            .collect();
+        assert_eq!(
+            recovered,
+            [
+                (6, "Malformed <p> tag"),
+                (37, "</span> doesn't close any element"),
+                (29, "<div> is never closed"),
+                (6, "<p> is never closed"),
+                (56, "Stray `<`, which should be written `&lt;`"),
+                (63, "<i> is never closed"),
+            ]
+        );
+
+        let (document, recovered) = Document::lenient("<p>é and <b>ü</b> more</p>");
+        assert!(recovered.is_empty());
+        assert_eq!(write(&document), "<p>é and <b>ü</b> more</p>");
+
+        let source =
+            r#"<body><!-- note --><Card title="x &amp; y" /><script>if (a < b) {}</script></body>"#;
+        let (lenient, recovered) = Document::lenient(source);
+        assert!(recovered.is_empty());
+        assert_eq!(lenient.nodes, Document::new(source).unwrap().nodes);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::element::{self, Element, Node};
+use crate::{escape, Document};
+use std::io::{Result, Write};
+
+/// Attributes whose presence alone is meaningful, so `defer="defer"` can become `defer`.
+const BOOLEAN_ATTRIBUTES: &[&str] = &[
+    "allowfullscreen",
+    "async",
+    "autofocus",
+    "autoplay",
+    "checked",
+    "controls",
+    "default",
+    "defer",
+    "disabled",
+    "formnovalidate",
+    "hidden",
+    "inert",
+    "ismap",
+    "itemscope",
+    "loop",
+    "multiple",
+    "muted",
+    "nomodule",
+    "novalidate",
+    "open",
+    "playsinline",
+    "readonly",
+    "required",
+    "reversed",
+    "selected",
+];
+
+/// Elements laid out as blocks, or not rendered at all, so whitespace
+/// around their tags never shows.
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "li",
+    "link",
+    "main",
+    "meta",
+    "nav",
+    "noscript",
+    "ol",
+    "p",
+    "pre",
+    "script",
+    "section",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "ul",
+];
+
+/// Elements whose whitespace matters, which are always written as they are.
+const PRESERVED: [&str; 5] = ["pre", "code", "textarea", "script", "style"];
+
+impl Document<'_> {
+    /// Write the document as compactly as possible, collapsing whitespace,
+    /// dropping comments, and shortening boolean attributes.
+    ///
+    /// Whitespace between two tags is dropped entirely when it spans a
+    /// line break beside a block, since that's almost always source
+    /// indentation. Between inline elements, it's kept as a single space.
+    pub fn write_minified<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write!(writer, "<!DOCTYPE html>")?;
+        write_nodes(writer, &self.nodes, &mut true, true)
+    }
+}
+
+impl Element<'_> {
+    /// Write the element like [`Document::write_minified`].
+    pub fn write_minified<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_element(writer, self, &mut true)
+    }
+}
+
+fn is_block(name: &str) -> bool {
+    BLOCKS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+/// Write `element`, where `after_block` tracks whether the last
+/// thing written was a block's tag rather than text or an inline tag.
+fn write_element<W: Write>(
+    writer: &mut W,
+    element: &Element<'_>,
+    after_block: &mut bool,
+) -> Result<()> {
+    write!(writer, "<{}", element.name)?;
+    for attribute in &element.attributes {
+        write!(writer, " {}", attribute.name)?;
+
+        let boolean = BOOLEAN_ATTRIBUTES.contains(&attribute.name.to_ascii_lowercase().as_str());
+        match attribute.value.as_deref() {
+            Some(value)
+                if boolean && (value.is_empty() || value.eq_ignore_ascii_case(&attribute.name)) => {
+            }
+            Some(value) => write!(writer, r#"="{}""#, escape::attribute(value))?,
+            None => {}
+        }
+    }
+
+    let block = is_block(&element.name);
+    *after_block = block;
+    write!(writer, ">")?;
+    if element::is_void(&element.name) {
+        return Ok(());
+    }
+    if PRESERVED.contains(&element.name.as_ref()) {
+        let raw = element::is_raw_text(&element.name);
+        Document::write_element(writer, &element.children, raw, &Default::default())?;
+    } else {
+        write_nodes(writer, &element.children, after_block, block)?;
+    }
+    *after_block = block;
+    write!(writer, "</{}>", element.name)
+}
+
+/// Write `nodes`, where `in_block` is whether their parent is a block.
+fn write_nodes<W: Write>(
+    writer: &mut W,
+    nodes: &[Node<'_>],
+    after_block: &mut bool,
+    in_block: bool,
+) -> Result<()> {
+    let mut index = 0;
+    while index < nodes.len() {
+        match &nodes[index] {
+            Node::Element(element) => write_element(writer, element, after_block)?,
+            Node::Raw(markup) => {
+                writer.write_all(markup.as_bytes())?;
+                *after_block = markup.trim_end().ends_with('>');
+            }
+            Node::Text(_) | Node::Comment(_) => {
+                // Text split up by comments or interpolation is collapsed as a whole.
+                let mut text = String::new();
+                while let Some(Node::Text(_) | Node::Comment(_)) = nodes.get(index) {
+                    if let Node::Text(t) = &nodes[index] {
+                        text.push_str(t);
+                    }
+                    index += 1;
+                }
+
+                // What follows is either a sibling element or the parent's closing tag.
+                let before_block = match nodes.get(index) {
+                    Some(Node::Element(element)) => is_block(&element.name),
+                    Some(Node::Raw(markup)) => markup.trim_start().starts_with('<'),
+                    _ => in_block,
+                };
+                if text.trim_ascii().is_empty()
+                    && (text.contains('\n') && (*after_block || before_block))
+                {
+                    continue;
+                }
+
+                let mut collapsed = String::with_capacity(text.len());
+                let mut space = false;
+                for c in text.chars() {
+                    // Only HTML's whitespace, so non-breaking spaces are kept.
+                    if c.is_ascii_whitespace() {
+                        space = true;
+                    } else {
+                        if space {
+                            collapsed.push(' ');
+                            space = false;
+                        }
+                        collapsed.push(c);
+                    }
+                }
+                if space {
+                    collapsed.push(' ');
+                }
+                if !collapsed.is_empty() {
+                    writer.write_all(escape::text(&collapsed).as_bytes())?;
+                    *after_block = false;
+                }
+                continue;
+            }
+        }
+        index += 1;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn minify(source: &str) -> String {
+        let mut output = Vec::new();
+        Document::new(source)
+            .unwrap()
+            .write_minified(&mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn minifies() {
+        assert_eq!(
+            minify("<div>\n  <p class=\"a  b\">Hello,\n   world</p>\n  <!-- note -->\n</div>"),
+            r#"<!DOCTYPE html><div><p class="a  b">Hello, world</p></div>"#
+        );
+        assert_eq!(
+            minify("<p>Components are nice.\n  <sup>2</sup>\n</p>"),
+            "<!DOCTYPE html><p>Components are nice. <sup>2</sup></p>"
+        );
+        assert_eq!(
+            minify(r#"<b>a</b> <i>b</i><input disabled="" checked="checked" value="x"/>"#),
+            r#"<!DOCTYPE html><b>a</b> <i>b</i><input disabled checked value="x">"#
+        );
+        assert_eq!(
+            minify("<pre>  keep\n  <code>this </code></pre>\n<script defer=\"defer\"> if (a < b) {} </script>"),
+            "<!DOCTYPE html><pre>  keep\n  <code>this </code></pre><script defer> if (a < b) {} </script>"
+        );
+        assert_eq!(
+            minify("<p>a  &nbsp;\n b</p>"),
+            "<!DOCTYPE html><p>a &nbsp; b</p>"
+        );
+    }
+
+    #[test]
+    fn keeps_spaces_between_inline_siblings() {
+        assert_eq!(
+            minify("<nav>\n  <a href=\"/\">x</a>\n  <a href=\"/y\">y</a>\n</nav>"),
+            r#"<!DOCTYPE html><nav><a href="/">x</a> <a href="/y">y</a></nav>"#
+        );
+        assert_eq!(
+            minify("<ul>\n  <li>\n    <b>a</b>\n  </li>\n  <li><i>b</i></li>\n</ul>"),
+            "<!DOCTYPE html><ul><li><b>a</b></li><li><i>b</i></li></ul>"
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use crate::element::{Attribute, Element, Node};
+use crate::element::{self, Attribute, Element, Node, Text};
+use crate::entity;
+use crate::SPREAD;
+use std::borrow::Cow;
 use winnow::{
     ascii::multispace0,
     combinator::{alt, cut_err, delimited, dispatch, opt, peek, preceded, repeat},
@@ -12,39 +15,70 @@ pub fn identifier<'s>(input: &mut &'s str) -> PResult<&'s str> {
     any.verify(|c: &char| c.is_alphabetic())
         .parse_peek(*input)?;
 
-    take_while(1.., |c: char| c.is_alphanumeric() || c == '_' || c == '-').parse_next(input)
+    take_while(1.., |c: char| {
+        c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')
+    })
+    .parse_next(input)
 }
 
 fn parse_string<'s>(input: &mut &'s str) -> PResult<&'s str> {
-    let checkpoint = input.checkpoint();
-    '"'.parse_next(input)?;
-
-    // go until we find another double quote not preceeded by a backslash
-    let mut last_char = '"';
-    for (i, char) in input.char_indices() {
-        if char == '"' && last_char != '\\' {
-            let string = &input[..i];
-            *input = &input[i + 1..];
-            return Ok(string);
+    quoted('"').parse_next(input)
+}
+
+fn quoted<'s>(mut quote: char) -> impl FnMut(&mut &'s str) -> PResult<&'s str> {
+    move |input| {
+        let checkpoint = input.checkpoint();
+        quote.parse_next(input)?;
+
+        // go until we find another quote not preceeded by a backslash
+        let mut last_char = quote;
+        for (i, char) in input.char_indices() {
+            if char == quote && last_char != '\\' {
+                let string = &input[..i];
+                *input = &input[i + 1..];
+                return Ok(string);
+            }
+
+            last_char = char;
         }
 
-        last_char = char;
+        Err(ErrMode::Cut(ContextError::default().add_context(
+            input,
+            &checkpoint,
+            StrContext::Expected(StrContextValue::CharLiteral(quote)),
+        )))
     }
+}
 
-    Err(ErrMode::Cut(ContextError::default().add_context(
-        input,
-        &checkpoint,
-        StrContext::Expected(StrContextValue::CharLiteral('"')),
-    )))
+/// A value without quotes, like `hidden=hidden`, which runs until
+/// whitespace or the end of the tag.
+///
+/// As in HTML, a slash just before the end is part of the value, like
+/// `<a href=/about/>`, so self-closing takes whitespace: `<img src=a.png />`.
+fn unquoted<'s>(input: &mut &'s str) -> PResult<&'s str> {
+    take_while(1.., |c: char| {
+        !c.is_whitespace() && !matches!(c, '"' | '\'' | '=' | '<' | '>' | '`')
+    })
+    .parse_next(input)
 }
 
 fn attribute<'s>(input: &mut &'s str) -> PResult<Attribute<'s>> {
-    let name = identifier.parse_next(input)?;
-    let value = opt((delimited(multispace0, '=', multispace0), parse_string))
-        .parse_next(input)?
-        .map(|(_, string)| string);
-
-    Ok(Attribute { name, value })
+    // A trailing `!` marks a component's attribute as required,
+    // and `{..}` marks where a component spreads undeclared attributes.
+    let remaining = input.len();
+    let name = alt(((identifier, opt('!')).take(), SPREAD)).parse_next(input)?;
+    let value = opt((
+        delimited(multispace0, '=', multispace0),
+        alt((parse_string, quoted('\''), unquoted)),
+    ))
+    .parse_next(input)?
+    .map(|(_, string)| entity::decode(string));
+
+    Ok(Attribute {
+        name: name.into(),
+        value,
+        offset: Some(remaining),
+    })
 }
 
 fn node<'s>(input: &mut &'s str) -> PResult<Node<'s>> {
@@ -53,7 +87,8 @@ fn node<'s>(input: &mut &'s str) -> PResult<Node<'s>> {
         alt((
             preceded(
                 "<!--",
-                advance_to::<_, _, ContextError>("-->", '-').map(|(text, _)| Node::Comment(text)),
+                advance_to::<_, _, ContextError>("-->", '-')
+                    .map(|(text, _)| Node::Comment(text.into())),
             ),
             preceded(peek("<"), element.map(Node::Element)),
         )),
@@ -61,14 +96,42 @@ fn node<'s>(input: &mut &'s str) -> PResult<Node<'s>> {
 
     dispatch! {peek(any);
         '<' => bracket_parser,
-        _ => take_until(1.., '<').map(Node::Text)
+        // Its `offset` is counted back from the end, like an element's.
+        _ => |input: &mut &'s str| {
+            let remaining = input.len();
+            take_until(1.., '<')
+                .map(|text: &'s str| {
+                    Node::Text(Text {
+                        content: Cow::Borrowed(text),
+                        offset: Some(remaining),
+                    })
+                })
+                .parse_next(input)
+        }
     }
     .context(StrContext::Label("tag or text"))
     .parse_next(input)
 }
 
+/// Parse nodes, decoding the character references in their text.
 pub(crate) fn nodes<'s>(input: &mut &'s str) -> PResult<Vec<Node<'s>>> {
-    repeat(0.., node).parse_next(input)
+    repeat(0.., node)
+        .fold(Vec::new, |mut nodes, node| {
+            match node {
+                Node::Text(Text {
+                    content: Cow::Borrowed(text),
+                    offset,
+                }) => nodes.extend(entity::split(text).into_iter().map(|(start, piece)| {
+                    Node::Text(Text {
+                        content: piece,
+                        offset: offset.map(|remaining| remaining - start),
+                    })
+                })),
+                node => nodes.push(node),
+            }
+            nodes
+        })
+        .parse_next(input)
 }
 
 fn closing_tag<'a>(name: &'a str) -> impl Fn(&mut &str) -> PResult<()> + 'a {
@@ -109,7 +172,10 @@ where
     }
 }
 
+/// Parse an element, whose `offset` is counted back from the end of the
+/// source until [`locate`] turns it around.
 pub fn element<'s>(input: &mut &'s str) -> PResult<Element<'s>> {
+    let remaining = input.len();
     '<'.parse_next(input)?;
 
     let name = identifier.parse_next(input)?;
@@ -118,24 +184,31 @@ pub fn element<'s>(input: &mut &'s str) -> PResult<Element<'s>> {
 
     match close {
         "/>" => Ok(Element {
-            name,
+            name: name.into(),
             attributes,
             children: Vec::new(),
+            offset: Some(remaining),
         }),
         ">" => match name {
-            "script" | "style" => {
+            name if element::is_raw_text(name) => {
+                let text_remaining = input.len();
                 let (text, _) = advance_to(closing_tag(name), '<').parse_next(input)?;
 
                 Ok(Element {
-                    name,
+                    name: name.into(),
                     attributes,
-                    children: vec![Node::Text(text)],
+                    children: vec![Node::Text(Text {
+                        content: text.into(),
+                        offset: Some(text_remaining),
+                    })],
+                    offset: Some(remaining),
                 })
             }
-            "hr" | "input" | "link" | "img" => Ok(Element {
-                name,
+            name if element::is_void(name) => Ok(Element {
+                name: name.into(),
                 attributes,
                 children: vec![],
+                offset: Some(remaining),
             }),
             _ => {
                 let nodes = nodes.parse_next(input)?;
@@ -146,9 +219,10 @@ pub fn element<'s>(input: &mut &'s str) -> PResult<Element<'s>> {
                     .parse_next(input)?;
 
                 Ok(Element {
-                    name,
+                    name: name.into(),
                     attributes,
                     children: nodes,
+                    offset: Some(remaining),
                 })
             }
         },
@@ -156,6 +230,213 @@ pub fn element<'s>(input: &mut &'s str) -> PResult<Element<'s>> {
     }
 }
 
+/// Turn the offsets [`element`] counted from the end of a source `len`
+/// bytes long into ones from its start, for its attributes and text too.
+///
+/// Elements only see the input left to parse, so this waits until the
+/// whole source is known.
+pub(crate) fn locate(element: &mut Element<'_>, len: usize) {
+    let from_start = |offset: &mut Option<usize>| *offset = offset.map(|remaining| len - remaining);
+    element::walk(element, &mut |element| {
+        from_start(&mut element.offset);
+        for attribute in &mut element.attributes {
+            from_start(&mut attribute.offset);
+        }
+        for child in &mut element.children {
+            if let Node::Text(text) = child {
+                from_start(&mut text.offset);
+            }
+        }
+    });
+}
+
+/// Like [`locate`], for every node parsed from a source `len` bytes long.
+pub(crate) fn locate_nodes(nodes: &mut [Node<'_>], len: usize) {
+    for node in nodes {
+        match node {
+            Node::Element(element) => locate(element, len),
+            Node::Text(text) => text.offset = text.offset.map(|remaining| len - remaining),
+            _ => {}
+        }
+    }
+}
+
+/// Malformed markup a lenient parse worked around, like an unclosed
+/// tag, and the byte offset into the source where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovery {
+    pub offset: usize,
+    pub message: String,
+}
+
+/// Parse nodes like [`nodes`], but work around malformed markup rather
+/// than failing, recording each problem in `recovered`.
+///
+/// Unclosed elements end where their parent does, closing tags that
+/// match nothing open are dropped, and a `<` that doesn't start a tag
+/// is kept as text.
+pub(crate) fn lenient_nodes<'s>(
+    input: &mut &'s str,
+    source: &'s str,
+    open: &mut Vec<&'s str>,
+    recovered: &mut Vec<Recovery>,
+) -> Vec<Node<'s>> {
+    let mut nodes = Vec::new();
+    while !input.is_empty() {
+        let offset = source.len() - input.len();
+        let mut recover = |message: String| recovered.push(Recovery { offset, message });
+
+        if input.starts_with("</") {
+            let mut closing = *input;
+            let name = delimited(("</", multispace0), identifier, (multispace0, '>'))
+                .parse_next(&mut closing);
+            match name {
+                // Left for whichever element it closes.
+                Ok(name) if open.contains(&name) => break,
+                Ok(name) => {
+                    recover(format!("</{name}> doesn't close any element"));
+                    *input = closing;
+                }
+                Err(_) => {
+                    recover("Malformed closing tag".into());
+                    nodes.push(Node::Text(Text {
+                        content: "<".into(),
+                        offset: Some(offset),
+                    }));
+                    *input = &input[1..];
+                }
+            }
+            continue;
+        }
+
+        if let Some(rest) = input.strip_prefix("<!--") {
+            let (comment, rest) = match rest.split_once("-->") {
+                Some(split) => split,
+                None => {
+                    recover("Unclosed comment".into());
+                    (rest, "")
+                }
+            };
+            nodes.push(Node::Comment(comment.into()));
+            *input = rest;
+            continue;
+        }
+
+        if input.starts_with('<') {
+            let mut tag = &input[1..];
+            match identifier.parse_next(&mut tag) {
+                Ok(name) => {
+                    *input = tag;
+                    let element = lenient_element(name, offset, input, source, open, recovered);
+                    nodes.push(Node::Element(element));
+                }
+                Err(_) => {
+                    recover("Stray `<`, which should be written `&lt;`".into());
+                    nodes.push(Node::Text(Text {
+                        content: "<".into(),
+                        offset: Some(offset),
+                    }));
+                    *input = &input[1..];
+                }
+            }
+            continue;
+        }
+
+        // Text takes at least its first character, which needn't be ASCII.
+        let first = input.chars().next().map_or(0, char::len_utf8);
+        let end = input[first..]
+            .find('<')
+            .map_or(input.len(), |end| end + first);
+        let (text, rest) = input.split_at(end);
+        nodes.extend(entity::split(text).into_iter().map(|(start, piece)| {
+            Node::Text(Text {
+                content: piece,
+                offset: Some(offset + start),
+            })
+        }));
+        *input = rest;
+    }
+
+    nodes
+}
+
+/// The rest of an element whose `<name` has been parsed, starting at `offset`.
+fn lenient_element<'s>(
+    name: &'s str,
+    offset: usize,
+    input: &mut &'s str,
+    source: &'s str,
+    open: &mut Vec<&'s str>,
+    recovered: &mut Vec<Recovery>,
+) -> Element<'s> {
+    let mut recover = |message: String| recovered.push(Recovery { offset, message });
+
+    let mut attributes = Vec::new();
+    loop {
+        let checkpoint = *input;
+        match preceded(multispace0, attribute).parse_next(input) {
+            Ok(attribute) => attributes.push(Attribute {
+                // Counted back from the end, like a strict parse's.
+                offset: attribute.offset.map(|remaining| source.len() - remaining),
+                ..attribute
+            }),
+            Err(_) => {
+                *input = checkpoint;
+                break;
+            }
+        }
+    }
+
+    let close = preceded(multispace0::<_, ContextError>, alt(("/>", ">"))).parse_next(input);
+    let close = close.unwrap_or_else(|_| {
+        // Whatever's left of the tag, like a stray quote, is dropped.
+        recover(format!("Malformed <{name}> tag"));
+        let end = input.find('>').map_or(input.len(), |end| end + 1);
+        *input = &input[end..];
+        ">"
+    });
+
+    let mut element = Element {
+        name: name.into(),
+        attributes,
+        children: Vec::new(),
+        offset: Some(offset),
+    };
+    if close == "/>" || element::is_void(name) {
+        return element;
+    }
+
+    if element::is_raw_text(name) {
+        let text_offset = source.len() - input.len();
+        let text = match advance_to(closing_tag(name), '<').parse_next(input) {
+            Ok((text, _)) => text,
+            Err(_) => {
+                recover(format!("<{name}> is never closed"));
+                std::mem::take(input)
+            }
+        };
+        element.children.push(Node::Text(Text {
+            content: text.into(),
+            offset: Some(text_offset),
+        }));
+        return element;
+    }
+
+    open.push(name);
+    element.children = lenient_nodes(input, source, open, recovered);
+    open.pop();
+
+    let mut closing = *input;
+    match preceded(multispace0, closing_tag(name)).parse_next(&mut closing) {
+        Ok(()) => *input = closing,
+        Err(_) => recovered.push(Recovery {
+            offset,
+            message: format!("<{name}> is never closed"),
+        }),
+    }
+    element
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -168,6 +449,14 @@ mod test {
             "test-kebab"
         );
         assert_eq!(identifier.parse_next(&mut "alpha1").unwrap(), "alpha1");
+        assert_eq!(
+            identifier.parse_next(&mut "Ui.Button>").unwrap(),
+            "Ui.Button"
+        );
+        assert_eq!(
+            identifier.parse_next(&mut "xlink:href=").unwrap(),
+            "xlink:href"
+        );
     }
 
     #[test]
@@ -219,6 +508,37 @@ mod test {
         assert_eq!(attrs[0].name, "key");
     }
 
+    #[test]
+    fn parses_other_quoting() {
+        let attrs = element
+            .parse_next(&mut r#"<img class='a "b"' hidden=hidden src=/a.png/>"#)
+            .unwrap()
+            .attributes;
+        let values: Vec<_> = attrs
+            .iter()
+            .map(|a| (a.name.as_ref(), a.value.as_deref()))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("class", Some(r#"a "b""#)),
+                ("hidden", Some("hidden")),
+                ("src", Some("/a.png/"))
+            ]
+        );
+
+        let element = element
+            .parse_next(&mut "<p><a href=/about/>About</a><img src=/a.png /></p>")
+            .unwrap();
+        let values: Vec<_> = element
+            .children
+            .iter()
+            .filter_map(Node::element)
+            .map(|e| e.attributes[0].value.as_deref())
+            .collect();
+        assert_eq!(values, [Some("/about/"), Some("/a.png")]);
+    }
+
     #[test]
     fn test_advance() {
         let js = r#"
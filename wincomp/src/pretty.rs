@@ -0,0 +1,193 @@
+use crate::element::{self, Element, Node};
+use crate::{escape, Document};
+use std::io::{Result, Write};
+
+/// How to lay out indented, human-readable HTML.
+#[derive(Debug, Clone, Copy)]
+pub struct Pretty {
+    /// Spaces per level of nesting.
+    pub indent: usize,
+    /// Elements that fit within this many columns stay on one line.
+    pub width: usize,
+    /// Keep comments, which are dropped otherwise.
+    pub comments: bool,
+}
+
+impl Default for Pretty {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            width: 100,
+            comments: false,
+        }
+    }
+}
+
+impl Document<'_> {
+    /// Write the document with one element or run of text per line,
+    /// indented by nesting.
+    ///
+    /// Whitespace between tags is reflowed, so this is meant for reading
+    /// and diffing pages rather than serving them.
+    pub fn write_pretty<W: Write>(&self, writer: &mut W, pretty: &Pretty) -> Result<()> {
+        writeln!(writer, "<!DOCTYPE html>")?;
+        write_nodes(writer, &self.nodes, pretty, 0)
+    }
+}
+
+impl Element<'_> {
+    /// Write the element like [`Document::write_pretty`].
+    pub fn write_pretty<W: Write>(&self, writer: &mut W, pretty: &Pretty) -> Result<()> {
+        write_element(writer, self, pretty, 0)
+    }
+}
+
+fn write_nodes<W: Write>(
+    writer: &mut W,
+    nodes: &[Node<'_>],
+    pretty: &Pretty,
+    depth: usize,
+) -> Result<()> {
+    let indent = depth * pretty.indent;
+    let mut index = 0;
+    while index < nodes.len() {
+        match &nodes[index] {
+            Node::Element(element) => write_element(writer, element, pretty, depth)?,
+            Node::Text(_) => {
+                // Text split up by character references or interpolation goes on one line.
+                let mut text = String::new();
+                while let Some(Node::Text(t)) = nodes.get(index) {
+                    text.push_str(t);
+                    index += 1;
+                }
+
+                let text = text.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+                if !text.is_empty() {
+                    writeln!(writer, "{:indent$}{}", "", escape::text(&text))?;
+                }
+                continue;
+            }
+            Node::Raw(markup) => writeln!(writer, "{:indent$}{}", "", markup.trim())?,
+            Node::Comment(comment) if pretty.comments => {
+                writeln!(writer, "{:indent$}<!--{comment}-->", "")?
+            }
+            Node::Comment(_) => {}
+        }
+        index += 1;
+    }
+
+    Ok(())
+}
+
+fn write_element<W: Write>(
+    writer: &mut W,
+    element: &Element<'_>,
+    pretty: &Pretty,
+    depth: usize,
+) -> Result<()> {
+    let indent = depth * pretty.indent;
+
+    let mut line = Vec::new();
+    write_inline(&mut line, element, pretty)?;
+    let fits = indent + line.len() <= pretty.width && !line.contains(&b'\n');
+    if fits || element::is_raw_text(&element.name) {
+        write!(writer, "{:indent$}", "")?;
+        writer.write_all(&line)?;
+        return writeln!(writer);
+    }
+
+    write!(writer, "{:indent$}", "")?;
+    element.write_start(writer)?;
+    writeln!(writer, ">")?;
+    write_nodes(writer, &element.children, pretty, depth + 1)?;
+    writeln!(writer, "{:indent$}</{}>", "", element.name)
+}
+
+/// Write `element` on a single line, collapsing the whitespace in its text.
+fn write_inline<W: Write>(writer: &mut W, element: &Element<'_>, pretty: &Pretty) -> Result<()> {
+    let empty = element.children.is_empty() || element::is_void(&element.name);
+    if empty || element::is_raw_text(&element.name) {
+        return element.write(writer);
+    }
+
+    element.write_start(writer)?;
+    write!(writer, ">")?;
+
+    let last = element.children.len() - 1;
+    for (i, child) in element.children.iter().enumerate() {
+        match child {
+            Node::Element(child) => write_inline(writer, child, pretty)?,
+            Node::Text(text) => {
+                let mut collapsed = String::with_capacity(text.len());
+                for (j, word) in text.split(|c: char| c.is_ascii_whitespace()).enumerate() {
+                    if j > 0 && !collapsed.ends_with(' ') {
+                        collapsed.push(' ');
+                    }
+                    collapsed.push_str(word);
+                }
+
+                let mut collapsed = collapsed.as_str();
+                if i == 0 {
+                    collapsed = collapsed.trim_ascii_start();
+                }
+                if i == last {
+                    collapsed = collapsed.trim_ascii_end();
+                }
+                writer.write_all(escape::text(collapsed).as_bytes())?;
+            }
+            Node::Raw(markup) => writer.write_all(markup.as_bytes())?,
+            Node::Comment(comment) if pretty.comments => write!(writer, "<!--{comment}-->")?,
+            Node::Comment(_) => {}
+        }
+    }
+
+    write!(writer, "</{}>", element.name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn indents_and_preserves() {
+        let document = Document::new(
+            r#"<html><body>
+  <nav><a href="/">Home</a>   <a href="/blog/">Blog</a></nav>
+  <main><p>A paragraph long enough that it has to be broken over several lines, with <b>bold</b> text.</p>
+  <pre>  keep
+    this</pre></main>
+</body></html>"#,
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        document
+            .write_pretty(
+                &mut output,
+                &Pretty {
+                    width: 80,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<!DOCTYPE html>
+<html>
+  <body>
+    <nav><a href="/">Home</a> <a href="/blog/">Blog</a></nav>
+    <main>
+      <p>
+        A paragraph long enough that it has to be broken over several lines, with
+        <b>bold</b>
+        text.
+      </p>
+      <pre>  keep
+    this</pre>
+    </main>
+  </body>
+</html>
+"#
+        );
+    }
+}
@@ -0,0 +1,183 @@
+/// The class scoping a component's styles, derived from its CSS so
+/// identical styles share a class.
+pub fn class(css: &str) -> String {
+    // FNV-1a, which is stable across builds unlike the std hasher.
+    let hash = css.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    format!("c-{hash:08x}")
+}
+
+/// Restrict every rule in `css` to elements carrying `class`.
+///
+/// The class joins the last compound selector of each selector,
+/// before any pseudo-classes, so `.card a:hover` becomes
+/// `.card a.c-1234abcd:hover`. Rules nested in `@media` and `@supports`
+/// are scoped too, while other at-rules are kept as they are.
+pub fn rewrite(css: &str, class: &str) -> String {
+    let mut scoped = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some((open, _)) = code(rest).find(|&(_, c)| c == '{') {
+        let prelude = &rest[..open];
+        let close = matching_brace(rest, open);
+        let body = &rest[open + 1..close.saturating_sub(1).max(open + 1)];
+        let trimmed = prelude.trim();
+
+        if trimmed.starts_with("@media") || trimmed.starts_with("@supports") {
+            scoped.push_str(prelude);
+            scoped.push('{');
+            scoped.push_str(&rewrite(body, class));
+            scoped.push('}');
+        } else if trimmed.starts_with('@') {
+            scoped.push_str(&rest[..close]);
+        } else {
+            // Keep the whitespace that came before the selectors.
+            let leading = &prelude[..prelude.len() - prelude.trim_start().len()];
+            scoped.push_str(leading);
+            let selectors: Vec<_> = split_selectors(trimmed)
+                .into_iter()
+                .map(|selector| scope_selector(selector.trim(), class))
+                .collect();
+            scoped.push_str(&selectors.join(", "));
+            scoped.push(' ');
+            scoped.push_str(&rest[open..close]);
+        }
+
+        rest = &rest[close..];
+    }
+
+    scoped.push_str(rest);
+    scoped
+}
+
+/// The index just past the brace closing the one at `open`, or the
+/// end of `css` if it's never closed.
+fn matching_brace(css: &str, open: usize) -> usize {
+    let mut depth = 0;
+    for (i, c) in code(&css[open..]) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return open + i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// The characters of `css` outside comments and strings, with their
+/// indices, so braces and the like inside them aren't mistaken for syntax.
+fn code(css: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut chars = css.char_indices().peekable();
+    let mut quote = None;
+    let mut comment = false;
+    std::iter::from_fn(move || loop {
+        let (i, c) = chars.next()?;
+        if comment {
+            comment = !(c == '*' && chars.next_if(|&(_, c)| c == '/').is_some());
+        } else if let Some(open) = quote {
+            if c == '\\' {
+                chars.next();
+            } else if c == open {
+                quote = None;
+            }
+        } else if matches!(c, '"' | '\'') {
+            quote = Some(c);
+        } else if c == '/' && chars.next_if(|&(_, c)| c == '*').is_some() {
+            comment = true;
+        } else {
+            return Some((i, c));
+        }
+    })
+}
+
+/// The characters of `selector` outside strings, attribute selectors,
+/// and the arguments of pseudo-classes like `:not(a b)`.
+fn top_level(selector: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut depth = 0usize;
+    code(selector).filter(move |&(_, c)| match c {
+        '[' | '(' => {
+            depth += 1;
+            false
+        }
+        ']' | ')' => {
+            depth = depth.saturating_sub(1);
+            false
+        }
+        _ => depth == 0,
+    })
+}
+
+/// The comma-separated selectors of a rule's prelude.
+fn split_selectors(prelude: &str) -> Vec<&str> {
+    let mut selectors = Vec::new();
+    let mut start = 0;
+    for (i, _) in top_level(prelude).filter(|&(_, c)| c == ',') {
+        selectors.push(&prelude[start..i]);
+        start = i + 1;
+    }
+    selectors.push(&prelude[start..]);
+    selectors
+}
+
+fn scope_selector(selector: &str, class: &str) -> String {
+    // The first pseudo-class of the last compound selector.
+    let mut pseudo = None;
+    for (i, c) in top_level(selector) {
+        if c.is_whitespace() || matches!(c, '>' | '+' | '~') {
+            pseudo = None;
+        } else if c == ':' && pseudo.is_none() {
+            pseudo = Some(i);
+        }
+    }
+    let pseudo = pseudo.unwrap_or(selector.len());
+    format!("{}.{class}{}", &selector[..pseudo], &selector[pseudo..])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scopes_selectors() {
+        assert_eq!(
+            rewrite(
+                ".card, h2 > a:hover { color: red; }\n@media (width < 40em) { p { margin: 0 } }\n@keyframes spin { to { rotate: 1turn } }",
+                "c-1"
+            ),
+            ".card.c-1, h2 > a.c-1:hover { color: red; }\n@media (width < 40em) { p.c-1 { margin: 0 } }\n@keyframes spin { to { rotate: 1turn } }"
+        );
+    }
+
+    #[test]
+    fn skips_attribute_values_and_arguments() {
+        assert_eq!(
+            scope_selector(r#"a[href^="http:"]"#, "c-1"),
+            r#"a[href^="http:"].c-1"#
+        );
+        assert_eq!(
+            scope_selector(r#"li a[title="a > b"]:hover"#, "c-1"),
+            r#"li a[title="a > b"].c-1:hover"#
+        );
+        assert_eq!(
+            rewrite(":is(h1, h2) a, p:not(.a .b) { margin: 0 }", "c-1"),
+            ":is(h1, h2) a.c-1, p.c-1:not(.a .b) { margin: 0 }"
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_strings_in_rules() {
+        assert_eq!(
+            rewrite(
+                "/* { not a rule } */ p { content: \"}\"; } q { quotes: '{' '}' }",
+                "c-1"
+            ),
+            "/* { not a rule } */ p.c-1 { content: \"}\"; } q.c-1 { quotes: '{' '}' }"
+        );
+    }
+}
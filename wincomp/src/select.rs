@@ -0,0 +1,295 @@
+use crate::element::{Element, Node};
+use crate::Document;
+use std::slice;
+
+/// Every element under some nodes, depth-first in document order.
+pub struct Descendants<'a, 's> {
+    stack: Vec<slice::Iter<'a, Node<'s>>>,
+}
+
+impl<'a, 's> Descendants<'a, 's> {
+    fn new(nodes: &'a [Node<'s>]) -> Self {
+        Self {
+            stack: vec![nodes.iter()],
+        }
+    }
+}
+
+impl<'a, 's> Iterator for Descendants<'a, 's> {
+    type Item = &'a Element<'s>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(Node::Element(element)) => {
+                    self.stack.push(element.children.iter());
+                    return Some(element);
+                }
+                Some(_) => {}
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// A simple selector, like `a`, `.card`, `#main`, `img[alt]`,
+/// `a[href^="/"]`, or several of them separated by commas.
+///
+/// Attribute selectors compare values with `=`, `~=`, `|=`, `^=`, `$=`,
+/// and `*=`, as in CSS. Combinators and pseudo-classes aren't supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector<'a> {
+    alternatives: Vec<Compound<'a>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Compound<'a> {
+    name: Option<&'a str>,
+    id: Option<&'a str>,
+    classes: Vec<&'a str>,
+    attributes: Vec<AttributeSelector<'a>>,
+}
+
+/// An attribute selector, like `[alt]` or `[rel~="noopener"]`.
+#[derive(Debug, Clone, PartialEq)]
+struct AttributeSelector<'a> {
+    name: &'a str,
+    /// The character before the `=`, like `^` for `^=`, or `=` itself
+    /// for a plain `=`, and the value to compare with.
+    value: Option<(char, &'a str)>,
+}
+
+impl<'a> Selector<'a> {
+    pub fn parse(selector: &'a str) -> Self {
+        let alternatives = alternatives(selector)
+            .into_iter()
+            .map(str::trim)
+            .filter(|compound| !compound.is_empty())
+            .map(Compound::parse)
+            .collect();
+        Self { alternatives }
+    }
+
+    pub fn matches(&self, element: &Element) -> bool {
+        self.alternatives
+            .iter()
+            .any(|compound| compound.matches(element))
+    }
+}
+
+impl<'a> Compound<'a> {
+    fn parse(mut compound: &'a str) -> Self {
+        let mut parsed = Self::default();
+
+        let end = |s: &str| s.find(['.', '#', '[']).unwrap_or(s.len());
+        let (name, rest) = compound.split_at(end(compound));
+        if !name.is_empty() && name != "*" {
+            parsed.name = Some(name);
+        }
+        compound = rest;
+
+        while let Some(marker) = compound.chars().next() {
+            let rest = &compound[1..];
+            if marker == '[' {
+                let close = closing_bracket(rest);
+                parsed
+                    .attributes
+                    .push(AttributeSelector::parse(&rest[..close]));
+                compound = rest.get(close + 1..).unwrap_or_default();
+                continue;
+            }
+
+            let (value, rest) = rest.split_at(end(rest));
+            match marker {
+                '#' => parsed.id = Some(value),
+                _ => parsed.classes.push(value),
+            }
+            compound = rest;
+        }
+
+        parsed
+    }
+
+    fn matches(&self, element: &Element) -> bool {
+        if self.name.is_some_and(|name| name != element.name) {
+            return false;
+        }
+        if self
+            .id
+            .is_some_and(|id| element.attribute("id") != Some(id))
+        {
+            return false;
+        }
+
+        let classes = element.attribute("class").unwrap_or_default();
+        self.classes
+            .iter()
+            .all(|class| classes.split_ascii_whitespace().any(|c| c == *class))
+            && self
+                .attributes
+                .iter()
+                .all(|selector| selector.matches(element))
+    }
+}
+
+impl<'a> AttributeSelector<'a> {
+    fn parse(selector: &'a str) -> Self {
+        let Some((name, value)) = selector.split_once('=') else {
+            return Self {
+                name: selector.trim(),
+                value: None,
+            };
+        };
+
+        let (name, operator) = match name.trim_end().strip_suffix(['~', '|', '^', '$', '*']) {
+            Some(stripped) => (stripped, name.trim_end().chars().last().unwrap()),
+            None => (name, '='),
+        };
+        let value = value.trim();
+        let value = ['"', '\'']
+            .into_iter()
+            .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+            .unwrap_or(value);
+
+        Self {
+            name: name.trim(),
+            value: Some((operator, value)),
+        }
+    }
+
+    fn matches(&self, element: &Element) -> bool {
+        let Some(attribute) = element.attributes.iter().find(|a| a.name == self.name) else {
+            return false;
+        };
+        let Some((operator, expected)) = self.value else {
+            return true;
+        };
+        let value = attribute.value.as_deref().unwrap_or_default();
+
+        match operator {
+            '~' => value.split_ascii_whitespace().any(|word| word == expected),
+            '|' => {
+                value == expected
+                    || value
+                        .strip_prefix(expected)
+                        .is_some_and(|rest| rest.starts_with('-'))
+            }
+            '^' => !expected.is_empty() && value.starts_with(expected),
+            '$' => !expected.is_empty() && value.ends_with(expected),
+            '*' => !expected.is_empty() && value.contains(expected),
+            _ => value == expected,
+        }
+    }
+}
+
+/// The index of the `]` closing an attribute selector that `selector`
+/// starts inside, skipping any in quoted values.
+fn closing_bracket(selector: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in selector.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, ']') => return i,
+            _ => {}
+        }
+    }
+    selector.len()
+}
+
+/// Split `selector` at the commas between its alternatives, skipping
+/// any in attribute selectors, like `[title="a, b"]`.
+fn alternatives(selector: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut bracket = false;
+    let mut quote = None;
+    for (i, c) in selector.char_indices() {
+        match (quote, c) {
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') if bracket => quote = Some(c),
+            (None, '[') => bracket = true,
+            (None, ']') => bracket = false,
+            (None, ',') if !bracket => {
+                parts.push(&selector[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&selector[start..]);
+    parts
+}
+
+impl<'s> Element<'s> {
+    /// The value of the attribute called `name`, if it's given one.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.name == name)
+            .and_then(|a| a.value.as_deref())
+    }
+
+    /// The elements under this one, depth-first in document order.
+    pub fn descendants(&self) -> Descendants<'_, 's> {
+        Descendants::new(&self.children)
+    }
+
+    /// The elements under this one matching `selector`, like `a[href]`
+    /// or `.card, #main`. See [`Selector`].
+    pub fn select<'a>(&'a self, selector: &'a str) -> impl Iterator<Item = &'a Element<'s>> {
+        let selector = Selector::parse(selector);
+        self.descendants().filter(move |e| selector.matches(e))
+    }
+}
+
+impl<'s> Document<'s> {
+    /// Every element in the document, depth-first in document order.
+    pub fn elements(&self) -> Descendants<'_, 's> {
+        Descendants::new(&self.nodes)
+    }
+
+    /// The elements in the document matching `selector`, like `a[href]`
+    /// or `.card, #main`. See [`Selector`].
+    pub fn select<'a>(&'a self, selector: &'a str) -> impl Iterator<Item = &'a Element<'s>> {
+        let selector = Selector::parse(selector);
+        self.elements().filter(move |e| selector.matches(e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn selects_elements() {
+        let document = Document::new(
+            r#"<main id="main"><div class="card big"><a href="/">Home</a><img alt="" src="a.png"></div><p class="card">Text <a>Nowhere</a></p></main>"#,
+        )
+        .unwrap();
+        let names = |selector| {
+            document
+                .select(selector)
+                .map(|e| e.name.as_ref())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("a"), ["a", "a"]);
+        assert_eq!(names(".card"), ["div", "p"]);
+        assert_eq!(names("div.card.big, #main"), ["main", "div"]);
+        assert_eq!(names("a[href], img[alt]"), ["a", "img"]);
+        assert_eq!(names("p.big"), Vec::<&str>::new());
+        assert_eq!(names(r#"a[href="/"]"#), ["a"]);
+        assert_eq!(names("[href=/blog/]"), Vec::<&str>::new());
+        assert_eq!(names(r#"[class~="big"], [id^='ma']"#), ["main", "div"]);
+        assert_eq!(names(r#"[src$=".png"], a[title="a, b]"]"#), ["img"]);
+
+        let main = document.elements().next().unwrap();
+        assert_eq!(main.attribute("id"), Some("main"));
+        assert_eq!(main.select("[src]").count(), 1);
+        assert_eq!(main.descendants().count(), 5);
+    }
+}
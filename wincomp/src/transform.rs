@@ -0,0 +1,79 @@
+use crate::element::{self, Element, Node};
+use crate::select::Selector;
+use crate::Document;
+
+type Pass = Box<dyn Fn(&mut Element<'_>) + Send + Sync>;
+
+/// Changes made to every element matching a selector, like adding
+/// `rel="noopener"` to `a[target]`, run in the order they were added.
+///
+/// Passes can't borrow from the documents they change, so any new
+/// attribute values have to be owned or `'static`.
+#[derive(Default)]
+pub struct Transforms {
+    passes: Vec<(String, Pass)>,
+}
+
+impl Transforms {
+    pub fn add<F>(&mut self, selector: impl Into<String>, pass: F)
+    where
+        F: Fn(&mut Element<'_>) + Send + Sync + 'static,
+    {
+        self.passes.push((selector.into(), Box::new(pass)));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    pub fn apply(&self, document: &mut Document<'_>) {
+        for (selector, pass) in &self.passes {
+            let selector = Selector::parse(selector);
+            for element in document.nodes.iter_mut().filter_map(Node::element_mut) {
+                element::walk(element, &mut |element| {
+                    if selector.matches(element) {
+                        pass(element);
+                    }
+                });
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Transforms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.passes.iter().map(|(selector, _)| selector))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn applies_passes_in_order() {
+        let mut transforms = Transforms::default();
+        transforms.add("img", |img| {
+            if img.attribute("loading").is_none() {
+                img.set_attribute("loading", Some("lazy".into()));
+            }
+        });
+        transforms.add("a[target], img[loading]", |e| {
+            e.set_attribute("data-seen", None)
+        });
+
+        let mut document =
+            Document::new(r#"<p><img src="a.png"><img src="b.png" loading="eager"><a target="_blank">A</a><a>B</a></p>"#)
+                .unwrap();
+        transforms.apply(&mut document);
+
+        let mut output = Vec::new();
+        document.write_fragment(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            r#"<p><img src="a.png" loading="lazy" data-seen><img src="b.png" loading="eager" data-seen><a target="_blank" data-seen>A</a><a>B</a></p>"#
+        );
+    }
+}